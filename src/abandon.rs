@@ -0,0 +1,149 @@
+use std::collections::BTreeSet;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::{BranchType, Remote, Repository};
+use octocrab::Octocrab;
+
+use crate::config::Config;
+use crate::gh::{self, GHRepo};
+use crate::metadata::{Metadata, NoteBatch, NOTE_REF};
+use crate::prompt;
+use crate::protected::{self, Protected};
+use crate::pulls::{self, Pulls};
+use crate::remotes;
+use crate::stack::Stack;
+use crate::state::State;
+use crate::status;
+
+/// Give up on `stack`: close its open PRs with a comment, delete the branches fel pushed for it
+/// from `remote`, and remove its notes and state. With `delete_branch` the local branch is
+/// deleted too, after switching to upstream. Asks first unless `yes`
+#[allow(clippy::too_many_arguments)]
+pub async fn abandon(
+    repo: &Repository,
+    stack: &Stack,
+    remote: &mut Remote<'_>,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    config: &Config,
+    delete_branch: bool,
+    yes: bool,
+) -> Result<()> {
+    let name = stack.name();
+    anyhow::ensure!(
+        !delete_branch || !stack.is_detached(),
+        "HEAD is detached, there is no branch to delete"
+    );
+
+    // Every commit submitted in the stack, including earlier revisions and dropped commits
+    let mut notes: BTreeSet<_> = stack.iter().map(|commit| commit.id()).collect();
+    for note in repo.notes(Some(NOTE_REF)).into_iter().flatten() {
+        let (_, id) = note.context("failed to read note")?;
+        let Ok(metadata) = Metadata::find(repo, id) else {
+            continue;
+        };
+        if metadata.stack.as_deref() == Some(name) {
+            notes.insert(id);
+        }
+    }
+
+    let mut state = State::load(repo)?;
+    let remembered = state.stack(name).cloned().unwrap_or_default();
+    let mut prs: BTreeSet<_> = remembered.prs.into_iter().collect();
+    let mut branches: BTreeSet<_> = remembered.branches.into_iter().collect();
+    for &id in &notes {
+        let metadata = Metadata::find(repo, id)?;
+        prs.extend(metadata.pr);
+        branches.extend(metadata.branch);
+    }
+
+    let prs: Vec<_> = prs.into_iter().collect();
+    let open: Vec<_> = status::fetch(octocrab, gh_repo, &prs, config.api.timeout())
+        .await?
+        .into_iter()
+        .filter(|status| status.state == "OPEN")
+        .map(|status| status.number)
+        .collect();
+    let branches: Vec<_> = branches.into_iter().collect();
+
+    // A bad note must not be able to delete a real branch, any more than submit can push over one
+    let timeout = config.api.timeout();
+    let rules = protected::rules(octocrab, gh_repo);
+    let rules = gh::timeout(timeout, "getting branch protection rules", rules).await?;
+    let protected = Protected::new(
+        [stack.upstream(), &config.default_upstream]
+            .into_iter()
+            .map(str::to_string)
+            .chain(config.submit.protected_branches.iter().cloned())
+            .chain(rules),
+    )?;
+    for branch in &branches {
+        protected
+            .check(branch)
+            .with_context(|| format!("not abandoning {name}, it would delete {branch}"))?;
+    }
+
+    let question = format!(
+        "abandon {name}, closing {} open PRs and deleting {} remote branches{}?",
+        open.len(),
+        branches.len(),
+        match delete_branch {
+            true => " and the local branch",
+            false => "",
+        }
+    );
+    if !yes && !prompt::confirm(&question)? {
+        anyhow::bail!("abandon aborted");
+    }
+
+    let pulls = Pulls::new(octocrab, gh_repo, config.api);
+    let issues = octocrab.issues(&gh_repo.owner, &gh_repo.repo);
+    for &pr in &open {
+        let comment = issues.create_comment(pr, format!("{name} was abandoned, closing"));
+        gh::timeout(timeout, &format!("commenting on #{pr}"), comment)
+            .await?
+            .with_context(|| format!("failed to comment on #{pr}"))?;
+        let close = pulls::Update {
+            close: true,
+            ..Default::default()
+        };
+        gh::timeout(timeout, &format!("closing #{pr}"), pulls.update(pr, close))
+            .await?
+            .with_context(|| format!("failed to close #{pr}"))?;
+        println!("closed #{pr}");
+    }
+
+    remotes::delete_branches(remote, &branches, config.proxy.as_deref())?;
+    println!("deleted {} remote branches", branches.len());
+
+    // Commits in the stack that were never submitted have no note to remove
+    let mut batch = NoteBatch::default();
+    for &id in &notes {
+        batch.remove(id);
+    }
+    batch.write(repo).context("failed to remove notes")?;
+    state.remove_stack(name);
+    state.save().context("failed to save state")?;
+
+    if delete_branch {
+        // The branch can't be deleted while it's checked out
+        let workdir = repo.workdir().context("repo has no working directory")?;
+        let status = Command::new("git")
+            .args(["switch", &config.default_upstream])
+            .current_dir(workdir)
+            .status()
+            .context("failed to run git switch")?;
+        anyhow::ensure!(
+            status.success(),
+            "failed to switch to {}, the local branch {name} was kept",
+            config.default_upstream
+        );
+        repo.find_branch(name, BranchType::Local)
+            .and_then(|mut branch| branch.delete())
+            .with_context(|| format!("failed to delete branch {name}"))?;
+        println!("deleted branch {name}");
+    }
+
+    Ok(())
+}
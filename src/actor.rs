@@ -0,0 +1,100 @@
+use std::path::Path;
+use std::thread;
+
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+use tokio::sync::{mpsc, oneshot};
+
+use crate::commit;
+use crate::metadata::{Metadata, NoteBatch};
+
+enum Request {
+    PatchId {
+        id: Oid,
+        reply: oneshot::Sender<Result<Oid>>,
+    },
+    AddNote {
+        metadata: Box<Metadata>,
+        id: Oid,
+        reply: oneshot::Sender<Result<()>>,
+    },
+    WriteNotes {
+        reply: oneshot::Sender<Result<()>>,
+    },
+}
+
+/// A handle to a thread that owns its own `Repository`, so tasks running on any thread can
+/// use the repo without waiting for each other to finish first. The thread exits once every
+/// handle is dropped
+#[derive(Clone)]
+pub struct GitActor {
+    tx: mpsc::UnboundedSender<Request>,
+}
+
+impl GitActor {
+    /// Open the repo at `path` on a new thread
+    pub fn spawn(path: &Path) -> Result<Self> {
+        let repo = Repository::open(path).context("failed to open repo for git thread")?;
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        thread::Builder::new()
+            .name("git".to_string())
+            .spawn(move || {
+                let mut notes = NoteBatch::default();
+                while let Some(request) = rx.blocking_recv() {
+                    match request {
+                        Request::PatchId { id, reply } => {
+                            reply.send(commit::patch_id(&repo, id)).ok();
+                        }
+                        Request::AddNote {
+                            metadata,
+                            id,
+                            reply,
+                        } => {
+                            reply.send(notes.add(&metadata, id)).ok();
+                        }
+                        Request::WriteNotes { reply } => {
+                            let batch = std::mem::take(&mut notes);
+                            reply.send(batch.write(&repo)).ok();
+                        }
+                    }
+                }
+                tracing::debug!("git thread exiting");
+            })
+            .context("failed to start git thread")?;
+
+        Ok(Self { tx })
+    }
+
+    async fn request<T>(
+        &self,
+        request: impl FnOnce(oneshot::Sender<Result<T>>) -> Request,
+    ) -> Result<T> {
+        let (reply, rx) = oneshot::channel();
+        self.tx
+            .send(request(reply))
+            .ok()
+            .context("git thread exited")?;
+        rx.await.context("git thread dropped request")?
+    }
+
+    pub async fn patch_id(&self, id: Oid) -> Result<Oid> {
+        self.request(|reply| Request::PatchId { id, reply }).await
+    }
+
+    /// Queue `metadata` to be written as the note of `id` by the next `write_notes`
+    pub async fn add_note(&self, metadata: Metadata, id: Oid) -> Result<()> {
+        let metadata = Box::new(metadata);
+        self.request(|reply| Request::AddNote {
+            metadata,
+            id,
+            reply,
+        })
+        .await
+    }
+
+    /// Write every queued note in a single notes commit
+    pub async fn write_notes(&self) -> Result<()> {
+        self.request(|reply| Request::WriteNotes { reply }).await
+    }
+}
@@ -1,6 +1,38 @@
-use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
 
-use git2::{Cred, RemoteCallbacks};
+use git2::{Cred, CredentialType, ProxyOptions, RemoteCallbacks};
+
+use crate::config;
+
+/// Keys tried for ssh remotes, in the order ssh itself tries them
+const SSH_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+static SSH_KEY: OnceLock<PathBuf> = OnceLock::new();
+
+/// Use `key` for ssh remotes instead of looking for one, if it's set. A leading `~` is the
+/// home dir
+pub fn init_ssh_key(key: Option<PathBuf>) {
+    let key = key.map(|key| match (key.strip_prefix("~"), config::home_dir()) {
+        (Ok(rest), Ok(home)) => home.join(rest),
+        _ => key,
+    });
+    if let Some(key) = key {
+        SSH_KEY.set(key).ok();
+    }
+}
+
+/// Returns the configured private key, or the first one in `~/.ssh`, if there is one
+pub fn ssh_key() -> Option<PathBuf> {
+    if let Some(key) = SSH_KEY.get() {
+        return Some(key.clone());
+    }
+    let ssh = config::home_dir().ok()?.join(".ssh");
+    SSH_KEYS
+        .iter()
+        .map(|key| ssh.join(key))
+        .find(|key| key.exists())
+}
 
 pub fn callbacks() -> RemoteCallbacks<'static> {
     let mut callbacks = RemoteCallbacks::default();
@@ -11,13 +43,37 @@ pub fn callbacks() -> RemoteCallbacks<'static> {
             ?allowed_types,
             "providing auth credentials"
         );
-        Cred::ssh_key(
-            username_from_url.unwrap(),
-            None,
-            std::path::Path::new(&format!("{}/.ssh/id_rsa", env::var("HOME").unwrap())),
-            None,
-        )
+        // HTTPS remotes authenticate through git's credential helpers. When there's no `sh`,
+        // like on Windows without git bash, libgit2 runs the helper directly, so Git
+        // Credential Manager works too
+        if allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username_from_url);
+        }
+
+        let username = username_from_url.unwrap_or("git");
+        if allowed_types.contains(CredentialType::USERNAME) {
+            return Cred::username(username);
+        }
+
+        // Without a key on disk, fall back to ssh-agent, or Pageant and the OpenSSH agent
+        // service on Windows
+        match ssh_key() {
+            Some(key) => Cred::ssh_key(username, None, &key, None),
+            None => Cred::ssh_key_from_agent(username),
+        }
     });
 
     callbacks
 }
+
+/// Proxy settings for connecting to remotes. Without a `proxy` url, libgit2 uses git's
+/// `http.proxy` config or the proxy environment variables
+pub fn proxy_options(proxy: Option<&str>) -> ProxyOptions<'_> {
+    let mut options = ProxyOptions::new();
+    match proxy {
+        Some(url) => options.url(url),
+        None => options.auto(),
+    };
+    options
+}
@@ -0,0 +1,193 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use git2::{BranchType, Oid, Repository};
+use octocrab::Octocrab;
+
+use crate::commit::{self, Commit};
+use crate::config::Config;
+use crate::gh::GHRepo;
+use crate::metadata::{Metadata, NoteBatch};
+use crate::stack::Stack;
+use crate::submit;
+
+/// Cherry-pick every commit in `stack` onto `branch`, which has just been fetched, and submit
+/// the copies like `fel submit` as a stack of their own against `branch`. The copies are kept
+/// on a local branch named after the stack and `branch`, so backporting again updates the same
+/// PRs, and leave out `Fel-Base` trailers, which name branches of the original stack. Each
+/// copy's note records the PR it was picked from, and each original's note the PRs it was
+/// backported in, then the stack is submitted again so every footer links the other PRs.
+/// Returns the name of the backport stack
+pub async fn backport(
+    repo: &Repository,
+    stack: &mut Stack,
+    octocrab: Arc<Octocrab>,
+    gh_repo: &GHRepo,
+    config: &Config,
+    branch: &str,
+) -> Result<String> {
+    if let Some(policy) = &config.policy {
+        policy.check_upstream(branch)?;
+    }
+    for commit in stack.iter() {
+        anyhow::ensure!(
+            commit.metadata.pr.is_some() && !commit.changed(),
+            "{} has not been submitted as it is, submit the stack before backporting it",
+            commit.title
+        );
+    }
+
+    let upstream = format!("{}/{branch}", config.default_remote);
+    let onto = repo
+        .find_branch(&upstream, BranchType::Remote)
+        .and_then(|branch| branch.get().peel_to_commit())
+        .with_context(|| format!("failed to find {upstream}"))?;
+    let name = format!("{}-{}", stack.name(), branch.replace('/', "-"));
+
+    // Copies from an earlier backport, by the commit they were picked from, so their PRs are
+    // updated rather than opened again
+    let mut previous = HashMap::new();
+    if let Ok(existing) = repo.find_branch(&name, BranchType::Local) {
+        let mut walk = repo.revwalk().context("failed to walk backport")?;
+        walk.push(existing.get().peel_to_commit()?.id())?;
+        walk.hide(onto.id())?;
+        for id in walk {
+            let id = id.context("failed to walk backport")?;
+            let metadata = Metadata::find(repo, id)?;
+            if let Some(source) = metadata.picked_from.clone() {
+                previous.insert(source, (id, metadata));
+            }
+        }
+    }
+
+    let signature = repo.signature().context("failed to get signature")?;
+    let mut notes = NoteBatch::default();
+    let mut parent = onto;
+    for commit in stack.iter() {
+        let original = repo.find_commit(commit.id())?;
+        let mut index = repo
+            .cherrypick_commit(&original, &parent, 0, None)
+            .with_context(|| format!("failed to cherry-pick {}", commit.title))?;
+        if index.has_conflicts() {
+            let paths: Vec<_> = index
+                .conflicts()?
+                .filter_map(|conflict| conflict.ok()?.our)
+                .map(|entry| String::from_utf8_lossy(&entry.path).into_owned())
+                .collect();
+            anyhow::bail!(
+                "{} conflicts with {branch} in {}, backport it by hand",
+                commit.title,
+                paths.join(", ")
+            );
+        }
+        let tree = index
+            .write_tree_to(repo)
+            .context("failed to write backport tree")?;
+
+        let source = commit.id().to_string();
+        let message = commit::decode(original.id(), original.message_bytes(), config.lossy_utf8)?;
+        let message = without_base(&message);
+        let (id, metadata) = match previous.remove(&source) {
+            // Unchanged since the last backport, so keep the commit its PR already has
+            Some((id, metadata)) if unchanged(repo, id, tree, parent.id(), &message)? => {
+                (id, metadata)
+            }
+            earlier => {
+                let id = repo
+                    .commit(
+                        None,
+                        &original.author(),
+                        &signature,
+                        &message,
+                        &repo.find_tree(tree)?,
+                        &[&parent],
+                    )
+                    .with_context(|| format!("failed to commit backport of {}", commit.title))?;
+                let metadata = earlier.map(|(_, metadata)| metadata).unwrap_or_default();
+                (id, metadata)
+            }
+        };
+        let metadata = Metadata {
+            picked_from: Some(source),
+            picked_from_pr: commit.metadata.pr,
+            ..metadata
+        };
+        notes.add(&metadata, id)?;
+        parent = repo.find_commit(id)?;
+    }
+    notes.write(repo).context("failed to write notes")?;
+    repo.branch(&name, &parent, true)
+        .with_context(|| format!("failed to update branch {name}"))?;
+
+    let mut backport = load(repo, parent.id(), stack.len(), &name, branch, config)?;
+    let remote = || repo.find_remote(&config.default_remote);
+    submit::run(
+        &mut backport,
+        repo,
+        remote()?,
+        octocrab.clone(),
+        gh_repo,
+        config,
+        false,
+    )
+    .await
+    .with_context(|| format!("failed to submit {name}"))?;
+
+    // Link each original to its backport, then submit the originals again for their footers
+    let mut notes = NoteBatch::default();
+    for (commit, copy) in stack.iter().zip(backport.iter()) {
+        let Some(pr) = Metadata::find(repo, copy.id())?.pr else {
+            continue;
+        };
+        if !commit.metadata.backports.contains(&pr) {
+            let mut metadata = commit.metadata.clone();
+            metadata.backports.push(pr);
+            notes.add(&metadata, commit.id())?;
+        }
+    }
+    notes.write(repo).context("failed to write notes")?;
+    stack.refresh(repo)?;
+    submit::run(stack, repo, remote()?, octocrab, gh_repo, config, false)
+        .await
+        .context("failed to update the stack's footers")?;
+
+    Ok(name)
+}
+
+/// Returns true if the commit `id` has `tree` and `message`, and is on top of `parent`
+fn unchanged(repo: &Repository, id: Oid, tree: Oid, parent: Oid, message: &str) -> Result<bool> {
+    let commit = repo.find_commit(id)?;
+    Ok(commit.tree_id() == tree
+        && commit.parent_id(0).ok() == Some(parent)
+        && commit.message() == Some(message))
+}
+
+/// Returns `message` without its `Fel-Base` trailers
+fn without_base(message: &str) -> String {
+    let kept: Vec<_> = message
+        .lines()
+        .filter(|line| !line.to_ascii_lowercase().starts_with("fel-base:"))
+        .collect();
+    format!("{}\n", kept.join("\n").trim_end())
+}
+
+/// Read the `len` commits ending at `top` as a stack named `name` against `upstream`
+fn load(
+    repo: &Repository,
+    top: Oid,
+    len: usize,
+    name: &str,
+    upstream: &str,
+    config: &Config,
+) -> Result<Stack> {
+    let mut commits = Vec::with_capacity(len);
+    let mut id = top;
+    for _ in 0..len {
+        let commit = repo.find_commit(id)?;
+        id = commit.parent_id(0)?;
+        commits.push(Commit::new(commit, repo, config.lossy_utf8)?);
+    }
+    commits.reverse();
+    Ok(Stack::from_commits(commits, name, upstream))
+}
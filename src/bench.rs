@@ -0,0 +1,126 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use git2::{Oid, Repository, Signature};
+
+use crate::config::Config;
+use crate::forge::MockForge;
+use crate::gh::{self, GHRepo};
+use crate::render::TeraRenderer;
+use crate::stack::Stack;
+use crate::submit;
+
+const CONFIG: &str = r#"
+default_remote = "origin"
+default_upstream = "main"
+
+[land]
+strip_footer = false
+
+[submit]
+use_indexed_branches = false
+auto_create_branches = false
+"#;
+
+/// Build a repo with a stack of `commits` commits and a local bare remote in `dir`
+fn synthetic_repo(dir: &Path, commits: usize) -> Result<Repository> {
+    let remote = dir.join("remote.git");
+    Repository::init_bare(&remote).context("failed to create remote")?;
+
+    let repo = Repository::init(dir.join("work")).context("failed to create repo")?;
+    let mut config = repo.config().context("failed to open repo config")?;
+    config.set_str("user.name", "fel bench")?;
+    config.set_str("user.email", "bench@fel.invalid")?;
+    let remote = remote.to_str().context("temp dir not utf-8")?;
+    repo.remote("origin", remote)
+        .context("failed to add remote")?;
+
+    let sig = Signature::now("fel bench", "bench@fel.invalid")?;
+    let commit = |parent: Option<Oid>, index: usize| -> Result<Oid> {
+        let mut tree = match parent {
+            Some(parent) => repo.treebuilder(Some(&repo.find_commit(parent)?.tree()?))?,
+            None => repo.treebuilder(None)?,
+        };
+        let contents: String = (0..20).map(|line| format!("{index} {line}\n")).collect();
+        let blob = repo.blob(contents.as_bytes())?;
+        tree.insert(format!("file{index}.txt"), blob, 0o100644)?;
+        let tree = repo.find_tree(tree.write()?)?;
+        let parents = match parent {
+            Some(parent) => vec![repo.find_commit(parent)?],
+            None => vec![],
+        };
+        let parents: Vec<_> = parents.iter().collect();
+        let message = format!("Change {index}\n\nSynthetic commit {index} of the benchmark.");
+        Ok(repo.commit(None, &sig, &sig, &message, &tree, &parents)?)
+    };
+
+    let base = commit(None, 0).context("failed to create base commit")?;
+    repo.reference("refs/remotes/origin/main", base, true, "fel bench")?;
+    let mut tip = base;
+    for index in 1..=commits {
+        tip = commit(Some(tip), index).context("failed to create commit")?;
+    }
+    repo.reference("refs/heads/bench", tip, true, "fel bench")?;
+    repo.set_head("refs/heads/bench")?;
+
+    Ok(repo)
+}
+
+/// Submit a synthetic stack of `commits` commits twice, once creating every PR and once with
+/// nothing to change, against an in-memory forge that takes `latency` to answer each request.
+/// Pushes go to a bare repo on disk
+pub async fn bench(commits: usize, latency: Duration) -> Result<()> {
+    let dir = std::env::temp_dir().join(format!("fel-bench-{}", std::process::id()));
+    let result = run(&dir, commits, latency).await;
+    fs::remove_dir_all(&dir).ok();
+    result
+}
+
+async fn run(dir: &Path, commits: usize, latency: Duration) -> Result<()> {
+    let setup = Instant::now();
+    let repo = synthetic_repo(dir, commits)?;
+    let config: Config = toml::from_str(CONFIG).context("invalid bench config")?;
+    let setup = setup.elapsed();
+
+    let forge = Arc::new(MockForge::new(latency));
+    // Submit makes every request through the forge, but still needs a client. It points
+    // nowhere, so the benchmark can't reach GitHub even if something slips past the forge
+    let octocrab = Arc::new(gh::client("bench", None, "https://api.mock.invalid")?);
+    let gh_repo = GHRepo {
+        host: "mock.invalid".to_string(),
+        owner: "fel".to_string(),
+        repo: "bench".to_string(),
+    };
+
+    let mut runs = Vec::new();
+    for name in ["create", "resubmit"] {
+        let stack = Stack::new(&repo, &config).context("failed to get stack")?;
+        let mut remote = repo.find_remote("origin")?;
+        let requests = forge.requests();
+        let start = Instant::now();
+        submit::submit_with(
+            &stack,
+            &mut remote,
+            forge.clone(),
+            Arc::new(TeraRenderer::new(config.submit.footer_template.clone())),
+            octocrab.clone(),
+            &gh_repo,
+            &repo,
+            &config,
+            true,
+        )
+        .await
+        .with_context(|| format!("{name} submit failed"))?;
+        runs.push((name, start.elapsed(), forge.requests() - requests));
+    }
+
+    println!("\n{commits} commits, {latency:?} forge latency, setup {setup:.1?}");
+    for (name, time, requests) in runs {
+        println!("  {name:<8} {time:>10.1?} {requests:>5} forge requests");
+    }
+
+    Ok(())
+}
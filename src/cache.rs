@@ -0,0 +1,98 @@
+use std::fs;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use http::header::{ETAG, IF_NONE_MATCH};
+use http::{HeaderMap, HeaderValue, StatusCode};
+use octocrab::models::pulls::PullRequest;
+use octocrab::Octocrab;
+
+use crate::gh::GHRepo;
+
+/// A PR response and the ETag GitHub sent with it
+#[derive(serde::Deserialize, serde::Serialize)]
+struct Cached {
+    etag: String,
+    body: String,
+}
+
+/// Caches PRs fetched from GitHub in the repo's git dir, and only downloads them again when
+/// they changed. GitHub doesn't count requests answered with 304 Not Modified against the
+/// rate limit
+#[derive(Default)]
+pub struct PrCache {
+    dir: Option<PathBuf>,
+}
+
+impl PrCache {
+    pub fn new(repo: &Repository, gh_repo: &GHRepo) -> Self {
+        let dir = repo
+            .path()
+            .join("fel/cache/pulls")
+            .join(&gh_repo.owner)
+            .join(&gh_repo.repo);
+        Self { dir: Some(dir) }
+    }
+
+    /// Fetch PR `number`, from the cache if it didn't change since it was last fetched
+    pub async fn get(
+        &self,
+        octocrab: &Octocrab,
+        gh_repo: &GHRepo,
+        number: u64,
+    ) -> Result<PullRequest> {
+        let route = format!("/repos/{}/{}/pulls/{number}", gh_repo.owner, gh_repo.repo);
+        let Some(dir) = &self.dir else {
+            return octocrab
+                .get(route, None::<&()>)
+                .await
+                .context("failed to get PR");
+        };
+        let path = dir.join(format!("{number}.json"));
+
+        // A missing or corrupt cache entry just means a full download
+        let cached: Option<Cached> = fs::read_to_string(&path)
+            .ok()
+            .and_then(|cached| serde_json::from_str(&cached).ok());
+        let mut headers = HeaderMap::new();
+        if let Some(cached) = &cached {
+            if let Ok(etag) = HeaderValue::from_str(&cached.etag) {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+        }
+
+        let response = octocrab
+            ._get_with_headers(route, Some(headers))
+            .await
+            .context("failed to get PR")?;
+        if let (StatusCode::NOT_MODIFIED, Some(cached)) = (response.status(), &cached) {
+            tracing::debug!(number, "PR not modified, using cache");
+            return serde_json::from_str(&cached.body).context("invalid cached PR");
+        }
+
+        let response = octocrab::map_github_error(response)
+            .await
+            .context("failed to get PR")?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|etag| etag.to_str().ok())
+            .map(str::to_string);
+        let body = octocrab
+            .body_to_string(response)
+            .await
+            .context("failed to read PR")?;
+        let pr = serde_json::from_str(&body).context("invalid PR")?;
+
+        if let Some(etag) = etag {
+            let cached =
+                serde_json::to_string(&Cached { etag, body }).context("failed to serialize PR")?;
+            if let Err(error) = fs::create_dir_all(dir).and_then(|_| fs::write(&path, cached)) {
+                tracing::warn!(?error, number, "failed to cache PR");
+            }
+        }
+
+        Ok(pr)
+    }
+}
@@ -0,0 +1,258 @@
+use anyhow::{Context, Result};
+use git2::Oid;
+use octocrab::models::pulls::PullRequest;
+use octocrab::models::IssueState;
+use octocrab::Octocrab;
+use serde_json::json;
+
+use crate::color;
+use crate::config::Config;
+use crate::forge::Forge;
+use crate::gh::{self, GHRepo};
+use crate::stack::Stack;
+
+/// Name of the check run `fel check --publish` posts on each PR
+const NAME: &str = "fel stack";
+
+/// Check that every PR in the stack is stacked correctly: made against the branch of the PR
+/// below it, on top of a PR that is still open, and with the commit in the local stack as its
+/// head. With `publish`, the result for each PR is also posted as a check run on its head.
+/// Returns true if every PR is stacked correctly
+pub async fn check(
+    stack: &Stack,
+    forge: &dyn Forge,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    config: &Config,
+    publish: bool,
+) -> Result<bool> {
+    let mut passed = true;
+    let mut below: Option<PullRequest> = None;
+    for (index, commit) in stack.iter().enumerate() {
+        let Some(number) = commit.metadata.pr else {
+            println!(
+                "{} {} has not been submitted",
+                color::dimmed().paint("skipped"),
+                commit.title
+            );
+            below = None;
+            continue;
+        };
+        let pr = forge
+            .get_pr(number)
+            .await
+            .with_context(|| format!("failed to get PR {number}"))?;
+
+        // Mirrors how submit picks the base of each PR
+        let base = match &commit.options.base {
+            Some(base) => base.clone(),
+            None if index == 0 || config.push_remote.is_some() => stack.upstream().to_string(),
+            None => match &below {
+                Some(below) => below.head.ref_field.clone(),
+                None => {
+                    println!(
+                        "{} #{number} {}: the commit below it has not been submitted",
+                        color::failure().paint("failed "),
+                        commit.title
+                    );
+                    passed = false;
+                    continue;
+                }
+            },
+        };
+
+        let found = problems(commit.id(), &pr, below.as_ref(), &base);
+        match found.is_empty() {
+            true => println!(
+                "{} #{number} {}",
+                color::success().paint("ok     "),
+                commit.title
+            ),
+            false => {
+                passed = false;
+                println!(
+                    "{} #{number} {}: {}",
+                    color::failure().paint("failed "),
+                    commit.title,
+                    found.join(", ")
+                );
+            }
+        }
+
+        if publish && pr.state == Some(IssueState::Open) {
+            gh::timeout(
+                config.api.timeout(),
+                &format!("publishing the stack check on PR {number}"),
+                publish_run(octocrab, gh_repo, &pr, &found),
+            )
+            .await??;
+        }
+        below = Some(pr);
+    }
+
+    Ok(passed)
+}
+
+/// Returns what's wrong with how `pr`, the PR of the commit `id`, is stacked on `below`, the
+/// PR of the commit under it, when it should be made against `base`
+fn problems(id: Oid, pr: &PullRequest, below: Option<&PullRequest>, base: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    if pr.base.ref_field != base {
+        problems.push(format!(
+            "made against {} instead of {base}",
+            pr.base.ref_field
+        ));
+    }
+
+    if let Some(below) = below {
+        if below.merged_at.is_some() {
+            problems.push(format!("#{} below it has merged, restack", below.number));
+        } else if below.state == Some(IssueState::Closed) {
+            problems.push(format!("#{} below it was closed", below.number));
+        }
+    }
+
+    if pr.head.sha != id.to_string() {
+        problems.push(format!(
+            "head {} isn't the commit in the stack, submit again",
+            short(&pr.head.sha)
+        ));
+    }
+
+    problems
+}
+
+/// Post the result on the head of `pr`, failing with `problems` if there are any. GitHub only
+/// lets apps create check runs, so with a personal token it's posted as a commit status instead
+async fn publish_run(
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    pr: &PullRequest,
+    problems: &[String],
+) -> Result<()> {
+    let (conclusion, title) = match problems.is_empty() {
+        true => ("success", "Stacked correctly".to_string()),
+        false => (
+            "failure",
+            format!("{} problems with the stack", problems.len()),
+        ),
+    };
+    let summary = match problems.is_empty() {
+        true => "This PR is made against the PR below it, which is still open, and its head is \
+            the commit in the author's stack."
+            .to_string(),
+        false => problems
+            .iter()
+            .map(|problem| format!("* {problem}\n"))
+            .collect(),
+    };
+
+    tracing::debug!(pr = pr.number, conclusion, "publishing check run");
+    let route = format!("/repos/{}/{}/check-runs", gh_repo.owner, gh_repo.repo);
+    let run: octocrab::Result<serde_json::Value> = octocrab
+        .post(
+            route,
+            Some(&json!({
+                "name": NAME,
+                "head_sha": pr.head.sha,
+                "status": "completed",
+                "conclusion": conclusion,
+                "output": { "title": title, "summary": summary },
+            })),
+        )
+        .await;
+    match run {
+        Ok(_) => return Ok(()),
+        Err(octocrab::Error::GitHub { source, .. }) => {
+            tracing::debug!(
+                pr = pr.number,
+                reason = source.message,
+                "check run refused, publishing commit status"
+            );
+        }
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to publish check run on PR {}", pr.number))
+        }
+    }
+
+    // Statuses only have a one line description
+    let description = match problems {
+        [] => title,
+        [problem] => problem.clone(),
+        _ => format!("{title}, run `fel check` for details"),
+    };
+    let route = format!(
+        "/repos/{}/{}/statuses/{}",
+        gh_repo.owner, gh_repo.repo, pr.head.sha
+    );
+    let _: serde_json::Value = octocrab
+        .post(
+            route,
+            Some(&json!({
+                "state": conclusion,
+                "context": NAME,
+                "description": truncate(&description, 140),
+            })),
+        )
+        .await
+        .with_context(|| {
+            format!(
+                "failed to publish the stack check on PR {}, --publish needs a token that can \
+                write checks or commit statuses, like the GITHUB_TOKEN of an Actions job",
+                pr.number
+            )
+        })?;
+
+    Ok(())
+}
+
+/// The first `max` characters of `text`, marked with an ellipsis if any were cut
+fn truncate(text: &str, max: usize) -> String {
+    match text.chars().count() > max {
+        true => text.chars().take(max - 1).chain(['…']).collect(),
+        false => text.to_string(),
+    }
+}
+
+fn short(sha: &str) -> &str {
+    sha.get(..8).unwrap_or(sha)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pr(number: u64, head: &str, sha: &str, base: &str, merged: bool) -> PullRequest {
+        serde_json::from_value(json!({
+            "url": "",
+            "id": number,
+            "number": number,
+            "state": "open",
+            "merged_at": merged.then_some("2024-01-01T00:00:00Z"),
+            "head": { "ref": head, "sha": sha },
+            "base": { "ref": base, "sha": "" },
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn finds_stacking_problems() {
+        let id = Oid::from_str("1111111111111111111111111111111111111111").unwrap();
+        let below = pr(1, "fel/a", "", "main", false);
+        let good = pr(2, "fel/b", &id.to_string(), "fel/a", false);
+        assert!(problems(id, &good, Some(&below), "fel/a").is_empty());
+
+        let merged = pr(1, "fel/a", "", "main", true);
+        let stale = pr(2, "fel/b", "2222222222", "main", false);
+        let found = problems(id, &stale, Some(&merged), "fel/a");
+        assert_eq!(
+            found,
+            [
+                "made against main instead of fel/a",
+                "#1 below it has merged, restack",
+                "head 22222222 isn't the commit in the stack, submit again",
+            ]
+        );
+    }
+}
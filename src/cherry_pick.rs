@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::{Commit, Oid, Remote, Repository, RepositoryState, Sort};
+
+use crate::commit;
+use crate::error::FelError;
+use crate::metadata::{Metadata, NoteBatch};
+use crate::stack::Stack;
+use crate::state::{PendingPick, State};
+use crate::upstream;
+
+/// Fetch PR `number` from `remote` and cherry-pick its commits onto the stack, along with any
+/// it is built on that aren't in `onto` or the stack yet. Each picked commit gets a new
+/// note recording where it came from, so it is submitted as a PR of its own. Returns how many
+/// commits were picked. With `autostash`, uncommitted changes are stashed while picking. If a
+/// pick stops on a conflict, it's left for `fel continue` or `fel abort`
+pub fn cherry_pick(
+    repo: &Repository,
+    remote: &mut Remote,
+    stack: &Stack,
+    onto: &str,
+    number: u64,
+    proxy: Option<&str>,
+    autostash: bool,
+) -> Result<usize> {
+    let head = upstream::fetch_pull(repo, remote, number, proxy)?;
+    let onto = repo
+        .revparse_single(onto)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("failed to find {onto}"))?;
+
+    let mut walk = repo.revwalk().context("failed to walk PR")?;
+    walk.set_sorting(Sort::TOPOLOGICAL | Sort::REVERSE)?;
+    walk.push(head)?;
+    // Only the PR's own commits, not the upstream it was made against
+    walk.hide(onto.id())?;
+    if let Ok(base) = repo.merge_base(head, onto.id()) {
+        walk.hide(base)?;
+    }
+    if let Ok(ours) = repo.head().and_then(|head| head.peel_to_commit()) {
+        walk.hide(ours.id())?;
+    }
+
+    // Commits already in the stack under another id, from an earlier pick or a rebase
+    let mut ours = HashSet::new();
+    for commit in stack.iter() {
+        ours.insert(commit::patch_id(repo, commit.id())?);
+    }
+
+    let mut picks = Vec::new();
+    for id in walk {
+        let id = id.context("failed to walk PR")?;
+        let commit = repo.find_commit(id)?;
+        anyhow::ensure!(
+            commit.parent_count() == 1,
+            "PR {number} contains the merge commit {id}, which can't be cherry-picked"
+        );
+        match ours.contains(&commit::patch_id(repo, id)?) {
+            true => tracing::debug!(?id, "already in stack"),
+            false => picks.push(id),
+        }
+    }
+    if picks.is_empty() {
+        return Ok(0);
+    }
+
+    anyhow::ensure!(
+        State::load(repo)?.pending_pick().is_none(),
+        "a cherry-pick is already in progress, finish it with `fel continue` or `fel abort`"
+    );
+
+    let workdir = repo.workdir().context("repo has no working directory")?;
+    let stashed = match autostash {
+        true => !upstream::dirty(repo)?.is_empty(),
+        false => {
+            upstream::ensure_clean(repo, &format!("cherry-pick PR {number}"))?;
+            false
+        }
+    };
+    if stashed {
+        let status = Command::new("git")
+            .args(["stash", "push", "--message", "fel: autostash"])
+            .current_dir(workdir)
+            .status()
+            .context("failed to run git stash")?;
+        anyhow::ensure!(status.success(), "failed to stash changes");
+    }
+
+    // Recorded first, so `fel continue` can finish the pick if it stops on a conflict
+    let pick = PendingPick {
+        number,
+        picks: picks.iter().map(Oid::to_string).collect(),
+        base: repo.head()?.peel_to_commit()?.id().to_string(),
+        stashed,
+    };
+    let mut state = State::load(repo)?;
+    state.set_pending_pick(Some(pick.clone()));
+    state.save()?;
+
+    let mut command = Command::new("git");
+    command
+        .arg("cherry-pick")
+        .args(&pick.picks)
+        .current_dir(workdir);
+    run_pick(repo, &pick, command)?;
+    finish(repo, &pick)
+}
+
+/// Finish a `fel cherry-pick` that stopped on a conflict, once the conflicts are resolved.
+/// Returns how many commits were picked
+pub fn continue_pick(repo: &Repository, pick: &PendingPick) -> Result<usize> {
+    // The pick may already have been finished with git
+    if in_pick(repo) {
+        let workdir = repo.workdir().context("repo has no working directory")?;
+        let mut command = Command::new("git");
+        command
+            .args(["cherry-pick", "--continue"])
+            .current_dir(workdir);
+        run_pick(repo, pick, command)?;
+    }
+    finish(repo, pick)
+}
+
+/// Roll back a `fel cherry-pick` that stopped on a conflict, and restore any changes it stashed
+pub fn abort_pick(repo: &Repository, pick: &PendingPick) -> Result<()> {
+    let workdir = repo.workdir().context("repo has no working directory")?;
+    if in_pick(repo) {
+        let status = Command::new("git")
+            .args(["cherry-pick", "--abort"])
+            .current_dir(workdir)
+            .status()
+            .context("failed to run git cherry-pick")?;
+        anyhow::ensure!(status.success(), "git cherry-pick --abort failed");
+    }
+    if pick.stashed {
+        pop_stash(workdir)?;
+    }
+
+    let mut state = State::load(repo)?;
+    state.set_pending_pick(None);
+    state.save()
+}
+
+/// Run a `git cherry-pick` command, leaving the pick pending if it stops on a conflict
+fn run_pick(repo: &Repository, pick: &PendingPick, mut command: Command) -> Result<()> {
+    let status = command.status().context("failed to run git cherry-pick")?;
+    if status.success() {
+        return Ok(());
+    }
+    if in_pick(repo) {
+        return Err(FelError::Conflict(format!("while cherry-picking PR {}", pick.number)).into());
+    }
+
+    // Anything but a conflict stops without leaving a pick in progress
+    let mut state = State::load(repo)?;
+    state.set_pending_pick(None);
+    state.save()?;
+    anyhow::bail!(
+        "git cherry-pick failed{}",
+        match pick.stashed {
+            true => ", restore your changes with `git stash pop`",
+            false => "",
+        }
+    )
+}
+
+/// Restore stashed changes and give each picked commit a note recording where it came from.
+/// Returns how many commits were picked
+fn finish(repo: &Repository, pick: &PendingPick) -> Result<usize> {
+    let workdir = repo.workdir().context("repo has no working directory")?;
+    if pick.stashed {
+        pop_stash(workdir)?;
+    }
+
+    // Picks skipped while resolving conflicts are missing, and git may have added to the
+    // messages of the rest, so match each commit on top of the base to its pick by author
+    // and title
+    let mut sources = HashMap::new();
+    for source in &pick.picks {
+        let commit = repo.find_commit(Oid::from_str(source)?)?;
+        sources.insert(pick_key(&commit), source.clone());
+    }
+    let mut walk = repo.revwalk().context("failed to walk picks")?;
+    walk.push_head()?;
+    walk.hide(Oid::from_str(&pick.base)?)?;
+
+    let mut notes = NoteBatch::default();
+    let mut picked = 0;
+    for id in walk {
+        let id = id.context("failed to walk picks")?;
+        let Some(source) = sources.get(&pick_key(&repo.find_commit(id)?)) else {
+            continue;
+        };
+        let metadata = Metadata {
+            picked_from: Some(source.clone()),
+            picked_from_pr: Some(pick.number),
+            ..Default::default()
+        };
+        notes.add(&metadata, id)?;
+        picked += 1;
+    }
+    notes.write(repo).context("failed to write notes")?;
+
+    let mut state = State::load(repo)?;
+    state.set_pending_pick(None);
+    state.save()?;
+    Ok(picked)
+}
+
+fn pick_key(commit: &Commit) -> (Vec<u8>, i64, Vec<u8>) {
+    let author = commit.author();
+    (
+        author.email_bytes().to_vec(),
+        author.when().seconds(),
+        commit.summary_bytes().unwrap_or_default().to_vec(),
+    )
+}
+
+fn pop_stash(workdir: &Path) -> Result<()> {
+    let status = Command::new("git")
+        .args(["stash", "pop"])
+        .current_dir(workdir)
+        .status()
+        .context("failed to run git stash pop")?;
+    anyhow::ensure!(status.success(), "failed to restore stashed changes");
+    Ok(())
+}
+
+fn in_pick(repo: &Repository) -> bool {
+    matches!(
+        repo.state(),
+        RepositoryState::CherryPick | RepositoryState::CherryPickSequence
+    )
+}
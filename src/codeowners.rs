@@ -0,0 +1,87 @@
+use anyhow::{Context, Result};
+use git2::Repository;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::config::OwnerRule;
+
+/// Places GitHub looks for a CODEOWNERS file, in order of precedence
+const LOCATIONS: [&str; 3] = [".github/CODEOWNERS", "CODEOWNERS", "docs/CODEOWNERS"];
+
+/// Maps paths in the repo to the users and teams that own them
+pub struct Owners {
+    rules: Vec<(GlobSet, Vec<String>)>,
+}
+
+impl Owners {
+    /// Load the repo's CODEOWNERS file followed by the rules from config. Like CODEOWNERS,
+    /// the last matching rule wins, so config rules take precedence
+    pub fn load(repo: &Repository, config_rules: &[OwnerRule]) -> Result<Self> {
+        let mut rules = Vec::new();
+
+        let codeowners = repo
+            .workdir()
+            .into_iter()
+            .flat_map(|workdir| LOCATIONS.iter().map(move |path| workdir.join(path)))
+            .find(|path| path.is_file());
+
+        if let Some(path) = codeowners {
+            tracing::debug!(?path, "reading CODEOWNERS");
+            let contents = std::fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+
+            for line in contents.lines() {
+                let line = line.split('#').next().unwrap_or_default();
+                let mut fields = line.split_whitespace();
+                let Some(pattern) = fields.next() else {
+                    continue;
+                };
+                rules.push((globs(pattern)?, fields.map(str::to_string).collect()));
+            }
+        }
+
+        for rule in config_rules {
+            rules.push((globs(&rule.pattern)?, rule.owners.clone()));
+        }
+
+        Ok(Self { rules })
+    }
+
+    /// Returns the owners of `path`, which is relative to the root of the repo
+    pub fn owners(&self, path: &str) -> &[String] {
+        self.rules
+            .iter()
+            .rev()
+            .find(|(globs, _)| globs.is_match(path))
+            .map(|(_, owners)| owners.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+/// Translate a CODEOWNERS pattern, which follows gitignore rules, into globs
+pub fn globs(pattern: &str) -> Result<GlobSet> {
+    // Patterns containing a slash anywhere but the end are relative to the root,
+    // anything else can match at any depth
+    let trimmed = pattern.trim_end_matches('/');
+    let base = match trimmed.strip_prefix('/') {
+        Some(anchored) => anchored.to_string(),
+        None if trimmed.contains('/') => trimmed.to_string(),
+        None => format!("**/{trimmed}"),
+    };
+
+    // A pattern matching a directory owns everything in it
+    let mut patterns = vec![format!("{base}/**")];
+    if !pattern.ends_with('/') {
+        patterns.push(base);
+    }
+
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        let glob = GlobBuilder::new(&pattern)
+            .literal_separator(true)
+            .build()
+            .with_context(|| format!("invalid owners pattern {pattern}"))?;
+        builder.add(glob);
+    }
+
+    builder.build().context("failed to build owners patterns")
+}
@@ -0,0 +1,123 @@
+use std::env;
+use std::io::IsTerminal;
+use std::sync::OnceLock;
+
+use ansi_term::{Colour, Style};
+use anyhow::{Context, Result};
+
+use crate::config::Colors;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color output to a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+struct Palette {
+    warning: Colour,
+    success: Colour,
+    failure: Colour,
+}
+
+const DEFAULT: Palette = Palette {
+    warning: Colour::Yellow,
+    success: Colour::Green,
+    failure: Colour::Red,
+};
+
+static ENABLED: OnceLock<bool> = OnceLock::new();
+static PALETTE: OnceLock<Palette> = OnceLock::new();
+
+/// Decide whether output is colored. Until this is called, nothing is
+pub fn init(choice: ColorChoice) {
+    let enabled = match choice {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => {
+            env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                && std::io::stdout().is_terminal()
+        }
+    };
+    ENABLED.set(enabled).ok();
+}
+
+/// Returns true if output is colored
+pub fn enabled() -> bool {
+    ENABLED.get().copied().unwrap_or(false)
+}
+
+/// Use the colors from config instead of the defaults
+pub fn set_colors(colors: &Colors) -> Result<()> {
+    let palette = Palette {
+        warning: parse(&colors.warning).context("invalid colors.warning")?,
+        success: parse(&colors.success).context("invalid colors.success")?,
+        failure: parse(&colors.failure).context("invalid colors.failure")?,
+    };
+    PALETTE.set(palette).ok();
+    Ok(())
+}
+
+/// For things that need attention, and commits and PRs being worked on
+pub fn warning() -> Style {
+    paint(|palette| palette.warning)
+}
+
+/// For things that are done or good to go
+pub fn success() -> Style {
+    paint(|palette| palette.success)
+}
+
+/// For things that failed or block landing
+pub fn failure() -> Style {
+    paint(|palette| palette.failure)
+}
+
+pub fn dimmed() -> Style {
+    match enabled() {
+        true => Style::new().dimmed(),
+        false => Style::new(),
+    }
+}
+
+pub fn bold() -> Style {
+    match enabled() {
+        true => Style::new().bold(),
+        false => Style::new(),
+    }
+}
+
+fn paint(colour: impl Fn(&Palette) -> Colour) -> Style {
+    match enabled() {
+        true => colour(PALETTE.get().unwrap_or(&DEFAULT)).normal(),
+        false => Style::new(),
+    }
+}
+
+/// Parse a color name like `yellow`, a terminal color number from 0 to 255, or `#rrggbb`
+fn parse(name: &str) -> Result<Colour> {
+    let colour = match name.to_lowercase().as_str() {
+        "black" => Colour::Black,
+        "red" => Colour::Red,
+        "green" => Colour::Green,
+        "yellow" => Colour::Yellow,
+        "blue" => Colour::Blue,
+        "purple" | "magenta" => Colour::Purple,
+        "cyan" => Colour::Cyan,
+        "white" => Colour::White,
+        name => match (name.parse::<u8>(), name.strip_prefix('#')) {
+            (Ok(number), _) => Colour::Fixed(number),
+            (_, Some(hex)) if hex.len() == 6 => {
+                let rgb = u32::from_str_radix(hex, 16)
+                    .with_context(|| format!("{name} is not a hex color"))?;
+                Colour::RGB((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8)
+            }
+            _ => anyhow::bail!(
+                "unknown color {name}, use a name like yellow, a number from 0 to 255 or #rrggbb"
+            ),
+        },
+    };
+    Ok(colour)
+}
@@ -0,0 +1,168 @@
+use anyhow::{Context, Result};
+use octocrab::Octocrab;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::color;
+use crate::gh::{self, GHRepo};
+use crate::stack::Stack;
+use crate::web;
+
+#[derive(serde::Deserialize, Debug)]
+struct Threads {
+    nodes: Vec<Thread>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Thread {
+    is_resolved: bool,
+    is_outdated: bool,
+    path: String,
+    line: Option<u64>,
+    comments: Comments,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Comments {
+    nodes: Vec<Comment>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Comment {
+    author: Option<Author>,
+    body: String,
+    url: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Author {
+    login: String,
+}
+
+/// Fetch the unresolved review threads on every PR in `prs` in one query
+async fn fetch(
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    prs: &[u64],
+    timeout: Duration,
+) -> Result<Vec<Vec<Thread>>> {
+    if prs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let fields = prs
+        .iter()
+        .map(|pr| {
+            format!(
+                "pr{pr}: pullRequest(number: {pr}) {{
+                    reviewThreads(first: 100) {{ nodes {{
+                        isResolved isOutdated path line
+                        comments(first: 50) {{ nodes {{ author {{ login }} body url }} }}
+                    }} }}
+                }}"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let query = format!(
+        "query($owner: String!, $name: String!) {{
+            repository(owner: $owner, name: $name) {{ {fields} }}
+        }}"
+    );
+
+    let query = gh::graphql(
+        octocrab,
+        &query,
+        json!({ "owner": gh_repo.owner, "name": gh_repo.repo }),
+    );
+    let data = gh::timeout(timeout, "getting review threads", query)
+        .await?
+        .context("failed to get review threads")?;
+
+    prs.iter()
+        .map(|pr| {
+            let threads: Threads = serde_json::from_value(
+                data["repository"][format!("pr{pr}")]["reviewThreads"].clone(),
+            )
+            .with_context(|| format!("invalid review threads for PR {pr}"))?;
+            Ok(threads
+                .nodes
+                .into_iter()
+                .filter(|thread| !thread.is_resolved)
+                .collect())
+        })
+        .collect()
+}
+
+/// Print the unresolved review comments on every PR in the stack, grouped by commit and file.
+/// If `open` is set, also open each unresolved thread in the browser
+pub async fn comments(
+    stack: &Stack,
+    open: bool,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    timeout: Duration,
+) -> Result<()> {
+    let prs: Vec<_> = stack
+        .iter()
+        .filter_map(|commit| commit.metadata.pr)
+        .collect();
+    let threads = fetch(octocrab, gh_repo, &prs, timeout).await?;
+
+    let mut unresolved = 0;
+    for commit in stack.iter().rev() {
+        let Some(pr) = commit.metadata.pr else {
+            continue;
+        };
+        let index = prs.iter().position(|p| *p == pr).expect("pr was fetched");
+        if threads[index].is_empty() {
+            continue;
+        }
+
+        let mut files: BTreeMap<&str, Vec<&Thread>> = BTreeMap::new();
+        for thread in &threads[index] {
+            files.entry(&thread.path).or_default().push(thread);
+        }
+
+        println!(
+            "{}",
+            color::warning().paint(format!("* #{pr} {}", commit.title))
+        );
+        for (path, threads) in files {
+            println!("  {}", color::bold().paint(path));
+            for thread in threads {
+                let Some(first) = thread.comments.nodes.first() else {
+                    continue;
+                };
+                unresolved += 1;
+
+                let line = match (thread.line, thread.is_outdated) {
+                    (_, true) => "outdated".to_string(),
+                    (Some(line), false) => format!("line {line}"),
+                    (None, false) => "file".to_string(),
+                };
+                println!("    {line}: {}", first.url);
+                for comment in &thread.comments.nodes {
+                    let author = comment
+                        .author
+                        .as_ref()
+                        .map_or("ghost", |author| author.login.as_str());
+                    let body = comment.body.lines().next().unwrap_or_default();
+                    println!("      {author}: {body}");
+                }
+
+                if open {
+                    web::open(&first.url)?;
+                }
+            }
+        }
+    }
+
+    if unresolved == 0 {
+        println!("no unresolved comments on {}", stack.name());
+    }
+
+    Ok(())
+}
@@ -8,19 +8,68 @@ pub struct Commit {
     pub metadata: Metadata,
     pub title: String,
     pub body: String,
+    pub author: String,
+    /// Trailers at the end of the commit message, like `Signed-off-by: ...`
+    pub trailers: Vec<(String, String)>,
+    /// PR options set with `Fel-*` trailers
+    pub options: PrOptions,
     id: Oid,
     parent: Oid,
+    is_merge: bool,
+    is_empty: bool,
+}
+
+/// Decode `bytes` from the message of commit `id`. If `lossy` is set, invalid UTF-8 is replaced
+/// rather than being an error
+pub fn decode(id: Oid, bytes: &[u8], lossy: bool) -> Result<String> {
+    match lossy {
+        true => Ok(String::from_utf8_lossy(bytes).into_owned()),
+        false => std::str::from_utf8(bytes)
+            .map(str::to_string)
+            .with_context(|| {
+                format!("message of {id} is not valid utf-8, set lossy_utf8 to submit it anyway")
+            }),
+    }
 }
 
 impl Commit {
-    pub fn new<'repo>(commit: git2::Commit<'repo>, repo: &'repo Repository) -> Result<Commit> {
+    /// Read a commit from the repo. If `lossy` is set, invalid UTF-8 in the commit message is
+    /// replaced rather than being an error
+    pub fn new<'repo>(
+        commit: git2::Commit<'repo>,
+        repo: &'repo Repository,
+        lossy: bool,
+    ) -> Result<Commit> {
         let parent = commit.parent_id(0).context("get parent")?;
+        let parent_tree = commit.parent(0).context("get parent")?.tree_id();
+        let text = |bytes: Option<&[u8]>| decode(commit.id(), bytes.unwrap_or_default(), lossy);
+
+        let trailers: Vec<_> = git2::message_trailers_bytes(commit.message_bytes())
+            .context("failed to parse trailers")?
+            .iter()
+            .map(|(key, value)| {
+                (
+                    String::from_utf8_lossy(key).into_owned(),
+                    String::from_utf8_lossy(value).into_owned(),
+                )
+            })
+            .collect();
+        let metadata = Metadata::new(repo, &commit).context("failed to get metadata")?;
+        let mut options = PrOptions::parse(&trailers)
+            .with_context(|| format!("invalid Fel- trailer in {}", commit.id()))?;
+        options.base = options.base.or_else(|| metadata.base.clone());
+
         Ok(Commit {
-            metadata: Metadata::new(repo, &commit).context("failed to get metadata")?,
-            title: commit.summary().context("summary not utf8")?.to_string(),
-            body: commit.body().unwrap_or("body not utf8").to_string(),
+            metadata,
+            title: text(commit.summary_bytes())?,
+            body: text(commit.body_bytes())?,
+            trailers,
+            options,
+            author: String::from_utf8_lossy(commit.author().name_bytes()).into_owned(),
             id: commit.id(),
             parent,
+            is_merge: commit.parent_count() > 1,
+            is_empty: commit.parent_count() == 1 && commit.tree_id() == parent_tree,
         })
     }
 
@@ -36,6 +85,19 @@ impl Commit {
     //Ok(())
     //}
 
+    /// Returns the value of the first trailer with the key `key`, ignoring case
+    pub fn trailer(&self, key: &str) -> Option<&str> {
+        self.trailers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Returns true if this commit is not the one that was last submitted
+    pub fn changed(&self) -> bool {
+        Some(self.id.to_string()) != self.metadata.commit
+    }
+
     pub fn id(&self) -> Oid {
         self.id
     }
@@ -43,4 +105,115 @@ impl Commit {
     pub fn parent(&self) -> &Oid {
         &self.parent
     }
+
+    pub fn is_merge(&self) -> bool {
+        self.is_merge
+    }
+
+    /// Returns true if the commit doesn't change anything, like after its changes landed
+    /// upstream and it was rebased
+    pub fn is_empty(&self) -> bool {
+        self.is_empty
+    }
+
+    /// Save `base` in the commit's note as the branch to make its PR against, or clear it with
+    /// `None`. A `Fel-Base` trailer still takes precedence
+    pub fn set_base(&mut self, repo: &Repository, base: Option<String>) -> Result<()> {
+        self.metadata.base = base;
+        self.metadata
+            .write(repo, self.id)
+            .context("failed to write metadata")?;
+        self.options.base = PrOptions::parse(&self.trailers)?
+            .base
+            .or_else(|| self.metadata.base.clone());
+        Ok(())
+    }
+
+    /// Treat `parent` as the commit's parent in the stack, when the commit between them is
+    /// left out of it
+    pub fn set_parent(&mut self, parent: Oid) {
+        self.parent = parent;
+    }
+}
+
+/// Options for a commit's PR, overriding config for that PR only
+#[derive(Clone, Debug, Default)]
+pub struct PrOptions {
+    /// Extra reviewers from `Fel-Reviewers`, as `user` or `@org/team`
+    pub reviewers: Vec<String>,
+    /// Extra labels from `Fel-Labels`
+    pub labels: Vec<String>,
+    /// Whether to open the PR as a draft, from `Fel-Draft`
+    pub draft: Option<bool>,
+    /// The branch to open the PR against, from `Fel-Base`
+    pub base: Option<String>,
+}
+
+impl PrOptions {
+    fn parse(trailers: &[(String, String)]) -> Result<Self> {
+        let mut options = PrOptions::default();
+        let list = |value: &str| {
+            value
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+
+        for (key, value) in trailers {
+            let value = value.trim();
+            match key.to_ascii_lowercase().as_str() {
+                "fel-reviewers" => options.reviewers.extend(list(value)),
+                "fel-labels" => options.labels.extend(list(value)),
+                "fel-draft" => {
+                    options.draft = Some(match value.to_ascii_lowercase().as_str() {
+                        "true" | "yes" | "1" => true,
+                        "false" | "no" | "0" => false,
+                        _ => anyhow::bail!("Fel-Draft must be true or false, not {value:?}"),
+                    })
+                }
+                "fel-base" if !value.is_empty() => options.base = Some(value.to_string()),
+                _ => {}
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// Returns the patch-id of the commit `id`, which stays the same when a commit is rebased
+/// without changing its diff
+pub fn patch_id(repo: &Repository, id: Oid) -> Result<Oid> {
+    diff(repo, id)?
+        .patchid(None)
+        .context("failed to compute patch-id")
+}
+
+/// Returns the paths changed by the commit `id`
+pub fn changed_files(repo: &Repository, id: Oid) -> Result<Vec<String>> {
+    let diff = diff(repo, id)?;
+    Ok(diff
+        .deltas()
+        .filter_map(|delta| delta.new_file().path().or(delta.old_file().path()))
+        .map(|path| path.to_string_lossy().into_owned())
+        .collect())
+}
+
+/// Diff the commit `id` against its first parent
+pub fn diff(repo: &Repository, id: Oid) -> Result<git2::Diff<'_>> {
+    let commit = repo.find_commit(id).context("failed to find commit")?;
+    let parent_tree = match commit.parent_count() {
+        0 => None,
+        _ => Some(
+            commit
+                .parent(0)
+                .context("failed to get parent")?
+                .tree()
+                .context("failed to get parent tree")?,
+        ),
+    };
+    let tree = commit.tree().context("failed to get tree")?;
+    repo.diff_tree_to_tree(parent_tree.as_ref(), Some(&tree), None)
+        .context("failed to diff commit")
 }
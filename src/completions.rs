@@ -0,0 +1,90 @@
+use std::io::Write;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap_complete::Shell;
+use git2::{BranchType, Repository};
+use octocrab::Octocrab;
+
+use fel_core::gh::{self, GHRepo};
+use fel_core::metadata::NOTE_REF;
+use fel_core::stack::Stack;
+
+/// Values the hidden `__complete` command can list for shell completions
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Candidates {
+    /// Indices of the commits in the current stack
+    Indices,
+    /// Local branches with fel stacks on them
+    Stacks,
+    /// Open PRs in the repo
+    Prs,
+}
+
+/// Print the completion script for `shell`. Dynamic scripts also complete stack indices and
+/// PR numbers by calling back into `fel __complete`
+pub fn completions(shell: Shell, dynamic: bool, command: &mut clap::Command) -> Result<()> {
+    let mut stdout = std::io::stdout();
+    clap_complete::generate(shell, command, "fel", &mut stdout);
+
+    if !dynamic {
+        return Ok(());
+    }
+
+    let script = match shell {
+        Shell::Bash => include_str!("../templates/completions.bash"),
+        Shell::Fish => include_str!("../templates/completions.fish"),
+        _ => anyhow::bail!("dynamic completions aren't supported for {shell}"),
+    };
+    stdout
+        .write_all(script.as_bytes())
+        .context("failed to write completions")?;
+
+    Ok(())
+}
+
+/// Print completion candidates, one per line with a tab separated description
+pub async fn complete(
+    candidates: Candidates,
+    stack: &Stack,
+    repo: &Repository,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    timeout: Duration,
+) -> Result<()> {
+    match candidates {
+        Candidates::Indices => {
+            for (index, commit) in stack.iter().enumerate() {
+                println!("{index}\t{}", commit.title);
+            }
+        }
+        Candidates::Stacks => {
+            let branches = repo
+                .branches(Some(BranchType::Local))
+                .context("failed to list branches")?;
+            for branch in branches {
+                let (branch, _) = branch.context("failed to read branch")?;
+                let Some(tip) = branch.get().target() else {
+                    continue;
+                };
+                if repo.find_note(Some(NOTE_REF), tip).is_ok() {
+                    if let Some(name) = branch.name().context("invalid branch name")? {
+                        println!("{name}");
+                    }
+                }
+            }
+        }
+        Candidates::Prs => {
+            let pulls = octocrab.pulls(&gh_repo.owner, &gh_repo.repo);
+            let list = pulls.list().per_page(100).send();
+            let prs = gh::timeout(timeout, "listing PRs", list)
+                .await?
+                .context("failed to list PRs")?;
+            for pr in prs {
+                println!("#{}\t{}", pr.number, pr.title.unwrap_or_default());
+            }
+        }
+    }
+
+    Ok(())
+}
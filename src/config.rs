@@ -1,30 +1,717 @@
 use anyhow::{Context, Result};
-use std::{env, fs, path::PathBuf};
 
-#[derive(serde::Deserialize, Clone)]
+use crate::error::FelError;
+use crate::policy::Policy;
+use std::{collections::BTreeMap, env, fs, path::PathBuf, time::Duration};
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
 pub struct Config {
+    /// The token used to create and modify PRs
+    #[serde(default)]
     pub token: String,
+
+    /// A low privilege token used by commands that only read from GitHub, like `fel status`.
+    /// Defaults to `token`
+    pub read_token: Option<String>,
+
+    #[serde(default = "default_remote")]
     pub default_remote: String,
+    #[serde(default = "default_upstream")]
     pub default_upstream: String,
+
+    /// Push branches to this remote, usually a fork, instead of `default_remote`. PRs are
+    /// still made against `default_remote`
+    pub push_remote: Option<String>,
+
+    /// HTTP proxy for the GitHub API and HTTPS remotes, like `http://proxy:3128`. Defaults to
+    /// the `HTTPS_PROXY` environment variable
+    pub proxy: Option<String>,
+
+    /// GitHub API url. Defaults to `https://api.github.com` for github.com, and to
+    /// `https://<host>/api/v3`, where GitHub Enterprise Server serves it, for other hosts
+    pub api_url: Option<String>,
+
+    /// Private key for ssh remotes, instead of the first key in `~/.ssh` or ssh-agent
+    pub ssh_key: Option<PathBuf>,
+
+    /// Settings for repos whose remote is on a host, like `[hosts."github.example.com"]`,
+    /// which replace the ones above
+    #[serde(default)]
+    pub hosts: BTreeMap<String, Host>,
+
+    /// Replace invalid UTF-8 in commit messages instead of refusing to read them
+    #[serde(default)]
+    pub lossy_utf8: bool,
+
+    /// Stash uncommitted changes before restacking or cherry-picking, and restore them after,
+    /// instead of refusing to start
+    #[serde(default)]
+    pub autostash: bool,
+
+    #[serde(default)]
     pub submit: Submit,
+
+    #[serde(default)]
+    pub land: Land,
+
+    #[serde(default)]
+    pub api: Api,
+
+    #[serde(default)]
+    pub status: Status,
+
+    #[serde(default)]
+    pub colors: Colors,
+
+    #[serde(default)]
+    pub render: Render,
+
+    #[serde(default)]
+    pub email: Email,
+
+    #[serde(default)]
+    pub hooks: Hooks,
+
+    /// The repo's policy, once it has been applied
+    #[serde(skip)]
+    pub policy: Option<Policy>,
+
+    #[serde(default)]
+    pub validate: Validate,
+
+    /// Owners of paths in the repo, applied after the repo's CODEOWNERS file
+    #[serde(default)]
+    pub owners: Vec<OwnerRule>,
+
+    /// Commands whose output is added to each PR body, between the author's text and the footer
+    #[serde(default)]
+    pub sections: Vec<Section>,
+}
+
+/// A section of the PR body generated by a command, re-run for every commit on every submit
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Section {
+    pub name: String,
+    /// Run with `sh -c` in the working tree, with the commit in `FEL_COMMIT`
+    pub command: String,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct OwnerRule {
+    /// A path pattern, in the same format as CODEOWNERS
+    pub pattern: String,
+    pub owners: Vec<String>,
+}
+
+/// Rules every commit message in a stack has to follow before it is submitted
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct Validate {
+    /// Regex commit titles must match
+    pub title: Option<String>,
+
+    /// Regex commit bodies must match
+    pub body: Option<String>,
+
+    /// Require titles to be conventional commits, like `fix(parser): message`
+    #[serde(default)]
+    pub conventional: bool,
+
+    pub max_title_length: Option<usize>,
+}
+
+/// Where `submit.backend = "email"` sends patches. SMTP is configured with git's
+/// `sendemail.*` settings
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct Email {
+    #[serde(default)]
+    pub to: Vec<String>,
+    #[serde(default)]
+    pub cc: Vec<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Land {
+    /// Leave the fel footer out of the merge commit message
+    #[serde(default = "default_true")]
+    pub strip_footer: bool,
+
+    /// How PRs are merged. Defaults to the first of merge, squash and rebase that the repo
+    /// allows
+    pub merge_method: Option<MergeMethod>,
+}
+
+impl Default for Land {
+    fn default() -> Self {
+        Self {
+            strip_footer: true,
+            merge_method: None,
+        }
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeMethod {
+    /// Merge with a merge commit
+    Merge,
+    /// Squash the PR into a single commit
+    Squash,
+    /// Rebase the PR's commits onto the base branch
+    Rebase,
 }
 
-#[derive(serde::Deserialize, Clone)]
+/// Which GitHub API each kind of PR request goes through, to work around a bug or missing
+/// feature in one of them, like on an older GitHub Enterprise Server
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default)]
+pub struct Api {
+    #[serde(default)]
+    pub create: Transport,
+    #[serde(default)]
+    pub update: Transport,
+    #[serde(default)]
+    pub list: Transport,
+
+    /// Seconds to wait for each GitHub request before giving up. Defaults to 30
+    pub timeout: Option<u64>,
+}
+
+impl Api {
+    /// How long to wait for each GitHub request
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.timeout.unwrap_or(30))
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Transport {
+    #[default]
+    Rest,
+    Graphql,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct Render {
+    /// Only draw with ASCII characters. Defaults to true when the locale isn't UTF-8
+    pub ascii: Option<bool>,
+}
+
+/// Colors used in output, each a name like `yellow`, a terminal color number from 0 to 255,
+/// or `#rrggbb`
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
+pub struct Colors {
+    /// Things that need attention, and commits being submitted
+    #[serde(default = "default_warning")]
+    pub warning: String,
+    /// Things that are done or good to go
+    #[serde(default = "default_success")]
+    pub success: String,
+    /// Things that failed or block landing
+    #[serde(default = "default_failure")]
+    pub failure: String,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            warning: default_warning(),
+            success: default_success(),
+            failure: default_failure(),
+        }
+    }
+}
+
+fn default_warning() -> String {
+    "yellow".to_string()
+}
+
+fn default_success() -> String {
+    "green".to_string()
+}
+
+fn default_failure() -> String {
+    "red".to_string()
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct Status {
+    /// Highlight PRs that have waited this many hours for review since their last revision
+    pub review_warn_hours: Option<u64>,
+
+    /// Highlight PRs that have waited this many hours as overdue
+    pub review_alert_hours: Option<u64>,
+}
+
+/// Settings for one GitHub host, for people who work across more than one
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct Host {
+    /// Token for the host. Without one, the top level `token` is used
+    pub token: Option<String>,
+    pub read_token: Option<String>,
+    pub api_url: Option<String>,
+    pub ssh_key: Option<PathBuf>,
+}
+
+/// Shell commands run at points during a submit. Each one gets the stack as JSON on stdin,
+/// and a command exiting unsuccessfully aborts the submit
+#[derive(serde::Deserialize, serde::Serialize, Clone, Default)]
+pub struct Hooks {
+    /// Run before anything is sent to the remote
+    #[serde(default)]
+    pub pre_submit: Vec<String>,
+
+    /// Run right before branches are pushed
+    #[serde(default)]
+    pub pre_push: Vec<String>,
+
+    /// Run after every PR has been updated, with the new metadata
+    #[serde(default)]
+    pub post_submit: Vec<String>,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone)]
 pub struct Submit {
-    /// When creating branches during submit, use this field as a prefix
+    /// Where the stack is submitted to
+    #[serde(default)]
+    pub backend: Backend,
+
+    /// When creating branches during submit, use this field as a prefix. `@me` is the login of
+    /// the authenticated user
     pub branch_prefix: Option<String>,
 
+    /// Extra branch patterns fel refuses to push to or delete, on top of upstream, `main`, `master`,
+    /// `release/*` and the patterns of the repo's branch protection rules
+    #[serde(default)]
+    pub protected_branches: Vec<String>,
+
     /// When submitting branches, should the commit sha or the index of the commit in the stack
     /// be used as the branch
+    #[serde(default)]
     pub use_indexed_branches: bool,
+    #[serde(default)]
     pub auto_create_branches: bool,
+
+    /// What to do when submitting from a detached HEAD without `auto_create_branches`
+    #[serde(default)]
+    pub detached: Detached,
+
+    /// Name of branches created by `auto_create_branches`, where `{slug}` is made from the top
+    /// commit's title and `{sha}` is its short sha
+    #[serde(default = "default_dev_branch_template")]
+    pub dev_branch_template: String,
+
+    /// When false, fel never force pushes. Every new revision of a commit is pushed to a
+    /// fresh `<branch>-v<revision>` branch and the PR is replaced by one opened from that branch
+    #[serde(default = "default_true")]
+    pub allow_force: bool,
+
+    /// Also push every revision of a commit to an immutable `<branch>-v<revision>` branch,
+    /// so consecutive revisions can be compared after the head branch is force pushed
+    #[serde(default)]
+    pub keep_revisions: bool,
+
+    /// Fetch the upstream branch before submitting
+    #[serde(default)]
+    pub fetch: bool,
+
+    /// What to do when the stack isn't based on the tip of the upstream branch
+    #[serde(default)]
+    pub stale_base: StaleBase,
+
+    /// What to do when the stack contains merge commits
+    #[serde(default)]
+    pub merge_commits: MergeCommits,
+
+    /// What to do with commits that don't change anything
+    #[serde(default)]
+    pub empty_commits: EmptyCommits,
+
+    /// Whether to close PRs whose commits were dropped from the stack
+    #[serde(default)]
+    pub close_dropped: CloseDropped,
+
+    /// Whether to squash `fixup!` and `squash!` commits into their targets before submitting
+    #[serde(default)]
+    pub autosquash: Autosquash,
+
+    /// When to comment on a PR after pushing a new revision to it
+    #[serde(default)]
+    pub revision_comments: RevisionComments,
+
+    /// Tera template used for revision comments instead of the built in one
+    pub revision_comment_template: Option<String>,
+
+    /// Seconds a submit may take before it stops starting on new PRs. PRs it already started on
+    /// are finished and recorded. Unlimited by default
+    pub deadline: Option<u64>,
+
+    /// Create new PRs as drafts
+    #[serde(default)]
+    pub draft: bool,
+
+    /// Replace PR bodies with the commit body on every submit, instead of only using it when
+    /// the PR is created
+    #[serde(default)]
+    pub authoritative_commits: bool,
+
+    /// Labels added to every PR
+    #[serde(default)]
+    pub labels: Vec<String>,
+
+    /// Mirror the stack tree into a GitHub Discussion in this category, and link to it from
+    /// each PR instead of repeating the tree in every PR body
+    pub discussion_category: Option<String>,
+
+    /// Only mirror stacks with at least this many commits into a discussion
+    #[serde(default)]
+    pub discussion_threshold: usize,
+
+    /// Open an issue listing every PR in the stack with its status, keep it up to date on
+    /// every submit and link to it from each PR
+    #[serde(default)]
+    pub overview_issue: bool,
+
+    /// How PRs link to the overview issue, so GitHub shows them under its "Development"
+    #[serde(default)]
+    pub overview_link: OverviewLink,
+
+    /// Users to request reviews from on every new PR
+    #[serde(default)]
+    pub default_reviewers: Vec<String>,
+
+    /// Teams to request reviews from on every new PR, written as `org/team`
+    #[serde(default)]
+    pub default_team_reviewers: Vec<String>,
+
+    /// Request reviews on new PRs from the owners of the files they change, according to
+    /// CODEOWNERS and the owners in config
+    #[serde(default)]
+    pub codeowners_reviewers: bool,
+
+    /// Title for every PR, where `{index}` is the commit's position in the stack counting from
+    /// 1, `{total}` is the size of the stack and `{title}` is the commit title
+    pub title_template: Option<String>,
+
+    /// Tera template for the footer added to every PR body instead of the built in one
+    pub footer_template: Option<String>,
+
+    /// How much of the stack the footer shows
+    #[serde(default)]
+    pub footer: Footer,
+
+    /// Where the footer goes
+    #[serde(default)]
+    pub footer_placement: FooterPlacement,
+
+    /// Marker written between the author's part of a PR body and the part fel manages,
+    /// instead of `[#]:fel`
+    pub footer_delimiter: Option<String>,
+
+    /// Markers that started fel's part of PR bodies before, replaced with the current one on
+    /// the next submit. The built in marker is always recognized
+    #[serde(default)]
+    pub legacy_delimiters: Vec<String>,
+}
+
+impl Default for Submit {
+    fn default() -> Self {
+        // Every field has a serde default, so an empty table is the default config
+        toml::from_str("").expect("every submit field has a default")
+    }
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Open a PR for each commit
+    #[default]
+    Github,
+    /// Email the stack as a patch series with `git send-email`
+    Email,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum StaleBase {
+    /// Print a warning and submit anyway
+    #[default]
+    Warn,
+    /// Rebase the stack onto upstream before submitting
+    Restack,
+    /// Refuse to submit
+    Error,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Autosquash {
+    /// Submit fixups as their own PRs
+    #[default]
+    Never,
+    /// Ask before squashing
+    Ask,
+    /// Squash without asking
+    Always,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum EmptyCommits {
+    /// Refuse to submit
+    #[default]
+    Error,
+    /// Submit the rest of the stack without them
+    Skip,
+    /// Remove them from the branch before submitting
+    Drop,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CloseDropped {
+    /// Leave them open
+    Never,
+    /// Ask before closing them, or warn about them if there's no terminal
+    #[default]
+    Ask,
+    /// Close them without asking
+    Always,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Detached {
+    /// Refuse to submit
+    #[default]
+    Error,
+    /// Submit as an anonymous stack, named after its bottom commit the first time it is
+    /// submitted
+    Anonymous,
+}
+
+fn default_remote() -> String {
+    "origin".to_string()
+}
+
+fn default_upstream() -> String {
+    "main".to_string()
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_dev_branch_template() -> String {
+    "{slug}".to_string()
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MergeCommits {
+    /// Refuse to submit, and explain how to remove them
+    #[default]
+    Error,
+    /// Rebase the stack onto upstream, which drops the merge commits
+    Rebase,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum RevisionComments {
+    #[default]
+    Always,
+    /// Only comment when the patch changed, not when the commit was just rebased
+    OnContentChange,
+    Never,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum OverviewLink {
+    #[default]
+    None,
+    /// Every PR references the issue
+    Refs,
+    /// The PR at the top of the stack closes the issue, and the rest reference it
+    Closes,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Footer {
+    /// The whole stack tree, or `footer_template`
+    #[default]
+    Full,
+    /// One line naming the stack and the PR's position in it
+    Compact,
+    /// Nothing
+    None,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum FooterPlacement {
+    /// At the end of the PR body
+    #[default]
+    Body,
+    /// In a comment on the PR that fel edits on every submit, leaving the body alone. The
+    /// comment is deleted once the footer moves back to the body
+    Comment,
+}
+
+/// Returns the user's home dir, from `HOME` or `USERPROFILE` on Windows
+pub fn home_dir() -> Result<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .filter(|home| !home.is_empty())
+        .map(PathBuf::from)
+        .context("failed to get home dir, set HOME")
 }
 
 impl Config {
-    pub fn load() -> Result<Self> {
-        let home = PathBuf::from(env::var("HOME").context("failed to get home dir")?);
-        let config_path = home.join(".config/fel/config.toml");
-        let contents = fs::read_to_string(config_path).context("failed to load config")?;
-        Ok(toml::from_str(&contents)?)
+    /// Returns the path config is loaded from
+    pub fn path() -> Result<PathBuf> {
+        let config = home_dir()?.join(".config").join("fel").join("config.toml");
+
+        // On Windows, config in %APPDATA% is used unless there's already one in the home dir
+        #[cfg(windows)]
+        if !config.exists() {
+            if let Some(appdata) = env::var_os("APPDATA") {
+                return Ok(PathBuf::from(appdata).join("fel").join("config.toml"));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Use the settings in `hosts` for `host`, if it has any, in place of the top level ones.
+    /// A host with its own token doesn't fall back to the top level read token
+    pub fn use_host(&mut self, host: &str) {
+        let Some(settings) = self
+            .hosts
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(host))
+            .map(|(_, settings)| settings.clone())
+        else {
+            return;
+        };
+        tracing::debug!(host, "using host config");
+
+        if let Some(token) = settings.token {
+            self.token = token;
+            self.read_token = settings.read_token;
+        } else if settings.read_token.is_some() {
+            self.read_token = settings.read_token;
+        }
+        if settings.api_url.is_some() {
+            self.api_url = settings.api_url;
+        }
+        if settings.ssh_key.is_some() {
+            self.ssh_key = settings.ssh_key;
+        }
+    }
+
+    /// Returns the API url for repos on `host`
+    pub fn api_url(&self, host: &str) -> String {
+        match &self.api_url {
+            Some(url) => url.trim_end_matches('/').to_string(),
+            None if host.eq_ignore_ascii_case("github.com") => "https://api.github.com".to_string(),
+            None => format!("https://{host}/api/v3"),
+        }
+    }
+
+    /// Returns the token to use for a command, the read token unless the command writes
+    pub fn token(&self, write: bool) -> Result<&str> {
+        let token = match (write, &self.read_token) {
+            (false, Some(read_token)) => read_token,
+            _ => &self.token,
+        };
+
+        match (token.is_empty(), write) {
+            (false, _) => Ok(token),
+            (true, true) => Err(FelError::Auth(
+                "this command needs a write token, set token in config".to_string(),
+            )
+            .into()),
+            (true, false) => Err(FelError::Auth(
+                "no token configured, set token or read_token in config".to_string(),
+            )
+            .into()),
+        }
+    }
+
+    pub fn load() -> crate::Result<Self> {
+        let path = Self::path()?;
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => {
+                return Err(anyhow::Error::new(FelError::MissingConfig(path)).into())
+            }
+            Err(error) => {
+                return Err(anyhow::Error::new(error)
+                    .context("failed to load config")
+                    .into())
+            }
+        };
+        Ok(Self::parse(&contents).with_context(|| format!("invalid config {}", path.display()))?)
+    }
+
+    /// Parse config, naming the key that couldn't be parsed as well as its line
+    fn parse(contents: &str) -> Result<Self> {
+        serde_path_to_error::deserialize(toml::Deserializer::new(contents)).map_err(|error| {
+            let key = error.path().to_string();
+            let error = error.into_inner();
+            match key.as_str() {
+                "." => anyhow::anyhow!("{}", error.to_string().trim_end()),
+                _ => anyhow::anyhow!("bad value for `{key}`: {}", error.to_string().trim_end()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn minimal_config() {
+        let config = Config::parse("token = \"x\"").unwrap();
+        assert_eq!(config.default_remote, "origin");
+        assert!(config.submit.allow_force);
+        assert_eq!(config.submit.dev_branch_template, "{slug}");
+
+        let error = Config::parse("token = \"x\"\n[submit]\nbackend = \"gitlab\"")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("`submit.backend`") && error.contains("line 3"));
+    }
+
+    #[test]
+    fn host_settings() {
+        let mut config = Config::parse(
+            r#"
+            token = "personal"
+            read_token = "personal-read"
+
+            [hosts."GitHub.Example.com"]
+            token = "enterprise"
+            ssh_key = "~/.ssh/work"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.api_url("github.com"), "https://api.github.com");
+
+        config.use_host("github.com");
+        assert_eq!(config.token, "personal");
+
+        config.use_host("github.example.com");
+        assert_eq!(config.token, "enterprise");
+        assert_eq!(config.read_token, None);
+        assert_eq!(config.ssh_key, Some(PathBuf::from("~/.ssh/work")));
+        assert_eq!(
+            config.api_url("github.example.com"),
+            "https://github.example.com/api/v3"
+        );
     }
 }
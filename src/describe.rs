@@ -0,0 +1,122 @@
+use std::fs;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use octocrab::Octocrab;
+
+use crate::config::Config;
+use crate::gh::{self, GHRepo};
+use crate::pulls::{self, Pulls};
+use crate::stack::Stack;
+use crate::submit::{self, strip_footer};
+
+/// Edit the description of the commit at `index`, or the top of the stack, in the user's
+/// editor. Usually this edits the PR body and leaves the fel footer alone, but if commits are
+/// `authoritative` it rewrites the commit body instead, to be sent on the next submit
+pub async fn describe(
+    stack: &mut Stack,
+    index: Option<usize>,
+    authoritative: bool,
+    repo: &Repository,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    config: &Config,
+) -> Result<()> {
+    let index = match index {
+        Some(index) => index,
+        None => stack.len().checked_sub(1).context("stack is empty")?,
+    };
+    let commit = stack
+        .get(index)
+        .with_context(|| format!("no commit at index {index}"))?
+        .clone();
+
+    if authoritative {
+        let Some(body) = edit(repo, &commit.body)? else {
+            println!("description unchanged");
+            return Ok(());
+        };
+
+        let message = match body.is_empty() {
+            true => format!("{}\n", commit.title),
+            false => format!("{}\n\n{body}\n", commit.title),
+        };
+        stack
+            .reword(repo, index, &message)
+            .context("failed to rewrite commit")?;
+        println!(
+            "rewrote {}, run `fel submit` to update its PR",
+            commit.title
+        );
+        return Ok(());
+    }
+
+    let pr = commit
+        .metadata
+        .pr
+        .with_context(|| format!("{} has not been submitted", commit.title))?;
+    let timeout = config.api.timeout();
+    let pulls = octocrab.pulls(&gh_repo.owner, &gh_repo.repo);
+    let current = gh::timeout(timeout, &format!("getting PR {pr}"), pulls.get(pr))
+        .await?
+        .context("failed to get PR")?
+        .body
+        .unwrap_or_default();
+
+    // Everything after the author's part of the body belongs to fel
+    let original = strip_footer(&current, &submit::delimiters(&config.submit));
+    let footer = &current[original.len()..];
+    let Some(body) = edit(repo, original)? else {
+        println!("description unchanged");
+        return Ok(());
+    };
+
+    let update = pulls::Update {
+        body: Some(format!("{body}{footer}")),
+        ..Default::default()
+    };
+    let pulls = Pulls::new(octocrab, gh_repo, config.api);
+    gh::timeout(
+        timeout,
+        &format!("updating PR {pr}"),
+        pulls.update(pr, update),
+    )
+    .await?
+    .context("failed to update PR")?;
+    println!("updated #{pr}");
+
+    Ok(())
+}
+
+/// Open `text` in the user's git editor. Returns the edited text, or None if it didn't change
+fn edit(repo: &Repository, text: &str) -> Result<Option<String>> {
+    let dir = repo.path().join("fel");
+    fs::create_dir_all(&dir).context("failed to create fel dir")?;
+    let path = dir.join("DESCRIBE_EDITMSG");
+    fs::write(&path, format!("{text}\n")).context("failed to write description")?;
+
+    // `git var` picks the editor the same way commit does, from GIT_EDITOR, core.editor,
+    // VISUAL and EDITOR
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo.path())
+        .args(["var", "GIT_EDITOR"])
+        .output()
+        .context("failed to run git var")?;
+    anyhow::ensure!(output.status.success(), "failed to find an editor");
+    let editor = String::from_utf8(output.stdout).context("editor is not utf-8")?;
+
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{} \"$@\"", editor.trim()))
+        .arg("editor")
+        .arg(&path)
+        .status()
+        .context("failed to run editor")?;
+    anyhow::ensure!(status.success(), "editor exited with {status}");
+
+    let edited = fs::read_to_string(&path).context("failed to read description")?;
+    let edited = edited.trim_end();
+    Ok((edited != text.trim_end()).then(|| edited.to_string()))
+}
@@ -0,0 +1,84 @@
+use anyhow::{Context, Result};
+use git2::Repository;
+
+use crate::config::Config;
+use crate::forge::Forge;
+use crate::metadata::{Metadata, NoteBatch, NOTE_REF};
+use crate::pulls;
+use crate::stack::Stack;
+use crate::stack_comment;
+use crate::state::State;
+use crate::submit;
+
+/// Hand the PR of the commit at `index`, or the top of the stack, off as a standalone PR: take
+/// the fel footer out of its body and delete the stack comment, and with `retarget` make it
+/// against upstream, then remove every note naming the PR so fel forgets it. Nothing else
+/// about the PR changes
+pub async fn detach(
+    stack: &Stack,
+    index: Option<usize>,
+    retarget: bool,
+    repo: &Repository,
+    forge: &dyn Forge,
+    config: &Config,
+) -> Result<()> {
+    let index = match index {
+        Some(index) => index,
+        None => stack.len().checked_sub(1).context("stack is empty")?,
+    };
+    let commit = stack
+        .get(index)
+        .with_context(|| format!("no commit at index {index}"))?;
+    let number = commit
+        .metadata
+        .pr
+        .with_context(|| format!("{} has not been submitted", commit.title))?;
+
+    let pr = forge
+        .get_pr(number)
+        .await
+        .with_context(|| format!("failed to get PR {number}"))?;
+    let current = pr.body.unwrap_or_default();
+    let body = submit::strip_footer(&current, &submit::delimiters(&config.submit));
+    let base = retarget
+        .then(|| stack.upstream().to_string())
+        .filter(|base| *base != pr.base.ref_field);
+
+    if body != current || base.is_some() {
+        let update = pulls::Update {
+            body: (body != current).then(|| body.to_string()),
+            base,
+            ..Default::default()
+        };
+        forge
+            .update_pr(number, update)
+            .await
+            .with_context(|| format!("failed to update PR {number}"))?;
+    }
+    stack_comment::remove_all(forge, number).await?;
+
+    // Forget the PR, so dropping the commit doesn't close it and abandoning doesn't delete its
+    // branch. Earlier revisions of the commit keep notes naming it too
+    let mut notes = NoteBatch::default();
+    for note in repo.notes(Some(NOTE_REF)).context("failed to read notes")? {
+        let (_, id) = note.context("failed to read note")?;
+        if Metadata::find(repo, id).is_ok_and(|metadata| metadata.pr == Some(number)) {
+            notes.remove(id);
+        }
+    }
+    notes.write(repo).context("failed to remove notes")?;
+    let mut state = State::load(repo)?;
+    let entry = state.stack_mut(stack.name());
+    entry.prs.retain(|pr| *pr != number);
+    if let Some(branch) = &commit.metadata.branch {
+        entry.branches.retain(|other| other != branch);
+    }
+    state.save().context("failed to save state")?;
+
+    println!(
+        "detached #{number} {}, drop the commit from the stack or the next submit opens a new PR \
+        for it",
+        commit.title
+    );
+    Ok(())
+}
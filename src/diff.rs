@@ -0,0 +1,53 @@
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::{Remote, Repository};
+
+use crate::commit::Commit;
+use crate::stack::Stack;
+use crate::upstream;
+
+/// Show what submitting would change on open PRs, by diffing the head of each PR on `remote`
+/// against its local commit. Either the commit at `index`, or every submitted commit
+pub fn diff(
+    stack: &Stack,
+    index: Option<usize>,
+    repo: &Repository,
+    remote: &mut Remote,
+    proxy: Option<&str>,
+) -> Result<()> {
+    let commits: Vec<&Commit> = match index {
+        Some(index) => vec![stack
+            .get(index)
+            .with_context(|| format!("no commit at index {index}"))?],
+        None => stack.iter().collect(),
+    };
+
+    for commit in commits {
+        let Some(pr) = commit.metadata.pr else {
+            println!("{} has not been submitted yet", commit.title);
+            continue;
+        };
+
+        let head = upstream::fetch_pull(repo, remote, pr, proxy)?;
+        if head == commit.id() {
+            if index.is_some() {
+                println!("#{pr} {} is up to date", commit.title);
+            }
+            continue;
+        }
+
+        println!("#{pr} {}", commit.title);
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo.path())
+            .args(["diff", "--color=auto", "--stat", "--patch"])
+            .arg(head.to_string())
+            .arg(commit.id().to_string())
+            .status()
+            .context("failed to run git diff")?;
+        anyhow::ensure!(status.success(), "git diff failed");
+    }
+
+    Ok(())
+}
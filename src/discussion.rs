@@ -0,0 +1,121 @@
+use anyhow::{Context, Result};
+use octocrab::Octocrab;
+use serde_json::json;
+
+use crate::gh::{self, GHRepo};
+
+/// A GitHub Discussion mirroring a whole stack
+#[derive(Clone, Debug)]
+pub struct Discussion {
+    /// The GraphQL node id
+    pub id: String,
+    pub url: String,
+}
+
+/// Update the body of the discussion `existing`, or create a new discussion in `category`
+/// if there isn't one yet
+pub async fn upsert(
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    existing: Option<&str>,
+    category: &str,
+    title: &str,
+    body: &str,
+) -> Result<Discussion> {
+    let data = match existing {
+        Some(id) => gh::graphql(
+            octocrab,
+            "mutation($id: ID!, $body: String!) {
+                updateDiscussion(input: {discussionId: $id, body: $body}) {
+                    discussion { id url }
+                }
+            }",
+            json!({ "id": id, "body": body }),
+        )
+        .await
+        .context("failed to update discussion")?
+        .get("updateDiscussion")
+        .cloned(),
+        None => {
+            let (repository, category) = find_category(octocrab, gh_repo, category).await?;
+            gh::graphql(
+                octocrab,
+                "mutation($repository: ID!, $category: ID!, $title: String!, $body: String!) {
+                    createDiscussion(input: {
+                        repositoryId: $repository,
+                        categoryId: $category,
+                        title: $title,
+                        body: $body
+                    }) {
+                        discussion { id url }
+                    }
+                }",
+                json!({
+                    "repository": repository,
+                    "category": category,
+                    "title": title,
+                    "body": body,
+                }),
+            )
+            .await
+            .context("failed to create discussion")?
+            .get("createDiscussion")
+            .cloned()
+        }
+    };
+
+    let discussion = data
+        .as_ref()
+        .and_then(|data| data.get("discussion"))
+        .context("missing discussion in response")?;
+
+    Ok(Discussion {
+        id: discussion["id"]
+            .as_str()
+            .context("missing discussion id")?
+            .to_string(),
+        url: discussion["url"]
+            .as_str()
+            .context("missing discussion url")?
+            .to_string(),
+    })
+}
+
+/// Returns the node ids of the repo and its discussion category named `name`
+async fn find_category(
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    name: &str,
+) -> Result<(String, String)> {
+    let data = gh::graphql(
+        octocrab,
+        "query($owner: String!, $name: String!) {
+            repository(owner: $owner, name: $name) {
+                id
+                discussionCategories(first: 100) { nodes { id name } }
+            }
+        }",
+        json!({ "owner": gh_repo.owner, "name": gh_repo.repo }),
+    )
+    .await
+    .context("failed to get discussion categories")?;
+
+    let repository = &data["repository"];
+    let category = repository["discussionCategories"]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .find(|category| category["name"].as_str() == Some(name))
+        .with_context(|| format!("no discussion category named {name}"))?;
+
+    Ok((
+        repository["id"]
+            .as_str()
+            .context("missing repository id")?
+            .to_string(),
+        category["id"]
+            .as_str()
+            .context("missing category id")?
+            .to_string(),
+    ))
+}
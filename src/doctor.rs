@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use git2::Repository;
+
+use crate::color;
+use crate::metadata::NOTE_REF;
+use crate::rescue;
+use crate::stack::Stack;
+
+/// Check that git is set up to carry fel's notes through rewrites, and that no commit in the
+/// stack already lost its notes to a tool that bypasses them. With `fix`, lost notes are
+/// re-attached. Returns true if nothing was wrong
+pub fn doctor(stack: &Stack, repo: &Repository, fix: bool) -> Result<bool> {
+    let mut healthy = true;
+    let mut report = |ok: bool, message: String| {
+        healthy &= ok;
+        match ok {
+            true => println!("{} {message}", color::success().paint("ok:")),
+            false => println!("{} {message}", color::warning().paint("warning:")),
+        }
+    };
+
+    let config = repo.config().context("failed to open git config")?;
+    let mut rewrite_refs = Vec::new();
+    config
+        .entries(Some("notes.rewriteref"))
+        .context("failed to get notes.rewriteRef")?
+        .for_each(|entry| rewrite_refs.extend(entry.value().map(str::to_string)))?;
+    report(
+        rewrite_refs
+            .iter()
+            .any(|rewrite_ref| rewrite_ref == NOTE_REF),
+        format!("notes.rewriteRef includes {NOTE_REF}"),
+    );
+
+    // Both default to true, and turn off note copying for amend or rebase when false
+    for key in ["notes.rewrite.amend", "notes.rewrite.rebase"] {
+        let enabled = config.get_bool(key).unwrap_or(true);
+        report(enabled, format!("{key} is not false"));
+    }
+
+    let lost = rescue::find_lost(stack, repo)?;
+    report(
+        lost.is_empty(),
+        format!(
+            "{} commits in {} lost their notes",
+            lost.len(),
+            stack.name()
+        ),
+    );
+    for lost in &lost {
+        println!("  {}", lost.describe());
+    }
+
+    if !lost.is_empty() {
+        match fix {
+            true => rescue::rescue(stack, repo, true)?,
+            false => println!(
+                "  something rewrote them without copying notes, like an IDE's amend or a \
+                rebase tool that skips git's post-rewrite step. Run `fel doctor --fix` to \
+                re-attach them"
+            ),
+        }
+    }
+
+    Ok(healthy)
+}
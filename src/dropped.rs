@@ -0,0 +1,126 @@
+use std::collections::{BTreeSet, HashSet};
+use std::io::IsTerminal;
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use octocrab::Octocrab;
+
+use crate::color;
+use crate::config::{Api, CloseDropped};
+use crate::events;
+use crate::gh::{self, GHRepo};
+use crate::metadata::{Metadata, NOTE_REF};
+use crate::prompt;
+use crate::pulls::{self, Pulls};
+use crate::stack::Stack;
+use crate::state::State;
+use crate::status;
+
+/// Find open PRs that were submitted in this stack for commits that are no longer in it, and
+/// depending on `mode` close them with a comment saying they were dropped
+pub async fn close_dropped(
+    stack: &Stack,
+    repo: &Repository,
+    mode: CloseDropped,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    api: Api,
+) -> Result<()> {
+    if mode == CloseDropped::Never {
+        return Ok(());
+    }
+
+    // The PRs the stack had at its last submit, and those of every revision of every commit
+    // submitted in it, which keeps its note. Any of them not in the stack now were dropped
+    let current: HashSet<_> = stack
+        .iter()
+        .filter_map(|commit| commit.metadata.pr)
+        .collect();
+    let mut dropped: BTreeSet<_> = State::load(repo)?
+        .stack(stack.name())
+        .map(|state| state.prs.clone())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|pr| !current.contains(pr))
+        .collect();
+    for note in repo.notes(Some(NOTE_REF)).into_iter().flatten() {
+        let (_, id) = note.context("failed to read note")?;
+        let Ok(metadata) = Metadata::find(repo, id) else {
+            continue;
+        };
+        if metadata.stack.as_deref() != Some(stack.name()) {
+            continue;
+        }
+        if let Some(pr) = metadata.pr.filter(|pr| !current.contains(pr)) {
+            dropped.insert(pr);
+        }
+    }
+    if dropped.is_empty() {
+        return Ok(());
+    }
+
+    // Merged and already closed PRs were dealt with
+    let prs: Vec<_> = dropped.into_iter().collect();
+    let open: Vec<_> = status::fetch(octocrab, gh_repo, &prs, api.timeout())
+        .await?
+        .into_iter()
+        .filter(|status| status.state == "OPEN")
+        .map(|status| status.number)
+        .collect();
+    if open.is_empty() {
+        return Ok(());
+    }
+
+    let list = open
+        .iter()
+        .map(|pr| format!("#{pr}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let close = match mode {
+        CloseDropped::Always => true,
+        CloseDropped::Ask if std::io::stdin().is_terminal() => prompt::confirm(&format!(
+            "{list} were dropped from {}, close them?",
+            stack.name()
+        ))?,
+        _ => {
+            let message = format!(
+                "{list} were dropped from {}, set submit.close_dropped = \"always\" to close them",
+                stack.name()
+            );
+            match events::json() {
+                true => tracing::warn!("{message}"),
+                false => eprintln!("{} {message}", color::warning().paint("warning:")),
+            }
+            false
+        }
+    };
+    if !close {
+        return Ok(());
+    }
+
+    let pulls = Pulls::new(octocrab, gh_repo, api);
+    let issues = octocrab.issues(&gh_repo.owner, &gh_repo.repo);
+    for pr in open {
+        let comment = issues.create_comment(
+            pr,
+            format!("The commit for this PR was dropped from {}", stack.name()),
+        );
+        gh::timeout(api.timeout(), &format!("commenting on #{pr}"), comment)
+            .await?
+            .with_context(|| format!("failed to comment on #{pr}"))?;
+        let close = pulls::Update {
+            close: true,
+            ..Default::default()
+        };
+        gh::timeout(
+            api.timeout(),
+            &format!("closing #{pr}"),
+            pulls.update(pr, close),
+        )
+        .await?
+        .with_context(|| format!("failed to close #{pr}"))?;
+        println!("closed #{pr}");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,142 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::Repository;
+
+use crate::config::Email;
+use crate::export;
+use crate::metadata::NoteBatch;
+use crate::stack::Stack;
+
+/// Send the stack as a series of patch emails with `git send-email`, which reads its SMTP
+/// settings from git config. Each submit is a new version of the series, sent as a reply to
+/// the cover letter of the previous version
+pub fn submit(stack: &Stack, repo: &Repository, config: &Email) -> Result<()> {
+    let tip = stack
+        .iter()
+        .last()
+        .context("nothing to submit, the stack is empty")?;
+    anyhow::ensure!(
+        !config.to.is_empty(),
+        "set email.to to the addresses to send patches to"
+    );
+
+    let previous = stack
+        .iter()
+        .filter_map(|commit| {
+            Some((
+                commit.metadata.email_version?,
+                commit.metadata.email_message_id.clone()?,
+            ))
+        })
+        .max_by_key(|(version, _)| *version);
+    let version = previous.as_ref().map_or(1, |(version, _)| version + 1);
+
+    let dir = repo.path().join("fel").join(format!("v{version}"));
+    if dir.exists() {
+        fs::remove_dir_all(&dir).context("failed to clear old patches")?;
+    }
+
+    let mut format_patch = git(repo);
+    format_patch
+        .arg("format-patch")
+        .arg("--cover-letter")
+        .arg("--no-thread")
+        .arg(format!("--reroll-count={version}"))
+        .arg("--output-directory")
+        .arg(&dir);
+    let output = format_patch
+        .arg(format!("{}..{}", stack.merge_base(), tip.id()))
+        .output()
+        .context("failed to run git format-patch")?;
+    anyhow::ensure!(output.status.success(), "git format-patch failed");
+
+    let patches: Vec<PathBuf> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(PathBuf::from)
+        .collect();
+    let (cover, series) = patches
+        .split_first()
+        .context("git format-patch wrote no patches")?;
+    fill_cover_letter(cover, stack)?;
+
+    // Thread every patch under the cover letter, and the cover letter under the last version.
+    // format-patch's ids are only unique to the second, so fel makes its own
+    let signature = repo.signature().context("failed to get signature")?;
+    let domain = signature
+        .email()
+        .and_then(|email| email.split_once('@'))
+        .map_or("fel", |(_, domain)| domain);
+    let message_id = format!("fel.{}.v{version}@{domain}", tip.id());
+    let mut headers = vec![format!("Message-ID: <{message_id}>")];
+    if let Some((_, previous)) = &previous {
+        headers.push(format!("In-Reply-To: <{previous}>"));
+        headers.push(format!("References: <{previous}>"));
+    }
+    add_headers(cover, &headers)?;
+    for patch in series {
+        add_headers(
+            patch,
+            &[
+                format!("In-Reply-To: <{message_id}>"),
+                format!("References: <{message_id}>"),
+            ],
+        )?;
+    }
+
+    let status = git(repo)
+        .arg("send-email")
+        .arg("--no-thread")
+        .arg("--confirm=never")
+        .args(config.to.iter().map(|to| format!("--to={to}")))
+        .args(config.cc.iter().map(|cc| format!("--cc={cc}")))
+        .args(&patches)
+        .status()
+        .context("failed to run git send-email")?;
+    anyhow::ensure!(status.success(), "git send-email failed");
+
+    let mut notes = NoteBatch::default();
+    for commit in stack.iter() {
+        let mut metadata = commit.metadata.clone();
+        metadata.email_version = Some(version);
+        metadata.email_message_id = Some(message_id.clone());
+        metadata.commit = Some(commit.id().to_string());
+        notes.add(&metadata, commit.id())?;
+    }
+    notes.write(repo).context("failed to write metadata")?;
+
+    println!(
+        "sent v{version} of {} ({} patches)",
+        stack.name(),
+        stack.len()
+    );
+    Ok(())
+}
+
+/// Replace the placeholders git leaves in the cover letter with the stack
+fn fill_cover_letter(path: &Path, stack: &Stack) -> Result<()> {
+    let contents = fs::read_to_string(path).context("failed to read cover letter")?;
+    let contents = contents
+        .replace("*** SUBJECT HERE ***", stack.name())
+        .replace("*** BLURB HERE ***", &export::cover_letter(stack)?);
+    fs::write(path, contents).context("failed to write cover letter")
+}
+
+/// Add `headers` to the patch email at `path`, after its mbox `From ` line
+fn add_headers(path: &Path, headers: &[String]) -> Result<()> {
+    let contents =
+        fs::read_to_string(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let (from, rest) = contents
+        .split_once('\n')
+        .with_context(|| format!("{} is not a patch", path.display()))?;
+    let contents = format!("{from}\n{}\n{rest}", headers.join("\n"));
+    fs::write(path, contents).with_context(|| format!("failed to write {}", path.display()))
+}
+
+fn git(repo: &Repository) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo.path());
+    command
+}
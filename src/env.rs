@@ -0,0 +1,127 @@
+use anyhow::{Context, Result};
+use git2::{BranchType, Repository};
+
+use crate::auth;
+use crate::config::Config;
+use crate::gh;
+use crate::policy::{Policy, POLICY_FILE};
+
+/// Print the effective config and what fel detected about the repo, to help diagnose
+/// why fel behaves differently on one machine
+pub fn env(repo: &Repository, config: &Config, policy: Option<&Policy>) -> Result<()> {
+    println!("config:   {}", Config::path()?.display());
+    match policy {
+        Some(policy) => {
+            let overrides = policy.overrides();
+            println!(
+                "policy:   {POLICY_FILE} (overrides {})",
+                match overrides.is_empty() {
+                    true => "nothing".to_string(),
+                    false => overrides.join(", "),
+                }
+            );
+        }
+        None => println!("policy:   none"),
+    }
+    println!("repo:     {}", repo.path().display());
+
+    let remote = repo
+        .find_remote(&config.default_remote)
+        .with_context(|| format!("failed to get remote {}", config.default_remote))?;
+    let url = remote.url().unwrap_or("<invalid utf-8>");
+    println!("remote:   {} ({url})", config.default_remote);
+
+    let mut api = config.api_url("github.com");
+    match gh::get_repo(&remote) {
+        Ok(gh_repo) => {
+            api = config.api_url(&gh_repo.host);
+            println!(
+                "github:   {}/{} on {}",
+                gh_repo.owner, gh_repo.repo, gh_repo.host
+            );
+
+            let names = repo.remotes().context("failed to list remotes")?;
+            for name in names.iter().flatten() {
+                if name == config.default_remote {
+                    continue;
+                }
+                let other = repo.find_remote(name).context("failed to get remote")?;
+                if matches!(gh::get_repo(&other), Ok(other) if other.same_repo(&gh_repo)) {
+                    println!(
+                        "          also pushable through {name} ({})",
+                        other.url().unwrap_or("<invalid utf-8>")
+                    );
+                }
+            }
+        }
+        Err(e) => println!("github:   not detected, {e:#}"),
+    }
+    println!("api:      {api}");
+
+    let upstream = format!("{}/{}", config.default_remote, config.default_upstream);
+    match repo.find_branch(&upstream, BranchType::Remote) {
+        Ok(branch) => {
+            let commit = branch
+                .get()
+                .peel_to_commit()
+                .context("failed to get commit")?;
+            println!("upstream: {upstream} at {}", commit.id());
+        }
+        Err(_) => println!("upstream: {upstream} (not found, try fetching)"),
+    }
+
+    println!("token:    {} (from config)", mask(&config.token));
+    if let Some(read_token) = &config.read_token {
+        println!("read:     {} (from config)", mask(read_token));
+    }
+    let auth = match url.starts_with("http") {
+        true => "git credential helper".to_string(),
+        false => match auth::ssh_key() {
+            Some(key) => format!("ssh key {}", key.display()),
+            None => "ssh agent".to_string(),
+        },
+    };
+    println!("auth:     {auth}");
+
+    let mut effective = config.clone();
+    effective.token = mask(&config.token);
+    effective.read_token = config.read_token.as_deref().map(mask);
+    for host in effective.hosts.values_mut() {
+        host.token = host.token.as_deref().map(mask);
+        host.read_token = host.read_token.as_deref().map(mask);
+    }
+    println!();
+    println!("# effective config");
+    print!(
+        "{}",
+        toml::to_string(&effective).context("failed to serialize config")?
+    );
+
+    Ok(())
+}
+
+/// Hide all but the last few characters of a secret
+fn mask(secret: &str) -> String {
+    let chars: Vec<char> = secret.chars().collect();
+    match chars.len() {
+        0 => "<empty>".to_string(),
+        len if len <= 8 => "*".repeat(len),
+        len => {
+            let shown: String = chars[len - 4..].iter().collect();
+            format!("{}{shown}", "*".repeat(len - 4))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn masks_by_character() {
+        assert_eq!(mask(""), "<empty>");
+        assert_eq!(mask("ghp_abc"), "*******");
+        assert_eq!(mask("ghp_1234abcd"), "********abcd");
+        assert_eq!(mask("ghp_1234abcdé"), "*********bcdé");
+    }
+}
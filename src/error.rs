@@ -0,0 +1,164 @@
+use std::path::PathBuf;
+
+use crate::forge::{ForgeError, ForgeErrorKind};
+use crate::push::PushError;
+
+/// Failures that scripts around fel can tell apart by exit code. Anything else exits with 1
+#[derive(thiserror::Error, Debug, Clone)]
+pub enum FelError {
+    #[error("GitHub didn't accept the token: {0}")]
+    Auth(String),
+
+    #[error("no config file at {}", .0.display())]
+    MissingConfig(PathBuf),
+
+    #[error("GitHub rate limit exceeded: {0}")]
+    RateLimited(String),
+
+    #[error("push rejected: {0}")]
+    PushRejected(String),
+
+    #[error("GitHub rejected the request: {0}")]
+    Validation(String),
+
+    #[error("stopped on a conflict: {0}")]
+    Conflict(String),
+
+    #[error("timed out {0}")]
+    Timeout(String),
+}
+
+impl FelError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Auth(_) => 3,
+            Self::MissingConfig(_) => 4,
+            Self::RateLimited(_) => 5,
+            Self::PushRejected(_) => 6,
+            Self::Validation(_) => 7,
+            Self::Conflict(_) => 8,
+            Self::Timeout(_) => 9,
+        }
+    }
+
+    /// What the user can do about it
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::Auth(_) => {
+                "check that `token` in fel's config (see `fel env`) hasn't expired and has access \
+                to the repo"
+            }
+            Self::MissingConfig(_) => {
+                "create it with at least `token`, `default_remote`, `default_upstream` and a \
+                `[submit]` table, see the readme"
+            }
+            Self::RateLimited(_) => {
+                "wait for the limit to reset, or set `read_token` so commands that only read \
+                use a separate limit"
+            }
+            Self::PushRejected(_) => {
+                "the branch moved on the remote or is protected, fetch and check it before \
+                submitting again"
+            }
+            Self::Validation(_) => "GitHub refused the request as it was made, see the reason",
+            Self::Conflict(_) => {
+                "resolve the conflicts and `git add` them, then run `fel continue`, or run \
+                `fel abort` to put the stack back how it was"
+            }
+            Self::Timeout(_) => {
+                "GitHub didn't answer in time, check your connection and proxy, or raise \
+                `api.timeout` or `submit.deadline`"
+            }
+        }
+    }
+
+    /// Find the failure behind `error`, from a `FelError` somewhere in its chain or from the
+    /// library error that caused it
+    pub fn classify(error: &anyhow::Error) -> Option<Self> {
+        for cause in error.chain().rev() {
+            if let Some(error) = cause.downcast_ref::<FelError>() {
+                return Some(error.clone());
+            }
+            if let Some(error) = cause.downcast_ref::<crate::Error>() {
+                return Self::from_error(error);
+            }
+            if let Some(error) = crate::Error::typed(cause) {
+                return Self::from_error(&error);
+            }
+        }
+
+        None
+    }
+
+    /// The failure behind a library error, if scripts can tell it apart
+    pub fn from_error(error: &crate::Error) -> Option<Self> {
+        match error {
+            crate::Error::Push {
+                error: PushError::Rejected(reason),
+                ..
+            } => Some(Self::PushRejected(reason.clone())),
+            crate::Error::Timeout { doing, .. } => Some(Self::Timeout(doing.clone())),
+            crate::Error::Forge { error, .. } => Self::from_forge(error),
+            crate::Error::Git { .. } => None,
+            crate::Error::Other(error) => Self::classify(error),
+        }
+    }
+
+    fn from_forge(error: &ForgeError) -> Option<Self> {
+        let reason = match error.reasons.is_empty() {
+            true => error.message.clone(),
+            false => error.reasons.join(", "),
+        };
+        match error.kind {
+            ForgeErrorKind::Auth => Some(Self::Auth(error.message.clone())),
+            ForgeErrorKind::RateLimited => Some(Self::RateLimited(reason)),
+            ForgeErrorKind::Validation => Some(Self::Validation(reason)),
+            ForgeErrorKind::NotFound | ForgeErrorKind::Unreachable | ForgeErrorKind::Other => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+
+    #[test]
+    fn finds_errors_behind_library_errors() {
+        let push: anyhow::Result<()> = Err(PushError::Rejected("non-fast-forward".into()).into());
+        let error: crate::Error = push.context("push branch").unwrap_err().into();
+        assert!(matches!(&error, crate::Error::Push { context, .. } if context == "push branch"));
+        let error = anyhow::Error::new(error).context("failed to submit");
+
+        let fel = FelError::classify(&error).unwrap();
+        assert!(matches!(fel, FelError::PushRejected(_)));
+        assert_eq!(fel.exit_code(), 6);
+
+        // Context added around a library error is kept when it's typed again
+        assert_eq!(
+            crate::Error::from(error.context("run")).to_string(),
+            "run: failed to submit: push branch: push rejected by remote: non-fast-forward"
+        );
+
+        let config: crate::Error = anyhow::Error::new(FelError::MissingConfig("x".into())).into();
+        let error = anyhow::Error::new(config).context("failed to load config");
+        assert!(matches!(
+            FelError::classify(&error),
+            Some(FelError::MissingConfig(_))
+        ));
+    }
+
+    #[test]
+    fn forge_errors() {
+        let auth = FelError::from_forge(&ForgeError::new("Bad credentials", vec![]));
+        assert!(matches!(auth, Some(FelError::Auth(_))));
+
+        let reasons = vec!["No commits between main and fel/x".to_string()];
+        let validation = FelError::from_forge(&ForgeError::new("Validation Failed", reasons));
+        assert!(
+            matches!(validation, Some(FelError::Validation(reason)) if reason.contains("No commits"))
+        );
+
+        assert!(FelError::from_forge(&ForgeError::new("Not Found", vec![])).is_none());
+    }
+}
@@ -0,0 +1,188 @@
+use std::env;
+use std::fmt::Debug;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{Context as _, Result};
+
+use serde_json::{json, Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::filter::{LevelFilter, Targets};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::Layer;
+
+use crate::color;
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable logs and progress bars
+    #[default]
+    Text,
+    /// One JSON object per line on stderr, for logs and progress
+    Json,
+}
+
+static FORMAT: OnceLock<LogFormat> = OnceLock::new();
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Start logging in `format`. With `Json`, logs are written to stderr as JSON lines, and
+/// progress is reported as events on the same stream instead of drawn as progress bars.
+/// `verbose` and `quiet` pick which logs are shown, see [`filter`]. With a `log_file`, every
+/// log is also appended to it, whatever the level
+pub fn init(format: LogFormat, verbose: u8, quiet: bool, log_file: Option<&Path>) -> Result<()> {
+    FORMAT.set(format).ok();
+    QUIET.set(quiet).ok();
+    let filter = filter(verbose, quiet);
+    let terminal = match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer()
+            .with_ansi(color::enabled())
+            .with_filter(filter)
+            .boxed(),
+        LogFormat::Json => JsonLayer.with_filter(filter).boxed(),
+    };
+
+    let file = match log_file {
+        Some(path) => {
+            if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+                fs::create_dir_all(dir)
+                    .with_context(|| format!("failed to create {}", dir.display()))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("failed to open log file {}", path.display()))?;
+            // Tracing every library would bury fel's logs under HTTP internals
+            let targets = Targets::new()
+                .with_default(LevelFilter::DEBUG)
+                .with_target("fel", LevelFilter::TRACE)
+                .with_target("fel_core", LevelFilter::TRACE);
+            let layer = tracing_subscriber::fmt::layer()
+                .with_ansi(false)
+                .with_writer(Mutex::new(file))
+                .with_filter(targets);
+            Some(layer)
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(terminal)
+        .with(file)
+        .init();
+    tracing::debug!(args = ?env::args().collect::<Vec<_>>(), "started");
+    Ok(())
+}
+
+/// Warnings are logged by default. Each `-v` logs another level of fel's own logs, up to debug,
+/// and `-vvv` traces everything, including the libraries fel uses. `-q` only logs errors.
+/// Without either, `RUST_LOG` can set the levels per target, like `fel_core::push=trace`
+fn filter(verbose: u8, quiet: bool) -> Targets {
+    if verbose == 0 && !quiet {
+        if let Ok(directives) = env::var("RUST_LOG") {
+            match directives.parse() {
+                Ok(targets) => return targets,
+                Err(error) => eprintln!("ignoring invalid RUST_LOG: {error}"),
+            }
+        }
+    }
+
+    let level = match (quiet, verbose) {
+        (true, _) => LevelFilter::ERROR,
+        (false, 0) => LevelFilter::WARN,
+        (false, 1) => LevelFilter::INFO,
+        (false, 2) => LevelFilter::DEBUG,
+        (false, _) => return Targets::new().with_default(LevelFilter::TRACE),
+    };
+    Targets::new()
+        .with_default(level.min(LevelFilter::WARN))
+        .with_target("fel", level)
+        .with_target("fel_core", level)
+}
+
+/// Returns true if progress should be reported as JSON events
+pub fn json() -> bool {
+    FORMAT.get() == Some(&LogFormat::Json)
+}
+
+/// Returns true if progress bars should be drawn, which they aren't with JSON logs or `-q`
+pub fn draw_progress() -> bool {
+    !json() && QUIET.get() != Some(&true)
+}
+
+/// Report a step of a long running command, so editors and wrappers can show their own
+/// progress. `phase` is the step of the whole command, like `push` or `prs`, or `commit` for a
+/// step of a single commit
+pub fn progress(phase: &str, commit: Option<&str>, pr: Option<u64>, message: &str) {
+    if json() {
+        write_line(json!({
+            "type": "progress",
+            "phase": phase,
+            "commit": commit,
+            "pr": pr,
+            "message": message,
+        }));
+    }
+}
+
+/// Report the error a command failed with, and the code fel exits with because of it
+pub fn error(error: &anyhow::Error, exit_code: i32) {
+    let causes: Vec<_> = error.chain().map(ToString::to_string).collect();
+    write_line(json!({
+        "type": "error",
+        "message": error.to_string(),
+        "causes": &causes[1..],
+        "exit_code": exit_code,
+    }));
+}
+
+fn write_line(value: Value) {
+    let mut stderr = std::io::stderr().lock();
+    serde_json::to_writer(&mut stderr, &value).ok();
+    writeln!(stderr).ok();
+}
+
+/// Writes every tracing event as a JSON line
+struct JsonLayer;
+
+impl<S: Subscriber> Layer<S> for JsonLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let mut fields = Map::new();
+        event.record(&mut Fields(&mut fields));
+        write_line(json!({
+            "type": "log",
+            "level": event.metadata().level().as_str(),
+            "target": event.metadata().target(),
+            "fields": fields,
+        }));
+    }
+}
+
+struct Fields<'a>(&'a mut Map<String, Value>);
+
+impl Visit for Fields<'_> {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0
+            .insert(field.name().to_string(), json!(format!("{value:?}")));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), json!(value));
+    }
+}
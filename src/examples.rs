@@ -0,0 +1,179 @@
+use anyhow::Result;
+
+use fel_core::config::Config;
+use fel_core::land;
+use fel_core::stack::Stack;
+use fel_core::submit::{self, PlannedPr};
+
+pub const SUBMIT: &str = "Examples:
+  fel submit                      Push every commit in the stack and open or update its PR
+  fel submit --force              Push new revisions even if their PRs were already reviewed
+  fel submit --profile            Show how long each phase of the submit and each PR took
+  fel submit --base 0=release-1.2 Make the bottom PR against release-1.2 instead of upstream";
+
+pub const EXPORT: &str = "Examples:
+  fel export > stack.mbox       Write the stack as an mbox
+  fel export -o patches         Write one patch per commit, and a cover letter, into patches/";
+
+pub const LAND: &str = "Examples:
+  fel status --exit-code && fel land    Merge the bottom PR once everything is green
+  fel land --up-to 2                    Merge the bottom three PRs and restack the rest";
+
+pub const CHECK: &str = "Examples:
+  fel check              Check that each PR is stacked on the one below it
+  fel check --publish    Also post the result on each PR, from a GitHub Actions job";
+
+pub const STATUS: &str = "Examples:
+  fel status                Show whether each PR is approved, green and mergeable
+  fel status --exit-code    Fail unless every PR is ready to land";
+
+pub const RANGE_DIFF: &str = "Examples:
+  fel range-diff              Show how every changed commit differs from its last submit
+  fel range-diff 0 --comment  Post the range-diff of the bottom commit on its PR";
+
+pub const WEB: &str = "Examples:
+  fel web          Open the PR at the top of the stack
+  fel web 0        Open the PR at the bottom of the stack
+  fel web '#123'   Open PR 123
+  fel web --all    Open every PR in the stack";
+
+pub const READY: &str = "Examples:
+  fel ready                          Mark the PR at the top of the stack ready for review
+  fel ready --all --request-reviews  Mark the whole stack ready and ask for reviews";
+
+/// Commands with examples that use the current stack
+const COMMANDS: &[&str] = &[
+    "submit",
+    "export",
+    "land",
+    "status",
+    "range-diff",
+    "web",
+    "ready",
+];
+
+/// Print worked examples of `command`, or of every command, using the current stack. Submit
+/// and land show what they would do, planned the same way they plan a real run. Nothing is run
+pub fn examples(stack: &Stack, config: &Config, command: Option<&str>) -> Result<()> {
+    let commands = match command {
+        Some(command) if COMMANDS.contains(&command) => vec![command],
+        Some(command) => anyhow::bail!(
+            "no examples for {command}, try one of {}",
+            COMMANDS.join(", ")
+        ),
+        None => COMMANDS.to_vec(),
+    };
+
+    let upstream = format!("{}/{}", config.default_remote, stack.upstream());
+    if stack.is_empty() {
+        println!(
+            "{} has no commits on top of {upstream}, so there is nothing to show yet",
+            stack.name()
+        );
+        return Ok(());
+    }
+
+    let submitted = stack
+        .iter()
+        .filter(|commit| commit.metadata.pr.is_some())
+        .count();
+    let changed = stack
+        .iter()
+        .filter(|commit| commit.metadata.pr.is_some() && commit.changed())
+        .count();
+    let describe = |index: usize| {
+        let commit = stack.get(index).expect("index in stack");
+        match commit.metadata.pr {
+            Some(pr) => format!("#{pr} {}", commit.title),
+            None => format!("{} (not submitted yet)", commit.title),
+        }
+    };
+    let top = stack.len() - 1;
+
+    for command in commands {
+        let lines = match command {
+            "submit" => {
+                let mut explanation = format!(
+                    "pushes {} commits to {}, opening {} new PRs and updating {} changed ones:",
+                    stack.len(),
+                    config.default_remote,
+                    stack.len() - submitted,
+                    changed
+                );
+                for planned in submit::plan(stack, config) {
+                    let pr = match planned.pr {
+                        PlannedPr::Create => "opens a PR".to_string(),
+                        PlannedPr::Replace(pr) => format!("opens a PR replacing #{pr}"),
+                        PlannedPr::Update(pr) => format!("updates #{pr}"),
+                        PlannedPr::UpToDate(pr) => format!("leaves #{pr} as it is"),
+                    };
+                    let push = match planned.force_push {
+                        true => "force pushes",
+                        false => "pushes",
+                    };
+                    let revision = planned
+                        .revision_branch
+                        .map(|branch| format!(", keeping a copy in {branch},"))
+                        .unwrap_or_default();
+                    explanation.push_str(&format!(
+                        "\n      {}: {push} {}{revision} and {pr} against {}",
+                        planned.title, planned.branch, planned.base
+                    ));
+                }
+                vec![("fel submit".to_string(), explanation)]
+            }
+            "export" => vec![(
+                "fel export -o patches".to_string(),
+                format!(
+                    "writes {} patches and a cover letter for {} into patches/",
+                    stack.len(),
+                    stack.name()
+                ),
+            )],
+            "land" => {
+                let method = match config.land.merge_method {
+                    Some(method) => format!("{method:?}").to_lowercase(),
+                    None => "the first method the repo allows".to_string(),
+                };
+                let explanation = match land::plan(stack, 0) {
+                    Ok(landings) => landings
+                        .iter()
+                        .map(|landing| {
+                            format!(
+                                "merges #{} {} into {} with {method}",
+                                landing.number, landing.title, landing.base
+                            )
+                        })
+                        .collect::<Vec<_>>()
+                        .join("\n    "),
+                    Err(error) => format!("fails, {error}"),
+                };
+                vec![("fel land".to_string(), explanation)]
+            }
+            "status" => vec![(
+                "fel status --exit-code".to_string(),
+                format!("checks the {submitted} submitted PRs and fails unless they can all land"),
+            )],
+            "range-diff" => vec![(
+                "fel range-diff".to_string(),
+                format!("shows how the {changed} changed commits differ from their last submit"),
+            )],
+            "web" => vec![
+                ("fel web".to_string(), format!("opens {}", describe(top))),
+                ("fel web 0".to_string(), format!("opens {}", describe(0))),
+            ],
+            "ready" => vec![(
+                "fel ready".to_string(),
+                format!("marks {} ready for review", describe(top)),
+            )],
+            _ => unreachable!("checked against COMMANDS"),
+        };
+
+        for (example, explanation) in lines {
+            println!("$ {example}");
+            println!("    {explanation}");
+        }
+    }
+
+    Ok(())
+}
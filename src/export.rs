@@ -0,0 +1,104 @@
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use serde_json::json;
+
+use crate::render;
+use crate::stack::Stack;
+
+/// Write the stack as a patch series with a cover letter, either as files in `output` or as
+/// an mbox on stdout
+pub fn export(stack: &Stack, repo: &Repository, output: Option<&Path>) -> Result<()> {
+    let tip = stack.iter().last().context("stack is empty")?;
+    let cover_letter = mbox_message(stack, repo, &cover_letter(stack)?)?;
+
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo.path())
+        .arg("format-patch")
+        .arg("--numbered")
+        .arg(format!("{}..{}", stack.merge_base(), tip.id()));
+
+    match output {
+        Some(dir) => {
+            // git runs in the repo, so a relative directory has to be made absolute for the
+            // patches and the cover letter to end up together
+            let dir = &std::path::absolute(dir)
+                .with_context(|| format!("failed to resolve {}", dir.display()))?;
+            let status = command
+                .arg("--output-directory")
+                .arg(dir)
+                .status()
+                .context("failed to run git format-patch")?;
+            anyhow::ensure!(status.success(), "git format-patch failed");
+
+            let path = dir.join("0000-cover-letter.patch");
+            fs::write(&path, &cover_letter)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        None => {
+            let output = command
+                .arg("--stdout")
+                .output()
+                .context("failed to run git format-patch")?;
+            anyhow::ensure!(output.status.success(), "git format-patch failed");
+
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(cover_letter.as_bytes())?;
+            stdout.write_all(&output.stdout)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Render the body of the cover letter, listing every commit in the stack from the top down
+pub fn cover_letter(stack: &Stack) -> Result<String> {
+    let prs: Vec<_> = stack
+        .iter()
+        .enumerate()
+        .rev()
+        .map(|(index, commit)| {
+            json!({
+                "number": commit.metadata.pr,
+                "url": commit.metadata.pr_url,
+                "title": commit.title,
+                "commit": commit.id().to_string(),
+                "author": commit.author,
+                "index": index,
+            })
+        })
+        .collect();
+
+    let tera = render::tera(
+        "cover_letter.txt",
+        include_str!("../templates/cover_letter.txt"),
+    )?;
+    let mut context = tera::Context::new();
+    context.insert("stack_size", &stack.len());
+    context.insert("prs", &prs);
+    context.insert("stack_name", stack.name());
+    context.insert("upstream", stack.upstream());
+    tera.render("cover_letter.txt", &context)
+        .context("render cover letter")
+}
+
+/// Wrap the cover letter in the same mbox headers git format-patch uses
+fn mbox_message(stack: &Stack, repo: &Repository, body: &str) -> Result<String> {
+    let author = repo
+        .signature()
+        .context("failed to get signature")?
+        .to_string();
+    Ok(format!(
+        "From 0000000000000000000000000000000000000000 Mon Sep 17 00:00:00 2001\n\
+        From: {author}\n\
+        Subject: [PATCH 0/{}] {}\n\n{body}\n",
+        stack.len(),
+        stack.name()
+    ))
+}
@@ -0,0 +1,555 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::Context;
+use async_trait::async_trait;
+use git2::Repository;
+use octocrab::models::pulls::{PullRequest, Review};
+use octocrab::models::IssueState;
+use octocrab::Octocrab;
+use parking_lot::Mutex;
+use serde_json::json;
+
+use crate::cache::PrCache;
+use crate::config::Api;
+use crate::gh::{self, GHRepo};
+use crate::protected;
+use crate::pulls::{Pulls, Update};
+use crate::reviewers;
+use crate::Result;
+
+/// Where PRs for a stack are opened. Submitting only needs to create, find, update, read,
+/// comment on, label and request reviews on PRs, so anything that hosts PRs between branches
+/// of the pushed remote can implement this
+#[async_trait]
+pub trait Forge: Send + Sync {
+    /// Get PR `number`
+    async fn get_pr(&self, number: u64) -> Result<PullRequest>;
+
+    /// Open a PR merging `head`, which is `owner:branch` for a fork, into `base`
+    async fn create_pr(
+        &self,
+        title: &str,
+        head: &str,
+        base: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest>;
+
+    /// Change the parts of PR `number` that are set in `update`
+    async fn update_pr(&self, number: u64, update: Update) -> Result<()>;
+
+    /// List the open PRs from `branch` in `owner`'s repo
+    async fn list_open_prs(&self, owner: &str, branch: &str) -> Result<Vec<PullRequest>>;
+
+    /// List the reviews of PR `number`
+    async fn list_reviews(&self, number: u64) -> Result<Vec<Review>>;
+
+    /// Comment `body` on PR `number`, returning the id of the comment
+    async fn comment(&self, number: u64, body: &str) -> Result<u64>;
+
+    /// List the comments on PR `number`, oldest first
+    async fn list_comments(&self, number: u64) -> Result<Vec<Comment>>;
+
+    /// Replace the body of comment `id` with `body`
+    async fn edit_comment(&self, id: u64, body: &str) -> Result<()>;
+
+    /// Delete comment `id`
+    async fn delete_comment(&self, id: u64) -> Result<()>;
+
+    /// Add `labels` to PR `number`
+    async fn add_labels(&self, number: u64, labels: &[String]) -> Result<()>;
+
+    /// Ask `users` and `teams`, by slug, to review PR `number`
+    async fn request_reviews(&self, number: u64, users: &[String], teams: &[String]) -> Result<()>;
+
+    /// The login of the user the forge is used as
+    async fn current_user(&self) -> Result<String>;
+
+    /// Patterns of the branches the repo protects, or none when they can't be read
+    async fn protected_branches(&self) -> Vec<String>;
+}
+
+/// A comment on a PR
+#[derive(Clone, Debug)]
+pub struct Comment {
+    pub id: u64,
+    pub body: String,
+}
+
+/// Why the forge refused a request, or that it couldn't be reached
+#[derive(thiserror::Error, Debug, Clone)]
+#[error("{message}{}", match .reasons.is_empty() {
+    true => String::new(),
+    false => format!(": {}", .reasons.join(", ")),
+})]
+pub struct ForgeError {
+    pub kind: ForgeErrorKind,
+    /// What the forge said, or why it couldn't be asked
+    pub message: String,
+    /// The forge's reasons for refusing, like the fields that failed validation
+    pub reasons: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForgeErrorKind {
+    /// The token was refused, or can't access the repo
+    Auth,
+    RateLimited,
+    NotFound,
+    /// The request was understood but refused as it was made
+    Validation,
+    /// No answer came back, or it couldn't be read
+    Unreachable,
+    Other,
+}
+
+impl ForgeError {
+    pub fn new(message: impl Into<String>, reasons: Vec<String>) -> Self {
+        let message = message.into();
+        let lower = message.to_lowercase();
+        let kind = if lower.contains("rate limit") {
+            ForgeErrorKind::RateLimited
+        } else if lower.contains("bad credentials")
+            || lower.contains("requires authentication")
+            || lower.contains("resource not accessible")
+        {
+            ForgeErrorKind::Auth
+        } else if lower.contains("not found") {
+            ForgeErrorKind::NotFound
+        } else if lower.contains("validation failed") {
+            ForgeErrorKind::Validation
+        } else {
+            ForgeErrorKind::Other
+        };
+        Self {
+            kind,
+            message,
+            reasons,
+        }
+    }
+
+    /// Build from the `errors` of a GraphQL response, which say what kind they are
+    pub fn from_graphql(errors: &[serde_json::Value]) -> Self {
+        let messages: Vec<_> = errors
+            .iter()
+            .filter_map(|error| error["message"].as_str())
+            .map(str::to_string)
+            .collect();
+        let kind = match errors.iter().find_map(|error| error["type"].as_str()) {
+            Some("RATE_LIMITED") => ForgeErrorKind::RateLimited,
+            Some("FORBIDDEN") => ForgeErrorKind::Auth,
+            Some("NOT_FOUND") => ForgeErrorKind::NotFound,
+            Some("UNPROCESSABLE") => ForgeErrorKind::Validation,
+            _ => ForgeError::new(messages.join(", "), Vec::new()).kind,
+        };
+        Self {
+            kind,
+            message: "graphql query failed".to_string(),
+            reasons: messages,
+        }
+    }
+}
+
+impl From<&octocrab::Error> for ForgeError {
+    fn from(error: &octocrab::Error) -> Self {
+        match error {
+            octocrab::Error::GitHub { source, .. } => {
+                let reasons = source
+                    .errors
+                    .iter()
+                    .flatten()
+                    .filter_map(|error| error["message"].as_str())
+                    .map(str::to_string)
+                    .collect();
+                ForgeError::new(&source.message, reasons)
+            }
+            octocrab::Error::Hyper { .. }
+            | octocrab::Error::Http { .. }
+            | octocrab::Error::Service { .. } => Self {
+                kind: ForgeErrorKind::Unreachable,
+                message: error.to_string(),
+                reasons: Vec::new(),
+            },
+            _ => Self {
+                kind: ForgeErrorKind::Other,
+                message: error.to_string(),
+                reasons: Vec::new(),
+            },
+        }
+    }
+}
+
+/// A repo on GitHub or GitHub Enterprise
+pub struct GitHub {
+    octocrab: Arc<Octocrab>,
+    gh_repo: GHRepo,
+    api: Api,
+    cache: PrCache,
+}
+
+impl GitHub {
+    pub fn new(octocrab: Arc<Octocrab>, gh_repo: GHRepo, api: Api) -> Self {
+        Self {
+            octocrab,
+            gh_repo,
+            api,
+            cache: PrCache::default(),
+        }
+    }
+
+    /// Cache fetched PRs in `repo`, and only download them again when they change
+    pub fn cached(mut self, repo: &Repository) -> Self {
+        self.cache = PrCache::new(repo, &self.gh_repo);
+        self
+    }
+
+    fn pulls(&self) -> Pulls<'_> {
+        Pulls::new(&self.octocrab, &self.gh_repo, self.api)
+    }
+
+    /// Wait for `request` for at most `api.timeout`
+    async fn timed<T>(
+        &self,
+        doing: &str,
+        request: impl Future<Output = anyhow::Result<T>>,
+    ) -> Result<T> {
+        Ok(gh::timeout(self.api.timeout(), doing, request).await??)
+    }
+}
+
+#[async_trait]
+impl Forge for GitHub {
+    async fn get_pr(&self, number: u64) -> Result<PullRequest> {
+        self.timed(
+            &format!("getting PR {number}"),
+            self.cache.get(&self.octocrab, &self.gh_repo, number),
+        )
+        .await
+    }
+
+    async fn create_pr(
+        &self,
+        title: &str,
+        head: &str,
+        base: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest> {
+        self.timed(
+            &format!("creating a PR for {head}"),
+            self.pulls().create(title, head, base, body, draft),
+        )
+        .await
+    }
+
+    async fn update_pr(&self, number: u64, update: Update) -> Result<()> {
+        self.timed(
+            &format!("updating PR {number}"),
+            self.pulls().update(number, update),
+        )
+        .await
+    }
+
+    async fn list_open_prs(&self, owner: &str, branch: &str) -> Result<Vec<PullRequest>> {
+        self.timed(
+            &format!("listing PRs for {owner}:{branch}"),
+            self.pulls().list_open(owner, branch),
+        )
+        .await
+    }
+
+    async fn list_reviews(&self, number: u64) -> Result<Vec<Review>> {
+        let reviews = async {
+            let page = self
+                .octocrab
+                .pulls(&self.gh_repo.owner, &self.gh_repo.repo)
+                .list_reviews(number)
+                .per_page(100)
+                .send()
+                .await?;
+            Ok(self.octocrab.all_pages(page).await?)
+        };
+        self.timed(&format!("listing reviews of PR {number}"), reviews)
+            .await
+    }
+
+    async fn comment(&self, number: u64, body: &str) -> Result<u64> {
+        let comment = async {
+            let comment = self
+                .octocrab
+                .issues(&self.gh_repo.owner, &self.gh_repo.repo)
+                .create_comment(number, body)
+                .await?;
+            Ok(comment.id.0)
+        };
+        self.timed(&format!("commenting on PR {number}"), comment)
+            .await
+    }
+
+    async fn list_comments(&self, number: u64) -> Result<Vec<Comment>> {
+        let list = async {
+            let page = self
+                .octocrab
+                .issues(&self.gh_repo.owner, &self.gh_repo.repo)
+                .list_comments(number)
+                .per_page(100)
+                .send()
+                .await?;
+            let comments = self.octocrab.all_pages(page).await?;
+            Ok(comments
+                .into_iter()
+                .map(|comment| Comment {
+                    id: comment.id.0,
+                    body: comment.body.unwrap_or_default(),
+                })
+                .collect())
+        };
+        self.timed(&format!("listing comments on PR {number}"), list)
+            .await
+    }
+
+    async fn edit_comment(&self, id: u64, body: &str) -> Result<()> {
+        let edit = async {
+            // octocrab edits comments with POST, which GitHub doesn't accept
+            let route = format!(
+                "/repos/{}/{}/issues/comments/{id}",
+                self.gh_repo.owner, self.gh_repo.repo
+            );
+            let _: serde_json::Value = self
+                .octocrab
+                .patch(route, Some(&json!({ "body": body })))
+                .await?;
+            Ok(())
+        };
+        self.timed(&format!("editing comment {id}"), edit).await
+    }
+
+    async fn delete_comment(&self, id: u64) -> Result<()> {
+        let delete = async {
+            self.octocrab
+                .issues(&self.gh_repo.owner, &self.gh_repo.repo)
+                .delete_comment(octocrab::models::CommentId(id))
+                .await?;
+            Ok(())
+        };
+        self.timed(&format!("deleting comment {id}"), delete).await
+    }
+
+    async fn add_labels(&self, number: u64, labels: &[String]) -> Result<()> {
+        let add = async {
+            self.octocrab
+                .issues(&self.gh_repo.owner, &self.gh_repo.repo)
+                .add_labels(number, labels)
+                .await?;
+            Ok(())
+        };
+        self.timed(&format!("adding labels to PR {number}"), add)
+            .await
+    }
+
+    async fn request_reviews(&self, number: u64, users: &[String], teams: &[String]) -> Result<()> {
+        self.timed(
+            &format!("requesting reviews on PR {number}"),
+            reviewers::request(&self.octocrab, &self.gh_repo, number, users, teams),
+        )
+        .await
+    }
+
+    async fn current_user(&self) -> Result<String> {
+        let user = async { Ok(self.octocrab.current().user().await?.login) };
+        self.timed("getting the authenticated user", user).await
+    }
+
+    async fn protected_branches(&self) -> Vec<String> {
+        protected::rules(&self.octocrab, &self.gh_repo).await
+    }
+}
+
+/// A forge that keeps PRs in memory, for benchmarks and tests. Every request waits `latency`
+/// first, like a round trip to a real forge would
+#[derive(Default)]
+pub struct MockForge {
+    prs: Mutex<Vec<PullRequest>>,
+    /// Comments and the PR each is on
+    comments: Mutex<Vec<(u64, Comment)>>,
+    latency: Duration,
+    requests: AtomicUsize,
+}
+
+impl MockForge {
+    pub fn new(latency: Duration) -> Self {
+        Self {
+            latency,
+            ..Default::default()
+        }
+    }
+
+    /// Returns how many requests have been made
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::Relaxed)
+    }
+
+    async fn request(&self) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        tokio::time::sleep(self.latency).await;
+    }
+}
+
+#[async_trait]
+impl Forge for MockForge {
+    async fn get_pr(&self, number: u64) -> Result<PullRequest> {
+        self.request().await;
+        let prs = self.prs.lock();
+        let pr = prs
+            .iter()
+            .find(|pr| pr.number == number)
+            .with_context(|| format!("no PR {number}"))?;
+        Ok(pr.clone())
+    }
+
+    async fn create_pr(
+        &self,
+        title: &str,
+        head: &str,
+        base: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest> {
+        self.request().await;
+        let mut prs = self.prs.lock();
+        let open = |pr: &PullRequest| pr.state == Some(IssueState::Open);
+        if prs.iter().any(|pr| pr.head.ref_field == head && open(pr)) {
+            let reason = format!("A pull request already exists for {head}");
+            return Err(ForgeError::new("Validation Failed", vec![reason]).into());
+        }
+
+        let number = prs.len() as u64 + 1;
+        let pr = serde_json::from_value(json!({
+            "url": format!("https://mock.invalid/pulls/{number}"),
+            "html_url": format!("https://mock.invalid/pull/{number}"),
+            "id": number,
+            "number": number,
+            "state": "open",
+            "title": title,
+            "body": body,
+            "draft": draft,
+            "head": { "ref": head, "sha": "" },
+            "base": { "ref": base, "sha": "" },
+        }))
+        .context("failed to build mock PR")?;
+        prs.push(pr);
+        Ok(prs.last().expect("just pushed").clone())
+    }
+
+    async fn update_pr(&self, number: u64, update: Update) -> Result<()> {
+        self.request().await;
+        let mut prs = self.prs.lock();
+        let pr = prs
+            .iter_mut()
+            .find(|pr| pr.number == number)
+            .with_context(|| format!("no PR {number}"))?;
+
+        if let Some(base) = update.base {
+            pr.base.ref_field = base;
+        }
+        if update.title.is_some() {
+            pr.title = update.title;
+        }
+        if update.body.is_some() {
+            pr.body = update.body;
+        }
+        if update.close {
+            pr.state = Some(IssueState::Closed);
+        }
+        Ok(())
+    }
+
+    async fn list_open_prs(&self, _owner: &str, branch: &str) -> Result<Vec<PullRequest>> {
+        self.request().await;
+        let prs = self.prs.lock();
+        Ok(prs
+            .iter()
+            .filter(|pr| pr.head.ref_field == branch && pr.state == Some(IssueState::Open))
+            .cloned()
+            .collect())
+    }
+
+    async fn list_reviews(&self, _number: u64) -> Result<Vec<Review>> {
+        self.request().await;
+        Ok(Vec::new())
+    }
+
+    async fn comment(&self, number: u64, body: &str) -> Result<u64> {
+        self.request().await;
+        let mut comments = self.comments.lock();
+        let id = comments
+            .iter()
+            .map(|(_, comment)| comment.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let body = body.to_string();
+        comments.push((number, Comment { id, body }));
+        Ok(id)
+    }
+
+    async fn list_comments(&self, number: u64) -> Result<Vec<Comment>> {
+        self.request().await;
+        let comments = self.comments.lock();
+        Ok(comments
+            .iter()
+            .filter(|(pr, _)| *pr == number)
+            .map(|(_, comment)| comment.clone())
+            .collect())
+    }
+
+    async fn edit_comment(&self, id: u64, body: &str) -> Result<()> {
+        self.request().await;
+        let mut comments = self.comments.lock();
+        let (_, comment) = comments
+            .iter_mut()
+            .find(|(_, comment)| comment.id == id)
+            .with_context(|| format!("no comment {id}"))?;
+        comment.body = body.to_string();
+        Ok(())
+    }
+
+    async fn delete_comment(&self, id: u64) -> Result<()> {
+        self.request().await;
+        let mut comments = self.comments.lock();
+        let index = comments
+            .iter()
+            .position(|(_, comment)| comment.id == id)
+            .ok_or_else(|| ForgeError::new("Not Found", Vec::new()))?;
+        comments.remove(index);
+        Ok(())
+    }
+
+    async fn add_labels(&self, _number: u64, _labels: &[String]) -> Result<()> {
+        self.request().await;
+        Ok(())
+    }
+
+    async fn request_reviews(
+        &self,
+        _number: u64,
+        _users: &[String],
+        _teams: &[String],
+    ) -> Result<()> {
+        self.request().await;
+        Ok(())
+    }
+
+    async fn current_user(&self) -> Result<String> {
+        self.request().await;
+        Ok("mock".to_string())
+    }
+
+    async fn protected_branches(&self) -> Vec<String> {
+        self.request().await;
+        Vec::new()
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use anyhow::{Context, Result};
+
+use crate::stack::Stack;
+
+#[derive(serde::Serialize)]
+struct HookStack<'a> {
+    name: &'a str,
+    upstream: &'a str,
+    commits: Vec<HookCommit<'a>>,
+}
+
+#[derive(serde::Serialize)]
+struct HookCommit<'a> {
+    id: String,
+    title: &'a str,
+    body: &'a str,
+    branch: Option<&'a str>,
+    pr: Option<u64>,
+    revision: Option<u32>,
+    custom: &'a BTreeMap<String, String>,
+}
+
+/// Run each command configured for the hook `name`, passing the stack as JSON on stdin.
+/// Fails as soon as a command exits unsuccessfully
+pub fn run(name: &str, commands: &[String], stack: &Stack) -> Result<()> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+
+    let info = HookStack {
+        name: stack.name(),
+        upstream: stack.upstream(),
+        commits: stack
+            .iter()
+            .map(|commit| HookCommit {
+                id: commit.id().to_string(),
+                title: &commit.title,
+                body: &commit.body,
+                branch: commit.metadata.branch.as_deref(),
+                pr: commit.metadata.pr,
+                revision: commit.metadata.revision,
+                custom: &commit.metadata.custom,
+            })
+            .collect(),
+    };
+    let info = serde_json::to_vec(&info).context("failed to serialize stack")?;
+
+    for command in commands {
+        tracing::debug!(name, command, "running hook");
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .env("FEL_HOOK", name)
+            .env("FEL_STACK", stack.name())
+            .env("FEL_UPSTREAM", stack.upstream())
+            .env("FEL_COMMITS", stack.len().to_string())
+            .stdin(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("failed to run {name} hook `{command}`"))?;
+
+        // The hook is free to ignore stdin, so a closed pipe isn't an error
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(&info).ok();
+        }
+
+        let status = child
+            .wait()
+            .with_context(|| format!("failed to wait for {name} hook `{command}`"))?;
+        anyhow::ensure!(status.success(), "{name} hook `{command}` failed: {status}");
+    }
+
+    Ok(())
+}
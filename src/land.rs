@@ -0,0 +1,148 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use octocrab::{params, Octocrab};
+
+use crate::config::{Land, MergeMethod};
+use crate::gh::{self, GHRepo};
+use crate::stack::Stack;
+use crate::submit;
+
+/// Merge the PRs for the commits at the bottom of the stack, up to and including the one at
+/// `up_to`, one after another. Footers starting with any of `delimiters` are kept out of merge
+/// commit messages. Each request may take `timeout`. Returns how many were landed
+pub async fn land(
+    stack: &Stack,
+    up_to: usize,
+    config: &Land,
+    delimiters: &[&str],
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    timeout: Duration,
+) -> Result<usize> {
+    let landings = plan(stack, up_to)?;
+
+    let method = match config.merge_method {
+        Some(method) => method,
+        None => {
+            let allowed = allowed_method(octocrab, gh_repo);
+            gh::timeout(timeout, "getting repo merge settings", allowed).await??
+        }
+    };
+    tracing::debug!(?method, "landing");
+
+    let pulls = octocrab.pulls(&gh_repo.owner, &gh_repo.repo);
+    for (index, (commit, landing)) in stack.iter().zip(landings).enumerate() {
+        let number = landing.number;
+        let pr = gh::timeout(timeout, &format!("getting PR {number}"), pulls.get(number))
+            .await?
+            .with_context(|| format!("failed to get PR {number}"))?;
+
+        // Every PR above the bottom is based on the branch of the one below it, which was
+        // just merged
+        if index > 0 {
+            let update = pulls.update(number).base(landing.base).send();
+            gh::timeout(timeout, &format!("retargeting PR {number}"), update)
+                .await?
+                .with_context(|| format!("failed to retarget PR {number}"))?;
+        }
+
+        // Only merge the commit that was submitted, in case the branch moved since
+        let mut merge = pulls
+            .merge(number)
+            .sha(commit.id().to_string())
+            .method(match method {
+                MergeMethod::Merge => params::pulls::MergeMethod::Merge,
+                MergeMethod::Squash => params::pulls::MergeMethod::Squash,
+                MergeMethod::Rebase => params::pulls::MergeMethod::Rebase,
+            });
+        // Rebasing keeps the commit's own message, so only the other methods use the body
+        if config.strip_footer && method != MergeMethod::Rebase {
+            let body = pr.body.as_deref().unwrap_or_default();
+            merge = merge.message(submit::strip_footer(body, delimiters));
+        }
+
+        let merged = gh::timeout(timeout, &format!("merging PR {number}"), merge.send())
+            .await?
+            .with_context(|| format!("failed to merge PR {number}"))?;
+        anyhow::ensure!(
+            merged.merged,
+            "failed to merge PR {number}: {}",
+            merged.message.unwrap_or_default()
+        );
+
+        println!("landed #{number} {}", commit.title);
+    }
+
+    // The rest stay open, so move the new bottom onto the base of its segment too
+    if let Some(number) = stack.get(up_to + 1).and_then(|commit| commit.metadata.pr) {
+        let update = pulls
+            .update(number)
+            .base(stack.segment_base(up_to + 1))
+            .send();
+        gh::timeout(timeout, &format!("retargeting PR {number}"), update)
+            .await?
+            .with_context(|| format!("failed to retarget PR {number}"))?;
+    }
+
+    Ok(up_to + 1)
+}
+
+/// A PR landing would merge, and the branch it's merged into
+pub struct Landing {
+    pub number: u64,
+    pub title: String,
+    pub base: String,
+}
+
+/// Plan landing the commits at the bottom of `stack` up to and including the one at `up_to`,
+/// failing if any of them can't be. Everything is checked before anything is merged, so a
+/// problem further up doesn't leave the stack half landed
+pub fn plan(stack: &Stack, up_to: usize) -> Result<Vec<Landing>> {
+    anyhow::ensure!(
+        up_to < stack.len(),
+        "no commit at index {up_to}, the stack has {} commits",
+        stack.len()
+    );
+
+    let mut landings = Vec::with_capacity(up_to + 1);
+    for (index, commit) in stack.iter().take(up_to + 1).enumerate() {
+        let number = commit
+            .metadata
+            .pr
+            .with_context(|| format!("{} has not been submitted", commit.title))?;
+        anyhow::ensure!(
+            !commit.changed(),
+            "{} has changed since it was submitted, submit it before landing",
+            commit.title
+        );
+        landings.push(Landing {
+            number,
+            title: commit.title.clone(),
+            base: stack.segment_base(index).to_string(),
+        });
+    }
+    Ok(landings)
+}
+
+/// Returns the first of merge, squash and rebase that `gh_repo` allows, the same order GitHub
+/// offers them in
+async fn allowed_method(octocrab: &Octocrab, gh_repo: &GHRepo) -> Result<MergeMethod> {
+    let repo = octocrab
+        .repos(&gh_repo.owner, &gh_repo.repo)
+        .get()
+        .await
+        .context("failed to get repo merge settings")?;
+
+    // GitHub leaves the settings out for tokens without push access, so assume they're allowed
+    let allowed = [
+        (MergeMethod::Merge, repo.allow_merge_commit),
+        (MergeMethod::Squash, repo.allow_squash_merge),
+        (MergeMethod::Rebase, repo.allow_rebase_merge),
+    ];
+    allowed
+        .into_iter()
+        .find(|(_, allowed)| allowed.unwrap_or(true))
+        .map(|(method, _)| method)
+        .context("the repo doesn't allow merging PRs")
+}
@@ -0,0 +1,283 @@
+//! Submit stacks of commits as stacked PRs.
+//!
+//! A [`Stack`](stack::Stack) is the commits between `HEAD` and upstream, each carrying its
+//! [`Metadata`](metadata::Metadata) in a git note. [`submit::submit`] pushes a branch for every
+//! commit with a [`BatchedPusher`](push::BatchedPusher), and opens or updates its PR through a
+//! [`Forge`](forge::Forge). [`submit::submit_with`] takes the forge and a
+//! [`StackRenderer`](render::StackRenderer) for the footers.
+//!
+//! ```no_run
+//! # async fn run() -> fel_core::Result<()> {
+//! use fel_core::{config::Config, stack::Stack};
+//!
+//! let repo = git2::Repository::discover(".")?;
+//! let config = Config::load()?;
+//! let stack = Stack::new(&repo, &config)?;
+//! for commit in stack.iter() {
+//!     println!("{} #{:?}", commit.title, commit.metadata.pr);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod commit;
+pub mod config;
+pub mod error;
+pub mod forge;
+pub mod gh;
+pub mod metadata;
+pub mod pulls;
+pub mod push;
+pub mod render;
+pub mod stack;
+pub mod submit;
+
+// Everything below is used by the fel binary to implement its commands, and isn't meant to be
+// stable
+#[doc(hidden)]
+pub mod abandon;
+#[doc(hidden)]
+pub mod actor;
+#[doc(hidden)]
+pub mod auth;
+#[doc(hidden)]
+pub mod backport;
+#[doc(hidden)]
+pub mod bench;
+#[doc(hidden)]
+pub mod cache;
+#[doc(hidden)]
+pub mod check;
+#[doc(hidden)]
+pub mod cherry_pick;
+#[doc(hidden)]
+pub mod codeowners;
+#[doc(hidden)]
+pub mod color;
+#[doc(hidden)]
+pub mod comments;
+#[doc(hidden)]
+pub mod describe;
+#[doc(hidden)]
+pub mod detach;
+#[doc(hidden)]
+pub mod diff;
+#[doc(hidden)]
+pub mod discussion;
+#[doc(hidden)]
+pub mod doctor;
+#[doc(hidden)]
+pub mod dropped;
+#[doc(hidden)]
+pub mod email;
+#[doc(hidden)]
+pub mod env;
+#[doc(hidden)]
+pub mod events;
+#[doc(hidden)]
+pub mod export;
+#[doc(hidden)]
+pub mod hooks;
+#[doc(hidden)]
+pub mod land;
+#[doc(hidden)]
+pub mod meta;
+#[doc(hidden)]
+pub mod overview;
+#[doc(hidden)]
+pub mod policy;
+#[doc(hidden)]
+pub mod prompt;
+#[doc(hidden)]
+pub mod protected;
+#[doc(hidden)]
+pub mod range_diff;
+#[doc(hidden)]
+pub mod ready;
+#[doc(hidden)]
+pub mod remotes;
+#[doc(hidden)]
+pub mod rename;
+#[doc(hidden)]
+pub mod rescue;
+#[doc(hidden)]
+pub mod review_map;
+#[doc(hidden)]
+pub mod reviewers;
+#[doc(hidden)]
+pub mod sections;
+#[doc(hidden)]
+pub mod selftest;
+#[doc(hidden)]
+pub mod stack_comment;
+#[doc(hidden)]
+pub mod state;
+#[doc(hidden)]
+pub mod status;
+#[doc(hidden)]
+pub mod summary;
+#[doc(hidden)]
+pub mod upstream;
+#[doc(hidden)]
+pub mod validate;
+#[doc(hidden)]
+pub mod watch;
+#[doc(hidden)]
+pub mod web;
+
+use std::error::Error as StdError;
+
+use forge::ForgeError;
+use push::PushError;
+
+/// Why an API call failed. `context` is what was being done when it did, outermost first
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    /// A git operation on the repo or its notes failed
+    #[error("{}{error}", prefix(.context))]
+    Git { context: String, error: git2::Error },
+
+    /// The forge refused a request, or couldn't be reached
+    #[error("{}{error}", prefix(.context))]
+    Forge { context: String, error: ForgeError },
+
+    /// The remote rejected a pushed branch
+    #[error("{}{error}", prefix(.context))]
+    Push { context: String, error: PushError },
+
+    /// A forge request took longer than `api.timeout`, or a submit longer than
+    /// `submit.deadline`
+    #[error("{}timed out {doing}", prefix(.context))]
+    Timeout { context: String, doing: String },
+
+    /// Anything else, like a bad config or a malformed note
+    #[error("{0:#}")]
+    Other(anyhow::Error),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn prefix(context: &str) -> String {
+    match context.is_empty() {
+        true => String::new(),
+        false => format!("{context}: "),
+    }
+}
+
+impl Error {
+    pub fn timeout(doing: impl Into<String>) -> Self {
+        Self::Timeout {
+            context: String::new(),
+            doing: doing.into(),
+        }
+    }
+
+    /// The typed error `cause` is, without any context
+    pub fn typed(cause: &(dyn StdError + 'static)) -> Option<Self> {
+        let context = String::new();
+        if let Some(error) = cause.downcast_ref::<PushError>() {
+            return Some(Self::Push {
+                context,
+                error: error.clone(),
+            });
+        }
+        if let Some(error) = cause.downcast_ref::<git2::Error>() {
+            let error = git2::Error::new(error.code(), error.class(), error.message());
+            return Some(Self::Git { context, error });
+        }
+        if let Some(error) = cause.downcast_ref::<ForgeError>() {
+            return Some(Self::Forge {
+                context,
+                error: error.clone(),
+            });
+        }
+        if let Some(error) = cause.downcast_ref::<octocrab::Error>() {
+            return Some(Self::Forge {
+                context,
+                error: ForgeError::from(error),
+            });
+        }
+        None
+    }
+
+    /// Add what was being done when this failed, outermost first
+    fn with_context(mut self, outer: &[String]) -> Self {
+        if outer.is_empty() {
+            return self;
+        }
+        let outer = outer.join(": ");
+        match &mut self {
+            Self::Git { context, .. }
+            | Self::Forge { context, .. }
+            | Self::Push { context, .. }
+            | Self::Timeout { context, .. } => {
+                *context = match context.is_empty() {
+                    true => outer,
+                    false => format!("{outer}: {context}"),
+                };
+            }
+            Self::Other(error) => {
+                let inner = std::mem::replace(error, anyhow::anyhow!(""));
+                *error = inner.context(outer);
+            }
+        }
+        self
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    /// Type an error by the first git, forge, push or library error in its chain
+    fn from(error: anyhow::Error) -> Self {
+        let mut context = Vec::new();
+        for cause in error.chain() {
+            // Already typed by a library call, only the callers' context needs adding
+            if cause.is::<Error>() {
+                break;
+            }
+            if let Some(typed) = Self::typed(cause) {
+                return typed.with_context(&context);
+            }
+            context.push(cause.to_string());
+        }
+        match error.downcast::<Error>() {
+            Ok(inner) => inner.with_context(&context),
+            Err(error) => Self::Other(error),
+        }
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(error: git2::Error) -> Self {
+        Self::Git {
+            context: String::new(),
+            error,
+        }
+    }
+}
+
+impl From<octocrab::Error> for Error {
+    fn from(error: octocrab::Error) -> Self {
+        Self::Forge {
+            context: String::new(),
+            error: ForgeError::from(&error),
+        }
+    }
+}
+
+impl From<ForgeError> for Error {
+    fn from(error: ForgeError) -> Self {
+        Self::Forge {
+            context: String::new(),
+            error,
+        }
+    }
+}
+
+impl From<PushError> for Error {
+    fn from(error: PushError) -> Self {
+        Self::Push {
+            context: String::new(),
+            error,
+        }
+    }
+}
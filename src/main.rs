@@ -1,21 +1,28 @@
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use clap::{Parser, Subcommand};
+use clap::{CommandFactory, Parser, Subcommand};
 use git2::Repository;
 
-mod auth;
-mod commit;
-mod config;
-mod gh;
-mod metadata;
-mod push;
-mod stack;
-mod submit;
+mod completions;
+mod examples;
 
-use config::Config;
-use stack::Stack;
+use fel_core::color::{self, ColorChoice};
+use fel_core::config::{
+    Autosquash, Backend, Config, Detached, EmptyCommits, MergeCommits, StaleBase,
+};
+use fel_core::error::FelError;
+use fel_core::events::{self, LogFormat};
+use fel_core::forge::GitHub;
+use fel_core::stack::Stack;
+use fel_core::{
+    abandon, auth, backport, bench, check, cherry_pick, comments, describe, detach, diff, doctor,
+    email, env, export, gh, land, meta, metadata, policy, prompt, range_diff, ready, remotes,
+    rename, render, rescue, reviewers, selftest, state, status, submit, summary, upstream, watch,
+    web,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -23,21 +30,363 @@ struct Cli {
     #[arg(short = 'C', value_name = "path", default_value = ".")]
     path: PathBuf,
 
+    /// Use this remote instead of default_remote. Submitting remembers it for the stack
+    #[arg(long, global = true)]
+    remote: Option<String>,
+
+    /// Make PRs against this branch or tag instead of default_upstream. Submitting remembers it
+    /// for the stack
+    #[arg(long, global = true)]
+    upstream: Option<String>,
+
+    /// Print logs and progress as JSON lines on stderr instead of drawing progress bars
+    #[arg(long, global = true, value_enum, default_value_t)]
+    log_format: LogFormat,
+
+    /// Stash uncommitted changes before restacking and restore them afterwards
+    #[arg(long, global = true)]
+    autostash: bool,
+
+    /// When to color output
+    #[arg(long, global = true, value_enum, default_value_t)]
+    color: ColorChoice,
+
+    /// Log more, -v for info, -vv for debug and -vvv to trace everything
+    #[arg(short, long, global = true, action = clap::ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only log errors, and don't draw progress bars
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Also append every log, down to trace, to this file
+    #[arg(long, global = true, value_name = "path")]
+    log_file: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand, Debug)]
 enum Commands {
-    Submit,
+    #[command(after_help = examples::SUBMIT)]
+    Submit {
+        /// Push new revisions even if the PRs they replace have already been reviewed
+        #[arg(long)]
+        force: bool,
+
+        /// Print how long each phase of the submit and each commit's PR took
+        #[arg(long)]
+        profile: bool,
+
+        /// Make the PR of the commit at INDEX against BRANCH from now on, like a `Fel-Base`
+        /// trailer, or clear it with INDEX=
+        #[arg(long, value_name = "INDEX=BRANCH")]
+        base: Vec<String>,
+    },
+
+    /// Write the stack as a patch series with a cover letter
+    #[command(after_help = examples::EXPORT)]
+    Export {
+        /// Directory to write patches to, instead of an mbox on stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Merge the PR at the bottom of the stack, then restack the rest onto upstream
+    #[command(after_help = examples::LAND)]
+    Land {
+        /// Merge every PR from the bottom up to this index
+        #[arg(long)]
+        up_to: Option<usize>,
+    },
+
+    /// Cherry-pick the commits of a PR onto the stack, to build on it before it lands
+    CherryPick {
+        /// The PR to pick, which can be from another fel stack or not
+        pr: u64,
+    },
+
+    /// Finish a restack or cherry-pick that stopped on a conflict once it's resolved, then run
+    /// the command that was restacking again
+    Continue,
+
+    /// Roll back a restack or cherry-pick that stopped on a conflict
+    Abort,
+
+    /// Keep the stack's PRs in sync while they merge, until interrupted. PRs above a merged
+    /// one are retargeted and their footers updated, and you're told when to restack
+    Watch {
+        /// Seconds between checks
+        #[arg(long, default_value_t = 60)]
+        interval: u64,
+    },
+
+    /// Check that every PR is made against the PR below it, that PR is still open, and its
+    /// head is the commit in the stack. Exits with 1 if any isn't
+    #[command(after_help = examples::CHECK)]
+    Check {
+        /// Also post the result on each PR as a check run. Meant for a GitHub Actions job using
+        /// its `GITHUB_TOKEN`, with a personal token it's posted as a commit status instead
+        #[arg(long)]
+        publish: bool,
+    },
+
+    /// Show the review, check and merge status of each PR in the stack
+    #[command(after_help = examples::STATUS)]
+    Status {
+        /// Exit with a non-zero status unless every PR is approved, green and mergeable
+        #[arg(long)]
+        exit_code: bool,
+
+        #[arg(long, value_enum, default_value_t)]
+        format: status::Format,
+
+        /// Replace names, titles and urls in JSON output with hashes, for bug reports. Only
+        /// allowed with `--format json`, text output isn't anonymized
+        #[arg(long)]
+        anonymize: bool,
+    },
+
+    /// List unresolved review comments across the stack
+    Comments {
+        /// Open each unresolved thread in the browser
+        #[arg(long)]
+        web: bool,
+    },
+
+    /// Show how commits changed since they were last submitted
+    #[command(after_help = examples::RANGE_DIFF)]
+    RangeDiff {
+        /// Index of the commit in the stack, defaults to every changed commit
+        index: Option<usize>,
+
+        /// Also post the range-diff as a comment on each PR
+        #[arg(long)]
+        comment: bool,
+    },
+
+    /// Show what submitting would change on each PR, by diffing its head on the remote against
+    /// the local commit
+    Diff {
+        /// Index of the commit in the stack, defaults to every submitted commit
+        index: Option<usize>,
+    },
+
+    /// Edit the description of a PR, or with authoritative commits its commit, in $EDITOR
+    Describe {
+        /// Index of the commit in the stack, defaults to the top
+        index: Option<usize>,
+    },
+
+    /// Hand a PR off as a standalone PR: take the fel footer out of its body and forget it
+    Detach {
+        /// Index of the commit in the stack, defaults to the top
+        index: Option<usize>,
+
+        /// Also make the PR against upstream instead of the PR below it
+        #[arg(long)]
+        retarget: bool,
+    },
+
+    /// Open PRs in the browser
+    #[command(after_help = examples::WEB)]
+    Web {
+        /// Index of the commit in the stack, or a PR number like #123. Defaults to the top
+        /// of the stack
+        target: Option<String>,
+
+        /// Open every PR in the stack
+        #[arg(long)]
+        all: bool,
+    },
+
+    /// Mark draft PRs as ready for review
+    #[command(after_help = examples::READY)]
+    Ready {
+        /// Index of the commit in the stack, defaults to the top of the stack
+        index: Option<usize>,
+
+        /// Mark every PR in the stack ready
+        #[arg(long)]
+        all: bool,
+
+        /// Also request reviews from the configured reviewers
+        #[arg(long)]
+        request_reviews: bool,
+    },
+
+    /// Close every open PR in the stack with a comment, delete its remote branches and forget
+    /// its notes
+    Abandon {
+        /// Also delete the local branch, after switching to upstream
+        #[arg(long)]
+        delete_branch: bool,
+
+        /// Don't ask first
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Rename the stack and its branch. Its PRs keep their branches, and new commits get
+    /// branches under the new name
+    Rename {
+        /// The new name, which can be the branch's name after renaming it with git
+        name: String,
+    },
+
+    /// Cherry-pick the stack onto a release branch and submit the copies as PRs against it,
+    /// linked to the originals in both stacks' footers
+    Backport {
+        /// The branch to backport to
+        branch: String,
+    },
+
+    /// Read and write custom metadata kept in a commit's note
+    Meta {
+        #[command(subcommand)]
+        command: MetaCommand,
+    },
+
+    /// Print the effective config and what fel detected about the repo
+    Env,
+
+    /// Show examples of a command using the current stack, without running anything
+    Examples {
+        /// The command to show examples of, defaults to every command
+        command: Option<String>,
+    },
+
+    /// Re-attach fel metadata lost when commits were rewritten without notes.rewriteRef
+    Rescue {
+        /// Link every match without asking
+        #[arg(long)]
+        yes: bool,
+    },
+
+    /// Check that notes survive rewrites, and find commits that already lost theirs
+    Doctor {
+        /// Re-attach lost notes
+        #[arg(long)]
+        fix: bool,
+    },
+
+    /// Check that fel can push, and open, update and close PRs, with a throwaway stack of
+    /// draft PRs that is cleaned up afterwards
+    Selftest,
+
+    /// Time submitting a synthetic stack, to measure performance
+    Bench {
+        /// Number of commits in the stack
+        #[arg(long, default_value_t = 100)]
+        commits: usize,
+
+        /// Submit to an in-memory forge instead of GitHub. Required, since benchmarking
+        /// against GitHub would open real PRs
+        #[arg(long)]
+        mock_forge: bool,
+
+        /// How long the mock forge takes to answer each request, in milliseconds
+        #[arg(long, default_value_t = 50)]
+        latency: u64,
+    },
+
+    /// Print a shell completion script
+    Completions {
+        shell: clap_complete::Shell,
+
+        /// Also complete stack indices and PR numbers by calling back into fel
+        #[arg(long)]
+        dynamic: bool,
+    },
+
+    /// List completion candidates for dynamic completion scripts
+    #[command(name = "__complete", hide = true)]
+    Complete { candidates: completions::Candidates },
+}
+
+#[derive(Subcommand, Debug)]
+enum MetaCommand {
+    /// Set a key on the commit at `index`
+    Set {
+        index: usize,
+        key: String,
+        value: String,
+    },
+
+    /// Remove a key from the commit at `index`
+    Unset { index: usize, key: String },
+
+    /// Print a key of the commit at `index`, or every key
+    Get { index: usize, key: Option<String> },
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn main() {
+    let Err(error) = run().await else {
+        return;
+    };
+
+    let fel_error = FelError::classify(&error);
+    let code = fel_error.as_ref().map_or(1, FelError::exit_code);
+    tracing::debug!(code, "failed: {error:#}");
+    if events::json() {
+        events::error(&error, code);
+    } else {
+        eprintln!("Error: {error:?}");
+        if let Some(fel_error) = fel_error {
+            eprintln!("\n{}", fel_error.hint());
+        }
+    }
+    std::process::exit(code);
+}
+
+async fn run() -> Result<()> {
     let cli = Cli::parse();
 
-    let config = Config::load().context("failed to load config")?;
-    tracing_subscriber::fmt::init();
+    // `--format` has a default, so clap can't require it to be json itself
+    if let Commands::Status {
+        anonymize: true,
+        format: status::Format::Text,
+        ..
+    } = cli.command
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "--anonymize only works with --format json",
+            )
+            .exit();
+    }
+
+    // Completion scripts don't depend on the config or the repo
+    if let Commands::Completions { shell, dynamic } = cli.command {
+        return completions::completions(shell, dynamic, &mut Cli::command());
+    }
+
+    color::init(cli.color);
+    events::init(
+        cli.log_format,
+        cli.verbose,
+        cli.quiet,
+        cli.log_file.as_deref(),
+    )?;
+
+    // Benchmarks build their own repo and config
+    if let Commands::Bench {
+        commits,
+        mock_forge,
+        latency,
+    } = cli.command
+    {
+        anyhow::ensure!(mock_forge, "fel bench only supports --mock-forge");
+        return bench::bench(commits, Duration::from_millis(latency)).await;
+    }
+
+    let mut config = Config::load().context("failed to load config")?;
+    config.autostash |= cli.autostash;
+    color::set_colors(&config.colors)?;
+    render::init_ascii(config.render.ascii);
 
     // Make sure that notes.rewriteRef contains the namespace for fel notes so
     // they are copied along with commits during a rebase or ammend
@@ -53,47 +402,462 @@ async fn main() -> Result<()> {
                 found = true;
             }
         })?;
+        // The doctor explains this itself
         anyhow::ensure!(
-            found,
+            found || matches!(cli.command, Commands::Doctor { .. }),
             "notes.rewriteRef must include 'refs/notes/fel' for fel to work properly"
         );
     }
 
     let repo = Repository::discover(&cli.path).context("failed to open repo")?;
 
-    let mut stack = Stack::new(&repo, &config).context("failed to get stack")?;
+    // Stacks keep using the remote and upstream they were last submitted to
+    let head_metadata = repo
+        .head()
+        .and_then(|head| head.peel_to_commit())
+        .ok()
+        .and_then(|head| metadata::Metadata::new(&repo, &head).ok())
+        .unwrap_or_default();
+    if let Some(remote) = cli.remote.clone().or(head_metadata.remote) {
+        config.default_remote = remote;
+    }
+    if let Some(upstream) = cli.upstream.clone().or(head_metadata.upstream) {
+        config.default_upstream = upstream;
+    }
 
-    let octocrab = Arc::new(
-        octocrab::OctocrabBuilder::default()
-            .personal_token(config.token.clone())
-            .build()?,
-    );
+    // The policy applies to the upstream actually used, wherever it came from
+    let policy = policy::Policy::load(&repo).context("failed to load policy")?;
+    if let Some(policy) = &policy {
+        policy
+            .apply(&mut config)
+            .context("config violates policy")?;
+    }
+
+    // Settings for the host the repo is on replace the top level ones
+    let host = repo
+        .find_remote(&config.default_remote)
+        .ok()
+        .and_then(|remote| gh::get_repo(&remote).ok())
+        .map(|gh_repo| gh_repo.host);
+    if let Some(host) = host {
+        config.use_host(&host);
+    }
+    auth::init_ssh_key(config.ssh_key.clone());
+
+    // Diagnostics shouldn't depend on finding a stack
+    if let Commands::Env = cli.command {
+        return env::env(&repo, &config, policy.as_ref());
+    }
+
+    match cli.command {
+        Commands::Continue => {
+            if let Some(pick) = state::State::load(&repo)?.pending_pick().cloned() {
+                let picked = cherry_pick::continue_pick(&repo, &pick)
+                    .with_context(|| format!("failed to cherry-pick PR {}", pick.number))?;
+                println!("picked {picked} commits from #{}", pick.number);
+                return Ok(());
+            }
+            let Some(resume) = upstream::continue_restack(&repo)? else {
+                return Ok(());
+            };
+            println!("restacked, running `fel {}` again", resume.join(" "));
+            let status = std::process::Command::new(std::env::current_exe()?)
+                .args(resume)
+                .status()
+                .context("failed to run fel")?;
+            std::process::exit(status.code().unwrap_or(1));
+        }
+        Commands::Abort => {
+            return match state::State::load(&repo)?.pending_pick() {
+                Some(pick) => cherry_pick::abort_pick(&repo, pick),
+                None => upstream::abort_restack(&repo),
+            };
+        }
+        _ => {}
+    }
 
     let mut remote = repo
         .find_remote(&config.default_remote)
         .context("failed to get remote")?;
 
+    let submitting = matches!(cli.command, Commands::Submit { .. });
+    if submitting && config.submit.fetch {
+        upstream::fetch(
+            &repo,
+            &mut remote,
+            &config.default_upstream,
+            config.proxy.as_deref(),
+        )
+        .context("failed to fetch upstream")?;
+    }
+
+    let discovery = Instant::now();
+    let mut stack = Stack::new(&repo, &config).context("failed to get stack")?;
+    let discovery = discovery.elapsed();
+
+    let upstream = format!("{}/{}", config.default_remote, config.default_upstream);
+    if submitting && stack.has_merges() {
+        match config.submit.merge_commits {
+            MergeCommits::Error => anyhow::bail!(
+                "stack contains merge commits, run `git rebase {upstream}` to remove them \
+                or set submit.merge_commits = \"rebase\""
+            ),
+            MergeCommits::Rebase => {
+                upstream::restack(&repo, &upstream, true, config.autostash)
+                    .context("failed to restack")?;
+                stack = Stack::new(&repo, &config).context("failed to get stack")?;
+            }
+        }
+    }
+
+    if submitting && stack.is_stale() {
+        match config.submit.stale_base {
+            StaleBase::Warn => eprintln!(
+                "{} stack is not based on the tip of {upstream}",
+                color::warning().paint("warning:")
+            ),
+            StaleBase::Restack => {
+                upstream::restack(&repo, &upstream, true, config.autostash)
+                    .context("failed to restack")?;
+                stack = Stack::new(&repo, &config).context("failed to get stack")?;
+            }
+            StaleBase::Error => {
+                anyhow::bail!("stack is not based on the tip of {upstream}, rebase and try again")
+            }
+        }
+    }
+
+    if submitting && stack.has_fixups() {
+        let squash = match config.submit.autosquash {
+            Autosquash::Never => false,
+            Autosquash::Ask => prompt::confirm("stack has fixup commits, squash them first?")?,
+            Autosquash::Always => true,
+        };
+        if squash {
+            upstream::autosquash(&repo, stack.merge_base(), true, config.autostash)
+                .context("failed to autosquash")?;
+            stack = Stack::new(&repo, &config).context("failed to get stack")?;
+        }
+    }
+
+    if submitting {
+        match (config.submit.empty_commits, stack.first_empty()) {
+            (_, None) => {}
+            (EmptyCommits::Error, Some(commit)) => anyhow::bail!(
+                "{} is empty, its changes may have landed upstream. Drop it with `git rebase -i` \
+                or set submit.empty_commits to \"skip\" or \"drop\"",
+                commit.title
+            ),
+            (EmptyCommits::Skip, Some(_)) => stack.skip_empty(),
+            (EmptyCommits::Drop, Some(_)) => stack
+                .drop_empty(&repo)
+                .context("failed to drop empty commits")?,
+        }
+    }
+
+    // Mailing lists don't need GitHub at all, but the stack is still checked first
+    if submitting && config.submit.backend == Backend::Email {
+        submit::prepare(&stack, &config)?;
+        return email::submit(&stack, &repo, &config.email).context("failed to email stack");
+    }
+
+    // Only commands that change PRs get the write token
+    let writes = matches!(
+        cli.command,
+        Commands::Submit { .. }
+            | Commands::Selftest
+            | Commands::Land { .. }
+            | Commands::Watch { .. }
+            | Commands::Check { publish: true }
+            | Commands::Abandon { .. }
+            | Commands::Backport { .. }
+            | Commands::Ready { .. }
+            | Commands::Describe { .. }
+            | Commands::Detach { .. }
+            | Commands::RangeDiff { comment: true, .. }
+    );
     let gh_repo = gh::get_repo(&remote).context("failed to get repo")?;
+    let token = config.token(writes)?;
+    let api = config.api_url(&gh_repo.host);
+    let octocrab = Arc::new(
+        gh::client(token, config.proxy.as_deref(), &api).context("failed to create client")?,
+    );
 
     match cli.command {
-        Commands::Submit => {
+        Commands::Submit {
+            force,
+            profile,
+            base,
+        } => {
+            if stack.is_empty() {
+                println!(
+                    "nothing to submit, {} is up to date with {upstream}",
+                    stack.name()
+                );
+                return Ok(());
+            }
+
             if config.submit.auto_create_branches && stack.is_detached() {
                 stack
-                    .dev_branch(&repo)
+                    .dev_branch(&repo, &config.submit.dev_branch_template)
                     .context("failed to create dev branch")?;
             }
 
-            // Push every commit
-            submit::submit(
-                &stack,
+            if stack.is_detached() {
+                match config.submit.detached {
+                    Detached::Error => anyhow::bail!(
+                        "HEAD is detached, check out a branch, set submit.auto_create_branches \
+                        or set submit.detached = \"anonymous\""
+                    ),
+                    Detached::Anonymous => {
+                        let name = stack.anonymous_name().context("stack is empty")?;
+                        stack.set_name(name);
+                    }
+                }
+            }
+
+            for base in base {
+                let (index, branch) = base
+                    .split_once('=')
+                    .with_context(|| format!("expected INDEX=BRANCH, got {base}"))?;
+                let index = index
+                    .parse()
+                    .with_context(|| format!("invalid index {index}"))?;
+                let branch = (!branch.is_empty()).then(|| branch.to_string());
+                if let (Some(policy), Some(branch)) = (&config.policy, &branch) {
+                    policy.check_upstream(branch)?;
+                }
+                stack.set_base(&repo, index, branch)?;
+            }
+
+            let timings = submit::run(
+                &mut stack, &repo, remote, octocrab, &gh_repo, &config, force,
+            )
+            .await?;
+            if profile {
+                summary::print_profile(&stack, discovery, &timings);
+            }
+        }
+        Commands::Selftest => {
+            let mut remote = remotes::push_remote(&repo, remote, &gh_repo, config.proxy.as_deref())
+                .context("failed to find push remote")?;
+            let passed = selftest::selftest(
+                &repo,
                 &mut remote,
+                &config.default_upstream,
                 octocrab.clone(),
                 &gh_repo,
+                &config,
+            )
+            .await
+            .context("failed to run self test")?;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Commands::Export { output } => {
+            export::export(&stack, &repo, output.as_deref()).context("failed to export")?;
+        }
+        Commands::Land { up_to } => {
+            let landed = land::land(
+                &stack,
+                up_to.unwrap_or(0),
+                &config.land,
+                &submit::delimiters(&config.submit),
+                &octocrab,
+                &gh_repo,
+                config.api.timeout(),
+            )
+            .await
+            .context("failed to land")?;
+
+            // Drop the landed commits locally, and leave the rest of the stack where the next
+            // submit expects it. Anything below that landed some other way is dropped too
+            upstream::fetch(
+                &repo,
+                &mut remote,
+                &config.default_upstream,
+                config.proxy.as_deref(),
+            )
+            .context("failed to fetch upstream")?;
+            let landed = upstream::landed(&repo, &stack, &upstream)
+                .context("failed to find landed commits")?
+                .max(landed);
+            let top_landed = stack.get(landed - 1).context("nothing landed")?;
+            upstream::restack_above(&repo, &upstream, top_landed.id(), false, config.autostash)
+                .context("failed to restack")?;
+            println!(
+                "dropped {landed} landed commits, {} left on {upstream}",
+                stack.len() - landed
+            );
+        }
+        Commands::CherryPick { pr } => {
+            let picked = cherry_pick::cherry_pick(
+                &repo,
+                &mut remote,
+                &stack,
+                &upstream,
+                pr,
+                config.proxy.as_deref(),
+                config.autostash,
+            )
+            .with_context(|| format!("failed to cherry-pick PR {pr}"))?;
+            println!("picked {picked} commits from #{pr}");
+        }
+        Commands::Watch { interval } => {
+            let forge = GitHub::new(octocrab.clone(), gh_repo, config.api).cached(&repo);
+            watch::watch(&repo, &config, &forge, Duration::from_secs(interval))
+                .await
+                .context("failed to watch")?;
+        }
+        Commands::Check { publish } => {
+            let forge = GitHub::new(octocrab.clone(), gh_repo.clone(), config.api);
+            let passed = check::check(&stack, &forge, &octocrab, &gh_repo, &config, publish)
+                .await
+                .context("failed to check stack")?;
+            if !passed {
+                std::process::exit(1);
+            }
+        }
+        Commands::Status {
+            exit_code,
+            format,
+            anonymize,
+        } => {
+            let state = state::State::load(&repo)?;
+            let last_submit = state
+                .stack(stack.name())
+                .and_then(|stack| stack.submitted_at);
+            let ready = status::status(
+                &stack,
+                last_submit,
+                &config.status,
+                format,
+                anonymize,
+                &octocrab,
+                &gh_repo,
+                config.api.timeout(),
+            )
+            .await
+            .context("failed to get status")?;
+            if exit_code && !ready {
+                std::process::exit(1);
+            }
+        }
+        Commands::Comments { web } => {
+            comments::comments(&stack, web, &octocrab, &gh_repo, config.api.timeout())
+                .await
+                .context("failed to get comments")?;
+        }
+        Commands::RangeDiff { index, comment } => {
+            let timeout = config.api.timeout();
+            range_diff::range_diff(&stack, index, comment, &repo, &octocrab, &gh_repo, timeout)
+                .await
+                .context("failed to range-diff")?;
+        }
+        Commands::Diff { index } => {
+            diff::diff(&stack, index, &repo, &mut remote, config.proxy.as_deref())
+                .context("failed to diff")?;
+        }
+        Commands::Detach { index, retarget } => {
+            let forge = GitHub::new(octocrab.clone(), gh_repo.clone(), config.api);
+            detach::detach(&stack, index, retarget, &repo, &forge, &config)
+                .await
+                .context("failed to detach PR")?;
+        }
+        Commands::Describe { index } => {
+            let authoritative = config.submit.authoritative_commits;
+            describe::describe(
+                &mut stack,
+                index,
+                authoritative,
                 &repo,
+                &octocrab,
+                &gh_repo,
                 &config,
             )
             .await
-            .context("failed to submit")?;
+            .context("failed to describe")?;
+        }
+        Commands::Web { target, all } => {
+            web::web(&stack, target.as_deref(), all, &gh_repo).context("failed to open PRs")?;
+        }
+        Commands::Ready {
+            index,
+            all,
+            request_reviews,
+        } => {
+            let reviewers = match request_reviews {
+                true => reviewers::for_stack(&stack, &repo, &config)
+                    .context("failed to find reviewers")?,
+                false => Default::default(),
+            };
+            let timeout = config.api.timeout();
+            ready::ready(&stack, index, all, &reviewers, &octocrab, &gh_repo, timeout)
+                .await
+                .context("failed to mark PRs ready")?;
+        }
+        Commands::Meta { command } => match command {
+            MetaCommand::Set { index, key, value } => {
+                meta::set(&stack, &repo, index, &key, Some(&value))?
+            }
+            MetaCommand::Unset { index, key } => meta::set(&stack, &repo, index, &key, None)?,
+            MetaCommand::Get { index, key } => meta::get(&stack, index, key.as_deref())?,
+        },
+        Commands::Abandon { delete_branch, yes } => {
+            let mut remote = remotes::configured(&repo, remote, &gh_repo, &config)
+                .context("failed to find push remote")?;
+            abandon::abandon(
+                &repo,
+                &stack,
+                &mut remote,
+                &octocrab,
+                &gh_repo,
+                &config,
+                delete_branch,
+                yes,
+            )
+            .await
+            .context("failed to abandon stack")?;
+        }
+        Commands::Rename { name } => {
+            rename::rename(&repo, &stack, &name).context("failed to rename stack")?;
+        }
+        Commands::Backport { branch } => {
+            upstream::fetch(&repo, &mut remote, &branch, config.proxy.as_deref())
+                .with_context(|| format!("failed to fetch {branch}"))?;
+            let name = backport::backport(
+                &repo,
+                &mut stack,
+                octocrab.clone(),
+                &gh_repo,
+                &config,
+                &branch,
+            )
+            .await
+            .with_context(|| format!("failed to backport to {branch}"))?;
+            println!("backported {} commits to {branch} as {name}", stack.len());
+        }
+        Commands::Rescue { yes } => {
+            rescue::rescue(&stack, &repo, yes).context("failed to rescue metadata")?;
+        }
+        Commands::Doctor { fix } => {
+            if !doctor::doctor(&stack, &repo, fix)? && !fix {
+                std::process::exit(1);
+            }
+        }
+        Commands::Completions { .. } | Commands::Bench { .. } => {
+            unreachable!("handled before loading config")
+        }
+        Commands::Examples { command } => {
+            examples::examples(&stack, &config, command.as_deref())?;
+        }
+        Commands::Env | Commands::Continue | Commands::Abort => {
+            unreachable!("handled before finding the stack")
+        }
+        Commands::Complete { candidates } => {
+            let timeout = config.api.timeout();
+            completions::complete(candidates, &stack, &repo, &octocrab, &gh_repo, timeout).await?;
         }
     }
     Ok(())
@@ -0,0 +1,57 @@
+use anyhow::{Context, Result};
+use git2::Repository;
+
+use crate::stack::Stack;
+
+/// Set the custom metadata `key` of the commit at `index` to `value`, or remove it if `value`
+/// is `None`. Custom keys live in the commit's note, so they follow it through rewrites
+pub fn set(
+    stack: &Stack,
+    repo: &Repository,
+    index: usize,
+    key: &str,
+    value: Option<&str>,
+) -> Result<()> {
+    anyhow::ensure!(!key.is_empty(), "metadata key can't be empty");
+    let commit = stack
+        .get(index)
+        .with_context(|| format!("no commit at index {index}"))?;
+
+    let mut metadata = commit.metadata.clone();
+    let previous = match value {
+        Some(value) => metadata.custom.insert(key.to_string(), value.to_string()),
+        None => metadata.custom.remove(key),
+    };
+    if previous.as_deref() == value {
+        return Ok(());
+    }
+
+    metadata
+        .write(repo, commit.id())
+        .context("failed to write metadata")?;
+    Ok(())
+}
+
+/// Print the custom metadata of the commit at `index`, only `key` if it is given
+pub fn get(stack: &Stack, index: usize, key: Option<&str>) -> Result<()> {
+    let commit = stack
+        .get(index)
+        .with_context(|| format!("no commit at index {index}"))?;
+    let custom = &commit.metadata.custom;
+
+    match key {
+        Some(key) => {
+            let value = custom
+                .get(key)
+                .with_context(|| format!("{} has no {key}", commit.title))?;
+            println!("{value}");
+        }
+        None => {
+            for (key, value) in custom {
+                println!("{key} = {value}");
+            }
+        }
+    }
+
+    Ok(())
+}
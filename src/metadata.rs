@@ -1,5 +1,7 @@
+use std::collections::BTreeMap;
+
 use anyhow::{Context, Result};
-use git2::{Commit, Oid, Repository};
+use git2::{Commit, ObjectType, Oid, Repository, Tree};
 
 pub const NOTE_REF: &str = "refs/notes/fel";
 
@@ -11,13 +13,53 @@ pub struct Metadata {
     pub commit: Option<String>,
     pub history: Option<Vec<String>>,
     pub pr_url: Option<String>,
+    pub discussion_id: Option<String>,
+    pub discussion_url: Option<String>,
+    pub overview_issue: Option<u64>,
+    pub overview_url: Option<String>,
+    /// The comment on the commit's PR holding the stack tree, with `submit.footer_placement`
+    pub stack_comment: Option<u64>,
+    /// The remote the commit was submitted to
+    pub remote: Option<String>,
+    /// The branch or tag the stack was submitted against
+    pub upstream: Option<String>,
+    /// Unix time the commit's PR was first submitted
+    pub submitted_at: Option<u64>,
+    /// Unix time a new revision of the commit was last pushed
+    pub updated_at: Option<u64>,
+    /// The stack the commit was submitted in
+    pub stack: Option<String>,
+    /// The branch to make the commit's PR against, set with `fel submit --base`
+    pub base: Option<String>,
+    /// The stack this commit's stack was submitted on top of
+    pub depends_on: Option<String>,
+    /// The branch fel created for the stack when this commit was at its top
+    pub dev_branch: Option<String>,
+    /// The version of the patch series this commit was last emailed in
+    pub email_version: Option<u32>,
+    /// Message-ID of the cover letter of that version
+    pub email_message_id: Option<String>,
+    /// The commit this one was copied from by `fel cherry-pick`
+    pub picked_from: Option<String>,
+    /// The PR it was picked from
+    pub picked_from_pr: Option<u64>,
+    /// PRs the commit was backported in by `fel backport`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub backports: Vec<u64>,
+    /// Keys set with `fel meta set`, for tools and templates outside fel
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub custom: BTreeMap<String, String>,
 }
 
 impl Metadata {
-    pub fn new(repo: &Repository, commit: &Commit) -> Result<Self> {
+    pub fn new(repo: &Repository, commit: &Commit) -> crate::Result<Self> {
         tracing::debug!(?commit, "walking tree");
+        Self::find(repo, commit.id())
+    }
 
-        let note = repo.find_note(Some(NOTE_REF), commit.id());
+    /// Read the metadata of the object `id`, which doesn't have to still exist
+    pub fn find(repo: &Repository, id: Oid) -> crate::Result<Self> {
+        let note = repo.find_note(Some(NOTE_REF), id);
 
         // check if this commit has a note already
         let metadata = match note {
@@ -37,7 +79,7 @@ impl Metadata {
         Ok(metadata)
     }
 
-    pub fn write(&self, repo: &Repository, commit: Oid) -> Result<()> {
+    pub fn write(&self, repo: &Repository, commit: Oid) -> crate::Result<()> {
         let metadata = toml::to_string_pretty(&self).context("failed to serialize metadata")?;
         let sig = repo.signature().context("failed to get signature")?;
         tracing::debug!(metadata, ?commit, "writing note");
@@ -46,3 +88,116 @@ impl Metadata {
         Ok(())
     }
 }
+
+/// Note updates staged to be written to `NOTE_REF` in a single notes commit, rather than one
+/// commit per note like `Metadata::write`
+#[derive(Default)]
+pub struct NoteBatch {
+    /// The note for each commit, or `None` to remove it
+    notes: Vec<(Oid, Option<String>)>,
+}
+
+impl NoteBatch {
+    /// Stage `metadata` as the note for `commit`
+    pub fn add(&mut self, metadata: &Metadata, commit: Oid) -> Result<()> {
+        let metadata = toml::to_string_pretty(metadata).context("failed to serialize metadata")?;
+        self.notes.push((commit, Some(metadata)));
+        Ok(())
+    }
+
+    /// Stage removing the note for `commit`, if it has one
+    pub fn remove(&mut self, commit: Oid) {
+        self.notes.push((commit, None));
+    }
+
+    /// Write every staged note in one commit on top of `NOTE_REF`
+    pub fn write(self, repo: &Repository) -> Result<()> {
+        if self.notes.is_empty() {
+            return Ok(());
+        }
+
+        let parent = match repo.find_reference(NOTE_REF) {
+            Ok(reference) => Some(reference.peel_to_commit().context("invalid notes ref")?),
+            Err(_) => None,
+        };
+        let mut tree = match &parent {
+            Some(parent) => Some(parent.tree().context("failed to get notes tree")?),
+            None => None,
+        };
+
+        tracing::debug!(count = self.notes.len(), "writing notes");
+        for (commit, metadata) in &self.notes {
+            let blob = match metadata {
+                Some(metadata) => Some(
+                    repo.blob(metadata.as_bytes())
+                        .context("failed to write note")?,
+                ),
+                None => None,
+            };
+            let id = update_note(repo, tree.as_ref(), &commit.to_string(), blob)?;
+            tree = Some(repo.find_tree(id).context("failed to find notes tree")?);
+        }
+
+        let tree = tree.context("no notes tree")?;
+        let unchanged = match &parent {
+            Some(parent) => parent.tree_id() == tree.id(),
+            None => tree.is_empty(),
+        };
+        if unchanged {
+            return Ok(());
+        }
+        let sig = repo.signature().context("failed to get signature")?;
+        let parents: Vec<_> = parent.iter().collect();
+        repo.commit(
+            Some(NOTE_REF),
+            &sig,
+            &sig,
+            "Notes added by 'fel'",
+            &tree,
+            &parents,
+        )
+        .context("failed to commit notes")?;
+
+        Ok(())
+    }
+}
+
+/// Add `blob` as the note named `hex` to `tree`, or remove that note when `blob` is `None`,
+/// following any fanout directories already in it the way git does, and return the new tree
+fn update_note(
+    repo: &Repository,
+    tree: Option<&Tree>,
+    hex: &str,
+    blob: Option<Oid>,
+) -> Result<Oid> {
+    let mut builder = repo
+        .treebuilder(tree)
+        .context("failed to create tree builder")?;
+
+    let fanout = tree
+        .filter(|_| hex.len() > 2)
+        .and_then(|tree| tree.get_name(&hex[..2]))
+        .filter(|entry| entry.kind() == Some(ObjectType::Tree))
+        .map(|entry| entry.id());
+    match fanout {
+        Some(subtree) => {
+            let subtree = repo.find_tree(subtree).context("failed to find fanout")?;
+            let id = update_note(repo, Some(&subtree), &hex[2..], blob)?;
+            match repo
+                .find_tree(id)
+                .context("failed to find fanout")?
+                .is_empty()
+            {
+                true => builder.remove(&hex[..2])?,
+                false => builder.insert(&hex[..2], id, 0o040000).map(drop)?,
+            }
+        }
+        None => match blob {
+            Some(blob) => builder.insert(hex, blob, 0o100644).map(drop)?,
+            None if builder.get(hex)?.is_some() => builder.remove(hex)?,
+            None => {}
+        },
+    }
+
+    builder.write().context("failed to write notes tree")
+}
@@ -0,0 +1,41 @@
+use anyhow::{Context, Result};
+use octocrab::Octocrab;
+
+use crate::gh::GHRepo;
+
+/// An issue tracking every PR in a stack
+#[derive(Clone, Debug)]
+pub struct Overview {
+    pub number: u64,
+    pub url: String,
+}
+
+/// Update the body of the issue `existing`, or open a new issue if there isn't one yet
+pub async fn upsert(
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    existing: Option<u64>,
+    title: &str,
+    body: &str,
+) -> Result<Overview> {
+    let issues = octocrab.issues(&gh_repo.owner, &gh_repo.repo);
+    let issue = match existing {
+        Some(number) => issues
+            .update(number)
+            .body(body)
+            .send()
+            .await
+            .with_context(|| format!("failed to update overview issue {number}"))?,
+        None => issues
+            .create(title)
+            .body(body)
+            .send()
+            .await
+            .context("failed to create overview issue")?,
+    };
+
+    Ok(Overview {
+        number: issue.number,
+        url: issue.html_url.to_string(),
+    })
+}
@@ -0,0 +1,109 @@
+use anyhow::{Context, Result};
+use git2::Repository;
+use globset::Glob;
+
+use crate::config::Config;
+
+/// Name of the policy file, at the root of the repo
+pub const POLICY_FILE: &str = ".fel-policy.toml";
+
+/// Rules maintainers commit to a repo to constrain how contributors use fel. Anything set in
+/// the policy takes precedence over the user's config
+#[derive(serde::Deserialize, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// Patterns matching the upstream branches stacks may be submitted against
+    pub allowed_upstreams: Option<Vec<String>>,
+
+    /// Force PRs to be created as drafts, or not
+    pub draft: Option<bool>,
+
+    /// Forbid force pushes when set to false
+    pub allow_force: Option<bool>,
+
+    /// Labels every PR must have
+    #[serde(default)]
+    pub labels: Vec<String>,
+}
+
+impl Policy {
+    /// Load the policy committed to `repo`, if there is one
+    pub fn load(repo: &Repository) -> Result<Option<Self>> {
+        let Some(path) = repo.workdir().map(|workdir| workdir.join(POLICY_FILE)) else {
+            return Ok(None);
+        };
+
+        if !path.is_file() {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read_to_string(&path).context("failed to read policy")?;
+        let policy = toml::from_str(&contents)
+            .with_context(|| format!("failed to parse {}", path.display()))?;
+        Ok(Some(policy))
+    }
+
+    /// Returns the config keys this policy overrides
+    pub fn overrides(&self) -> Vec<&'static str> {
+        let mut keys = Vec::new();
+        if self.allowed_upstreams.is_some() {
+            keys.push("default_upstream");
+        }
+        if self.draft.is_some() {
+            keys.push("submit.draft");
+        }
+        if self.allow_force.is_some() {
+            keys.push("submit.allow_force");
+        }
+        if !self.labels.is_empty() {
+            keys.push("submit.labels");
+        }
+        keys
+    }
+
+    /// Fail if stacks may not be submitted against `upstream`
+    pub fn check_upstream(&self, upstream: &str) -> Result<()> {
+        let Some(allowed) = &self.allowed_upstreams else {
+            return Ok(());
+        };
+
+        let mut matched = false;
+        for pattern in allowed {
+            let glob = Glob::new(pattern)
+                .with_context(|| format!("invalid upstream pattern {pattern}"))?;
+            matched |= glob.compile_matcher().is_match(upstream);
+        }
+
+        anyhow::ensure!(
+            matched,
+            "{POLICY_FILE} doesn't allow submitting against {upstream}, allowed upstreams are {}",
+            allowed.join(", ")
+        );
+        Ok(())
+    }
+
+    /// Merge the policy into `config`, failing if the config breaks a rule that can't be
+    /// overridden
+    pub fn apply(&self, config: &mut Config) -> Result<()> {
+        self.check_upstream(&config.default_upstream)?;
+
+        if let Some(draft) = self.draft {
+            config.submit.draft = draft;
+        }
+
+        if let Some(allow_force) = self.allow_force {
+            config.submit.allow_force = allow_force;
+        }
+
+        for label in &self.labels {
+            if !config.submit.labels.contains(label) {
+                config.submit.labels.push(label.clone());
+            }
+        }
+
+        // Commands still need the rules that per-commit settings like trailers can't break
+        config.policy = Some(self.clone());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,25 @@
+use std::io::{BufRead, IsTerminal, Write};
+
+use anyhow::{Context, Result};
+
+/// Ask the user a yes/no question on the terminal, defaulting to no. Fails if stdin is not a
+/// terminal, since there is no one to answer
+pub fn confirm(question: &str) -> Result<bool> {
+    let stdin = std::io::stdin();
+    anyhow::ensure!(
+        stdin.is_terminal(),
+        "{question} (not a terminal, can't ask)"
+    );
+
+    let mut stderr = std::io::stderr();
+    write!(stderr, "{question} [y/N] ").context("failed to write prompt")?;
+    stderr.flush().context("failed to flush prompt")?;
+
+    let mut answer = String::new();
+    stdin
+        .lock()
+        .read_line(&mut answer)
+        .context("failed to read answer")?;
+
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
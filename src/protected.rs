@@ -0,0 +1,90 @@
+use anyhow::{Context, Result};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use octocrab::Octocrab;
+use serde_json::json;
+
+use crate::gh::{self, GHRepo};
+
+/// Branches fel never pushes to, on top of the upstream branch, config and the repo's rules
+const DEFAULT: &[&str] = &["main", "master", "release/*"];
+
+const RULES: &str = "
+query($owner: String!, $repo: String!) {
+    repository(owner: $owner, name: $repo) {
+        branchProtectionRules(first: 100) { nodes { pattern } }
+    }
+}";
+
+/// Branch name patterns that a misconfigured prefix or template must not be able to push over.
+/// `*` matches across slashes, so a pattern protects at least as much as it does on GitHub
+pub struct Protected {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Protected {
+    pub fn new(patterns: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut builder = GlobSetBuilder::new();
+        let mut all = Vec::new();
+        let defaults = DEFAULT.iter().map(|pattern| pattern.to_string());
+        for pattern in defaults.chain(patterns) {
+            let glob = Glob::new(&pattern)
+                .with_context(|| format!("invalid protected branch pattern {pattern}"))?;
+            builder.add(glob);
+            all.push(pattern);
+        }
+        let set = builder
+            .build()
+            .context("failed to build protected branches")?;
+        Ok(Self { patterns: all, set })
+    }
+
+    /// Fail if pushing to `branch` could overwrite a protected branch
+    pub fn check(&self, branch: &str) -> Result<()> {
+        if let Some(index) = self.set.matches(branch).first() {
+            anyhow::bail!(
+                "refusing to push to {branch}, it matches the protected branch pattern {}, check \
+                submit.branch_prefix and the branches in the stack's notes",
+                self.patterns[*index]
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Fetch the patterns of the repo's branch protection rules. Only admins can read them, so
+/// when they can't be read there are none
+pub async fn rules(octocrab: &Octocrab, gh_repo: &GHRepo) -> Vec<String> {
+    let variables = json!({ "owner": gh_repo.owner, "repo": gh_repo.repo });
+    let data = match gh::graphql(octocrab, RULES, variables).await {
+        Ok(data) => data,
+        Err(error) => {
+            tracing::debug!("can't read branch protection rules: {error:#}");
+            return Vec::new();
+        }
+    };
+
+    data["repository"]["branchProtectionRules"]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|rule| rule["pattern"].as_str())
+        .map(str::to_string)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protects_defaults_and_patterns() {
+        let protected = Protected::new(["stable-*".to_string()]).unwrap();
+        for branch in ["main", "release/1.2", "release/1.2/hotfix", "stable-3"] {
+            assert!(protected.check(branch).is_err(), "{branch}");
+        }
+        for branch in ["fel/stack/1a2b", "alice/main", "mainline"] {
+            assert!(protected.check(branch).is_ok(), "{branch}");
+        }
+    }
+}
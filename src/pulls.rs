@@ -0,0 +1,266 @@
+use anyhow::{Context, Result};
+use octocrab::{models::pulls::PullRequest, Octocrab};
+use serde_json::{json, Value};
+
+use crate::config::{Api, Transport};
+use crate::gh::{self, GHRepo};
+
+const PR_FIELDS: &str = "
+fragment pr on PullRequest {
+    id databaseId number title body url state isDraft mergedAt
+    baseRefName baseRefOid headRefName headRefOid
+    headRepositoryOwner { login }
+}";
+
+const REPO_ID: &str = "
+query($owner: String!, $repo: String!) {
+    repository(owner: $owner, name: $repo) { id }
+}";
+
+const PR_ID: &str = "
+query($owner: String!, $repo: String!, $number: Int!) {
+    repository(owner: $owner, name: $repo) { pullRequest(number: $number) { id } }
+}";
+
+const CREATE: &str = "
+mutation($input: CreatePullRequestInput!) {
+    createPullRequest(input: $input) { pullRequest { ...pr } }
+}";
+
+const UPDATE: &str = "
+mutation($input: UpdatePullRequestInput!) {
+    updatePullRequest(input: $input) { pullRequest { id } }
+}";
+
+const LIST: &str = "
+query($owner: String!, $repo: String!, $head: String!) {
+    repository(owner: $owner, name: $repo) {
+        pullRequests(headRefName: $head, states: OPEN, first: 20) { nodes { ...pr } }
+    }
+}";
+
+/// Changes to make to a PR, leaving anything that is `None` alone
+#[derive(Default)]
+pub struct Update {
+    pub base: Option<String>,
+    pub title: Option<String>,
+    pub body: Option<String>,
+    pub close: bool,
+}
+
+/// Creates, updates and lists PRs through whichever API `[api]` picks for each operation
+pub struct Pulls<'a> {
+    octocrab: &'a Octocrab,
+    gh_repo: &'a GHRepo,
+    api: Api,
+}
+
+/// Returns true if GitHub refused to create a PR because the branch already has one
+pub fn already_exists(err: &crate::Error) -> bool {
+    const MESSAGE: &str = "A pull request already exists";
+    let crate::Error::Forge { error, .. } = err else {
+        return false;
+    };
+    error.reasons.iter().any(|reason| reason.contains(MESSAGE))
+}
+
+impl<'a> Pulls<'a> {
+    pub fn new(octocrab: &'a Octocrab, gh_repo: &'a GHRepo, api: Api) -> Self {
+        Self {
+            octocrab,
+            gh_repo,
+            api,
+        }
+    }
+
+    /// Open a PR merging `head`, which is `owner:branch` for a fork, into `base`
+    pub async fn create(
+        &self,
+        title: &str,
+        head: &str,
+        base: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest> {
+        match self.api.create {
+            Transport::Rest => Ok(self
+                .octocrab
+                .pulls(&self.gh_repo.owner, &self.gh_repo.repo)
+                .create(title, head, base)
+                .body(body)
+                .draft(draft)
+                .send()
+                .await?),
+            Transport::Graphql => {
+                let repo = self.query(REPO_ID, json!({})).await?;
+                let input = json!({
+                    "repositoryId": repo["repository"]["id"],
+                    "title": title,
+                    "headRefName": head,
+                    "baseRefName": base,
+                    "body": body,
+                    "draft": draft,
+                });
+                let query = format!("{CREATE}{PR_FIELDS}");
+                let data = gh::graphql(self.octocrab, &query, json!({ "input": input })).await?;
+                to_rest(&data["createPullRequest"]["pullRequest"])
+            }
+        }
+    }
+
+    pub async fn update(&self, number: u64, update: Update) -> Result<()> {
+        match self.api.update {
+            Transport::Rest => {
+                let pulls = self.octocrab.pulls(&self.gh_repo.owner, &self.gh_repo.repo);
+                let mut request = pulls.update(number);
+                if let Some(base) = update.base {
+                    request = request.base(base);
+                }
+                if let Some(title) = update.title {
+                    request = request.title(title);
+                }
+                if let Some(body) = update.body {
+                    request = request.body(body);
+                }
+                if update.close {
+                    request = request.state(octocrab::params::pulls::State::Closed);
+                }
+                request.send().await?;
+            }
+            Transport::Graphql => {
+                let pr = self.query(PR_ID, json!({ "number": number })).await?;
+                let input = update_input(&pr["repository"]["pullRequest"]["id"], update);
+                gh::graphql(self.octocrab, UPDATE, json!({ "input": input })).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// List the open PRs from `branch` in `owner`'s repo
+    pub async fn list_open(&self, owner: &str, branch: &str) -> Result<Vec<PullRequest>> {
+        match self.api.list {
+            Transport::Rest => Ok(self
+                .octocrab
+                .pulls(&self.gh_repo.owner, &self.gh_repo.repo)
+                .list()
+                .head(format!("{owner}:{branch}"))
+                .state(octocrab::params::State::Open)
+                .send()
+                .await?
+                .items),
+            Transport::Graphql => {
+                let query = format!("{LIST}{PR_FIELDS}");
+                let data = self.query(&query, json!({ "head": branch })).await?;
+                let nodes = data["repository"]["pullRequests"]["nodes"]
+                    .as_array()
+                    .context("missing pull requests")?;
+
+                // GraphQL can only filter on the branch name, not which fork it's in
+                nodes
+                    .iter()
+                    .filter(|pr| pr["headRepositoryOwner"]["login"] == owner)
+                    .map(to_rest)
+                    .collect()
+            }
+        }
+    }
+
+    /// Run a query about this repo, which takes its `$owner` and `$repo`
+    async fn query(&self, query: &str, mut variables: Value) -> Result<Value> {
+        variables["owner"] = json!(self.gh_repo.owner);
+        variables["repo"] = json!(self.gh_repo.repo);
+        gh::graphql(self.octocrab, query, variables).await
+    }
+}
+
+/// Convert a PR from GraphQL into the shape the REST API returns, for the fields fel reads
+fn to_rest(pr: &Value) -> Result<PullRequest> {
+    let state = match pr["state"].as_str() {
+        Some("OPEN") => "open",
+        _ => "closed",
+    };
+    let rest = json!({
+        "url": pr["url"],
+        "id": pr["databaseId"],
+        "node_id": pr["id"],
+        "html_url": pr["url"],
+        "number": pr["number"],
+        "state": state,
+        "title": pr["title"],
+        "body": pr["body"],
+        "merged_at": pr["mergedAt"],
+        "draft": pr["isDraft"],
+        "head": { "ref": pr["headRefName"], "sha": pr["headRefOid"] },
+        "base": { "ref": pr["baseRefName"], "sha": pr["baseRefOid"] },
+    });
+    serde_json::from_value(rest).context("unexpected pull request from graphql")
+}
+
+/// The input to `updatePullRequest` for PR `id`. A null would clear a field, so only the ones
+/// that change are sent
+fn update_input(id: &Value, update: Update) -> Value {
+    let mut input = json!({ "pullRequestId": id });
+    if let Some(base) = update.base {
+        input["baseRefName"] = json!(base);
+    }
+    if let Some(title) = update.title {
+        input["title"] = json!(title);
+    }
+    if let Some(body) = update.body {
+        input["body"] = json!(body);
+    }
+    if update.close {
+        input["state"] = json!("CLOSED");
+    }
+    input
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn graphql_pr_reads_like_rest() {
+        let pr = to_rest(&json!({
+            "id": "PR_kwDOA",
+            "databaseId": 1234,
+            "number": 12,
+            "title": "Add a thing",
+            "body": "body",
+            "url": "https://github.com/owner/repo/pull/12",
+            "state": "MERGED",
+            "isDraft": false,
+            "mergedAt": "2024-01-02T03:04:05Z",
+            "baseRefName": "main",
+            "baseRefOid": "aaaa",
+            "headRefName": "fel/stack/0",
+            "headRefOid": "bbbb",
+            "headRepositoryOwner": { "login": "owner" },
+        }))
+        .unwrap();
+
+        assert_eq!(pr.number, 12);
+        assert_eq!(pr.node_id.as_deref(), Some("PR_kwDOA"));
+        assert_eq!(pr.state, Some(octocrab::models::IssueState::Closed));
+        assert!(pr.merged_at.is_some());
+        assert_eq!(pr.base.ref_field, "main");
+        assert_eq!(pr.head.ref_field, "fel/stack/0");
+        assert_eq!(
+            pr.html_url.map(|url| url.to_string()).as_deref(),
+            Some("https://github.com/owner/repo/pull/12")
+        );
+    }
+
+    #[test]
+    fn close_only_update() {
+        let close = Update {
+            close: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            update_input(&json!("PR_kwDOA"), close),
+            json!({ "pullRequestId": "PR_kwDOA", "state": "CLOSED" })
+        );
+    }
+}
@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
 
 use anyhow::Context;
 use anyhow::Result;
@@ -11,6 +10,8 @@ use parking_lot::Mutex;
 use tokio::sync::oneshot;
 use tokio::sync::Notify;
 
+use crate::auth;
+
 type PushResult = Result<(), PushError>;
 
 struct PendingPush {
@@ -37,10 +38,11 @@ struct Refspec {
     force: bool,
 }
 
-impl ToString for Refspec {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Refspec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let refname = self.refname();
-        format!(
+        write!(
+            f,
             "{}{}:{}",
             if self.force { "+" } else { "" },
             self.commit,
@@ -60,10 +62,8 @@ impl Refspec {
     }
 
     fn refname(&self) -> String {
-        PathBuf::from("refs/heads")
-            .join(&self.branch)
-            .display()
-            .to_string()
+        // Refnames always use `/`, joining paths would use `\\` on Windows
+        format!("refs/heads/{}", self.branch)
     }
 }
 
@@ -81,7 +81,12 @@ impl BatchedPusher {
         Ok(result?)
     }
 
-    pub async fn wait_for(&self, count: usize, remote: &mut Remote<'_>) -> Result<()> {
+    pub async fn wait_for(
+        &self,
+        count: usize,
+        remote: &mut Remote<'_>,
+        proxy: Option<&str>,
+    ) -> Result<()> {
         tracing::debug!("waiting for pending pushes");
         let pending = loop {
             {
@@ -149,7 +154,11 @@ impl BatchedPusher {
         tokio::task::block_in_place(|| {
             remote.push(
                 &refspecs,
-                Some(PushOptions::default().remote_callbacks(callbacks)),
+                Some(
+                    PushOptions::default()
+                        .remote_callbacks(callbacks)
+                        .proxy_options(auth::proxy_options(proxy)),
+                ),
             )
         })
         .context("failed to push")?;
@@ -0,0 +1,88 @@
+use std::process::Command;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use git2::Repository;
+use octocrab::Octocrab;
+
+use crate::commit::Commit;
+use crate::gh::{self, GHRepo};
+use crate::stack::Stack;
+
+/// Longest range-diff posted in a PR comment, anything past this is trimmed
+const COMMENT_LINES: usize = 200;
+
+/// Show how commits in the stack changed since they were last submitted, either a single
+/// commit at `index` or every changed commit in the stack. Comments may take `timeout` each
+pub async fn range_diff(
+    stack: &Stack,
+    index: Option<usize>,
+    comment: bool,
+    repo: &Repository,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    timeout: Duration,
+) -> Result<()> {
+    let commits: Vec<&Commit> = match index {
+        Some(index) => vec![stack
+            .get(index)
+            .with_context(|| format!("no commit at index {index}"))?],
+        None => stack.iter().filter(|commit| commit.changed()).collect(),
+    };
+
+    for commit in commits {
+        let Some(previous) = &commit.metadata.commit else {
+            println!("{} has not been submitted yet", commit.title);
+            continue;
+        };
+
+        let ranges = [format!("{previous}^!"), format!("{}^!", commit.id())];
+        let status = git_range_diff(repo, &ranges)
+            .arg("--color=auto")
+            .status()
+            .context("failed to run git range-diff")?;
+        anyhow::ensure!(status.success(), "git range-diff failed");
+
+        let (true, Some(pr)) = (comment, commit.metadata.pr) else {
+            continue;
+        };
+
+        let output = git_range_diff(repo, &ranges)
+            .arg("--no-color")
+            .output()
+            .context("failed to run git range-diff")?;
+        anyhow::ensure!(output.status.success(), "git range-diff failed");
+
+        let output = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<_> = output.lines().collect();
+        let mut body = format!(
+            "Changes since {}\n\n```diff\n{}\n```",
+            &previous[..8],
+            lines[..lines.len().min(COMMENT_LINES)].join("\n")
+        );
+        if lines.len() > COMMENT_LINES {
+            body.push_str(&format!(
+                "\n\n{} more lines not shown",
+                lines.len() - COMMENT_LINES
+            ));
+        }
+
+        let issues = octocrab.issues(&gh_repo.owner, &gh_repo.repo);
+        let comment = issues.create_comment(pr, body);
+        gh::timeout(timeout, &format!("commenting on PR {pr}"), comment)
+            .await?
+            .with_context(|| format!("failed to comment on PR {pr}"))?;
+    }
+
+    Ok(())
+}
+
+fn git_range_diff(repo: &Repository, ranges: &[String]) -> Command {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo.path())
+        .arg("range-diff")
+        .args(ranges);
+    command
+}
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use git2::Oid;
+use octocrab::Octocrab;
+use serde_json::json;
+
+use crate::commit::Commit;
+use crate::gh::{self, GHRepo};
+use crate::reviewers::{self, Reviewers};
+use crate::stack::Stack;
+
+const READY_FOR_REVIEW: &str = r#"
+mutation($id: ID!) {
+  markPullRequestReadyForReview(input: { pullRequestId: $id }) {
+    pullRequest { number }
+  }
+}
+"#;
+
+/// Mark draft PRs as ready for review, either the PR for the commit at `index` or every PR
+/// in the stack. Defaults to the top of the stack. Reviews are requested on each PR from
+/// `reviewers`. Each request may take `timeout`
+pub async fn ready(
+    stack: &Stack,
+    index: Option<usize>,
+    all: bool,
+    reviewers: &HashMap<Oid, Reviewers>,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    timeout: Duration,
+) -> Result<()> {
+    let commits: Vec<&Commit> = match (all, index) {
+        (true, _) => stack.iter().collect(),
+        (false, Some(index)) => vec![stack
+            .get(index)
+            .with_context(|| format!("no commit at index {index}"))?],
+        (false, None) => vec![stack.iter().last().context("stack is empty")?],
+    };
+
+    for commit in commits {
+        let Some(number) = commit.metadata.pr else {
+            println!("{} has not been submitted yet", commit.title);
+            continue;
+        };
+
+        let pulls = octocrab.pulls(&gh_repo.owner, &gh_repo.repo);
+        let pr = gh::timeout(timeout, &format!("getting PR {number}"), pulls.get(number))
+            .await?
+            .with_context(|| format!("failed to get PR {number}"))?;
+
+        if pr.draft == Some(true) {
+            let id = pr.node_id.as_deref().context("PR has no node id")?;
+            let ready = gh::graphql(octocrab, READY_FOR_REVIEW, json!({ "id": id }));
+            gh::timeout(timeout, &format!("marking PR {number} ready"), ready)
+                .await?
+                .with_context(|| format!("failed to mark PR {number} ready for review"))?;
+            println!("#{number} is ready for review");
+        } else {
+            println!("#{number} is already ready for review");
+        }
+
+        if let Some(reviewers) = reviewers.get(&commit.id()) {
+            let reviewers = reviewers::unrequested(&pr, reviewers);
+            if !reviewers.is_empty() {
+                let request = reviewers::request(
+                    octocrab,
+                    gh_repo,
+                    number,
+                    &reviewers.users,
+                    &reviewers.teams,
+                );
+                gh::timeout(
+                    timeout,
+                    &format!("requesting reviews on PR {number}"),
+                    request,
+                )
+                .await??;
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,98 @@
+use anyhow::{Context, Result};
+use git2::{Direction, PushOptions, Remote, Repository};
+
+use crate::auth;
+use crate::config::Config;
+use crate::gh::{self, GHRepo};
+
+/// Find the remote to push to. Other remotes that point at the same GitHub repo as `remote`,
+/// over SSH and HTTPS for example, are also candidates, and the first one that accepts a
+/// connection is used. PRs are always made against `gh_repo`, whichever remote is pushed to
+pub fn push_remote<'repo>(
+    repo: &'repo Repository,
+    remote: Remote<'repo>,
+    gh_repo: &GHRepo,
+    proxy: Option<&str>,
+) -> Result<Remote<'repo>> {
+    let names = repo.remotes().context("failed to list remotes")?;
+    let mut candidates = vec![remote];
+    for name in names.iter().flatten() {
+        if Some(name) == candidates[0].name() {
+            continue;
+        }
+
+        let other = repo.find_remote(name).context("failed to get remote")?;
+        match gh::get_repo(&other) {
+            Ok(other_repo) if other_repo.same_repo(gh_repo) => candidates.push(other),
+            _ => {}
+        }
+    }
+
+    if candidates.len() == 1 {
+        return Ok(candidates.remove(0));
+    }
+
+    // Fall back to the configured remote if none are reachable, so pushing reports the error
+    let mut index = 0;
+    for (i, candidate) in candidates.iter_mut().enumerate() {
+        let name = candidate.name().unwrap_or_default().to_string();
+        let connection = candidate.connect_auth(
+            Direction::Push,
+            Some(auth::callbacks()),
+            Some(auth::proxy_options(proxy)),
+        );
+        match connection {
+            Ok(_) => {
+                tracing::debug!(remote = name, "found reachable remote");
+                index = i;
+                break;
+            }
+            Err(e) => tracing::debug!(remote = name, ?e, "remote unreachable"),
+        }
+    }
+
+    Ok(candidates.swap_remove(index))
+}
+
+/// Find the remote branches are pushed to: `push_remote` from config when contributing from a
+/// fork, otherwise `remote` or another remote for the same repo, as `push_remote` picks
+pub fn configured<'repo>(
+    repo: &'repo Repository,
+    remote: Remote<'repo>,
+    gh_repo: &GHRepo,
+    config: &Config,
+) -> Result<Remote<'repo>> {
+    let proxy = config.proxy.as_deref();
+    match &config.push_remote {
+        Some(name) => {
+            let fork = repo
+                .find_remote(name)
+                .with_context(|| format!("failed to get push remote {name}"))?;
+            let fork_repo = gh::get_repo(&fork).context("failed to get fork")?;
+            push_remote(repo, fork, &fork_repo, proxy)
+        }
+        None => push_remote(repo, remote, gh_repo, proxy),
+    }
+}
+
+/// Delete `branches` from `remote` in one push
+pub fn delete_branches(
+    remote: &mut Remote,
+    branches: &[String],
+    proxy: Option<&str>,
+) -> Result<()> {
+    if branches.is_empty() {
+        return Ok(());
+    }
+
+    let refspecs: Vec<_> = branches
+        .iter()
+        .map(|branch| format!(":refs/heads/{branch}"))
+        .collect();
+    let mut options = PushOptions::new();
+    options.remote_callbacks(auth::callbacks());
+    options.proxy_options(auth::proxy_options(proxy));
+    remote
+        .push(&refspecs, Some(&mut options))
+        .context("failed to delete branches")
+}
@@ -0,0 +1,76 @@
+use std::collections::BTreeSet;
+
+use anyhow::{Context, Result};
+use git2::{Branch, BranchType, Repository};
+
+use crate::metadata::NoteBatch;
+use crate::stack::Stack;
+use crate::state::State;
+
+/// Rename `stack` to `name`, renaming its branch unless it already has that name, like after
+/// `git branch -m`. The notes of its submitted commits and what fel remembers about the stack
+/// move to the new name. PRs keep their head branches, since GitHub can't change them, but
+/// commits submitted from now on get branches under the new name
+pub fn rename(repo: &Repository, stack: &Stack, name: &str) -> Result<()> {
+    anyhow::ensure!(
+        !stack.is_detached(),
+        "HEAD is detached, check out the stack's branch to rename it"
+    );
+    anyhow::ensure!(
+        Branch::name_is_valid(name).context("failed to check branch name")?,
+        "{name} is not a valid branch name"
+    );
+
+    // Every name the stack was submitted under, including ones from branches renamed by hand
+    let mut previous: BTreeSet<String> = stack
+        .iter()
+        .filter_map(|commit| commit.metadata.stack.clone())
+        .collect();
+    previous.insert(stack.name().to_string());
+    previous.remove(name);
+    anyhow::ensure!(!previous.is_empty(), "the stack is already named {name}");
+
+    if stack.name() != name {
+        anyhow::ensure!(
+            repo.find_branch(name, BranchType::Local).is_err(),
+            "a branch named {name} already exists"
+        );
+        repo.find_branch(stack.name(), BranchType::Local)
+            .with_context(|| format!("failed to find branch {}", stack.name()))?
+            .rename(name, false)
+            .with_context(|| format!("failed to rename {} to {name}", stack.name()))?;
+    }
+
+    let mut notes = NoteBatch::default();
+    let mut updated = 0;
+    for commit in stack.iter() {
+        if commit
+            .metadata
+            .stack
+            .as_ref()
+            .is_some_and(|stack| stack != name)
+        {
+            let mut metadata = commit.metadata.clone();
+            metadata.stack = Some(name.to_string());
+            notes
+                .add(&metadata, commit.id())
+                .with_context(|| format!("failed to update the note on {}", commit.title))?;
+            updated += 1;
+        }
+    }
+    notes.write(repo).context("failed to update notes")?;
+
+    let mut state = State::load(repo)?;
+    for old in &previous {
+        state.rename_stack(old, name);
+    }
+    state.save().context("failed to save state")?;
+
+    let previous: Vec<_> = previous.into_iter().collect();
+    println!(
+        "renamed {} to {name} and updated {updated} notes, run `fel submit` to show the new name \
+        in PR footers",
+        previous.join(", ")
+    );
+    Ok(())
+}
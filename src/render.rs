@@ -0,0 +1,329 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::sync::OnceLock;
+
+use anyhow::{Context, Result};
+use indicatif::ProgressStyle;
+use tera::{Tera, Value};
+
+/// Spinner frames for terminals that can't draw the default braille ones. The last one is
+/// shown once the spinner finishes
+const ASCII_SPINNER: &str = "|/-\\ ";
+
+static ASCII: OnceLock<bool> = OnceLock::new();
+
+/// Only draw with ASCII if `ascii` is set, or if it isn't and the locale isn't UTF-8. Trees
+/// and footers are always ASCII, this covers everything else fel draws
+pub fn init_ascii(ascii: Option<bool>) {
+    ASCII.set(ascii.unwrap_or_else(|| !utf8_locale())).ok();
+}
+
+/// Returns true if output has to be ASCII
+pub fn ascii() -> bool {
+    ASCII.get().copied().unwrap_or(false)
+}
+
+/// The style of fel's spinners, ASCII if output has to be
+pub fn spinner() -> ProgressStyle {
+    match ascii() {
+        true => ProgressStyle::default_spinner().tick_chars(ASCII_SPINNER),
+        false => ProgressStyle::default_spinner(),
+    }
+}
+
+/// Returns true if the locale, from the first of `LC_ALL`, `LC_CTYPE` and `LANG` that is set,
+/// uses UTF-8
+fn utf8_locale() -> bool {
+    let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+        .iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|locale| !locale.is_empty())
+        .unwrap_or_default()
+        .to_lowercase();
+    locale.contains("utf-8") || locale.contains("utf8")
+}
+
+/// Create a Tera instance holding `template` under `name`, with fel's filters registered.
+/// Templates with names ending in `.html` are autoescaped
+pub fn tera(name: &str, template: &str) -> Result<Tera> {
+    let mut tera = Tera::default();
+    tera.register_filter("short_sha", short_sha);
+    tera.register_filter("escape_html", escape_html);
+    tera.add_raw_template(name, template)
+        .with_context(|| format!("invalid template {name}"))?;
+    Ok(tera)
+}
+
+/// Shorten a commit sha to `len` characters, 8 by default
+fn short_sha(value: &Value, args: &HashMap<String, Value>) -> tera::Result<Value> {
+    let sha = tera::try_get_value!("short_sha", "value", String, value);
+    let len = match args.get("len") {
+        Some(len) => tera::try_get_value!("short_sha", "len", usize, len),
+        None => 8,
+    };
+    Ok(Value::String(sha.chars().take(len).collect()))
+}
+
+/// Escape HTML, for templates that aren't autoescaped
+fn escape_html(value: &Value, _: &HashMap<String, Value>) -> tera::Result<Value> {
+    let text = tera::try_get_value!("escape_html", "value", String, value);
+    Ok(Value::String(tera::escape_html(&text)))
+}
+
+/// A PR in a stack, as renderers see it
+#[derive(serde::Serialize, Clone, Debug)]
+pub struct PrInfo {
+    pub number: u64,
+    pub title: String,
+    pub url: String,
+    pub commit: String,
+    pub branch: String,
+    pub revision: u32,
+    pub author: String,
+    pub index: usize,
+    /// The branch the PR is made against when its commit starts a segment of the stack with
+    /// `Fel-Base`, and that branch's url
+    pub base: Option<String>,
+    pub base_url: Option<String>,
+    /// The PR the commit was picked from, and the PRs it was backported in
+    pub picked_from: Option<u64>,
+    pub backports: Vec<u64>,
+    /// One of `open`, `draft`, `closed` or `merged`
+    pub status: &'static str,
+    /// The commit's `fel meta` keys
+    pub custom: BTreeMap<String, String>,
+}
+
+/// Everything known about a stack when its footer is rendered. Templates see these fields as
+/// variables of the same names
+#[derive(serde::Serialize, Clone, Debug, Default)]
+pub struct RenderStore {
+    pub stack_name: String,
+    pub stack_size: usize,
+    /// Every PR in the stack, from the top down
+    pub prs: Vec<PrInfo>,
+    /// The branch the bottom of the stack is made against
+    pub upstream: String,
+    pub upstream_url: String,
+    /// Diff of the whole stack against upstream
+    pub stack_url: Option<String>,
+    pub overview_url: Option<String>,
+    /// The stack this one is built on and the PR it is built on
+    pub depends_on_stack: Option<String>,
+    pub depends_on_pr: Option<u64>,
+    /// The discussion holding the stack's tree, if it has one
+    pub url: Option<String>,
+}
+
+/// Renders what fel adds to PRs and issues for a stack. Submit only talks to a renderer, so a
+/// different one can produce another format entirely
+pub trait StackRenderer: Send + Sync {
+    /// The footer added to every PR, which is also mirrored into the discussion
+    fn footer(&self, store: &RenderStore) -> Result<String>;
+
+    /// The footer PRs get instead when the stack has a discussion, linking to it
+    fn discussion_footer(&self, store: &RenderStore) -> Result<String>;
+
+    /// The body of the overview issue
+    fn overview(&self, store: &RenderStore) -> Result<String>;
+}
+
+/// Renders with Tera templates, the built in ones unless config replaces the footer
+pub struct TeraRenderer {
+    footer: String,
+}
+
+impl TeraRenderer {
+    pub fn new(footer_template: Option<String>) -> Self {
+        Self {
+            footer: footer_template
+                .unwrap_or_else(|| include_str!("../templates/footer.html").to_string()),
+        }
+    }
+
+    fn render(name: &str, template: &str, store: &RenderStore) -> Result<String> {
+        let context = tera::Context::from_serialize(store).context("invalid render store")?;
+        tera(name, template)?
+            .render(name, &context)
+            .with_context(|| format!("failed to render {name}"))
+    }
+}
+
+impl StackRenderer for TeraRenderer {
+    fn footer(&self, store: &RenderStore) -> Result<String> {
+        Self::render("footer.html", &self.footer, store)
+    }
+
+    fn discussion_footer(&self, store: &RenderStore) -> Result<String> {
+        Self::render(
+            "discussion_footer.html",
+            include_str!("../templates/discussion_footer.html"),
+            store,
+        )
+    }
+
+    fn overview(&self, store: &RenderStore) -> Result<String> {
+        Self::render(
+            "overview.md",
+            include_str!("../templates/overview.md"),
+            store,
+        )
+    }
+}
+
+/// A node of a tree drawn by [`tree`]
+pub struct TreeNode<'a> {
+    pub label: &'a str,
+    /// Index of the node's parent in the same slice, or None for a root
+    pub parent: Option<usize>,
+}
+
+/// Draw a tree the way `git log --graph` does, with children above their parents. Siblings are
+/// drawn newest, highest index, first, and each subtree is drawn before moving on to the next
+pub fn tree(nodes: &[TreeNode]) -> Vec<String> {
+    let mut children = vec![Vec::new(); nodes.len()];
+    let mut roots = Vec::new();
+    for (index, node) in nodes.iter().enumerate() {
+        match node.parent {
+            Some(parent) => children[parent].push(index),
+            None => roots.push(index),
+        }
+    }
+
+    // Every subtree is drawn before its root, so the only columns to the right of the first
+    // one waiting for a node are the other ones waiting for it
+    let mut order = Vec::with_capacity(nodes.len());
+    for root in roots.into_iter().rev() {
+        subtree_order(root, &children, &mut order);
+    }
+
+    let mut lines = Vec::new();
+    // The node each column is waiting to reach
+    let mut columns: Vec<usize> = Vec::new();
+    for index in order {
+        let column = match columns.iter().position(|waiting| *waiting == index) {
+            Some(column) => column,
+            None => {
+                columns.push(index);
+                columns.len() - 1
+            }
+        };
+
+        // Fold the other branches into the first, one step to the left per line
+        while columns.len() > column + 1 && columns[column + 1] == index {
+            let folding = columns.len() - column - 1;
+            let line = format!("{}|{}", "| ".repeat(column), "/ ".repeat(folding));
+            lines.push(line.trim_end().to_string());
+            columns.remove(column + 1);
+        }
+
+        let mut line = String::new();
+        for position in 0..columns.len() {
+            line.push(if position == column { '*' } else { '|' });
+            line.push(' ');
+        }
+        line.push_str(nodes[index].label);
+        lines.push(line.trim_end().to_string());
+
+        match nodes[index].parent {
+            Some(parent) => columns[column] = parent,
+            None => {
+                columns.remove(column);
+                // Shift the columns to the right of it over
+                if column < columns.len() {
+                    let shifting = columns.len() - column;
+                    lines.push(format!("{}{}", "| ".repeat(column), " /".repeat(shifting)));
+                }
+            }
+        }
+    }
+
+    lines
+}
+
+/// Push `node` and everything above it onto `order`, in the order they are drawn
+fn subtree_order(node: usize, children: &[Vec<usize>], order: &mut Vec<usize>) {
+    for child in children[node].iter().rev() {
+        subtree_order(*child, children, order);
+    }
+    order.push(node);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn draw(nodes: &[(&'static str, Option<usize>)]) -> String {
+        let nodes: Vec<_> = nodes
+            .iter()
+            .map(|(label, parent)| TreeNode {
+                label,
+                parent: *parent,
+            })
+            .collect();
+        tree(&nodes).join("\n")
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(draw(&[]), "");
+    }
+
+    #[test]
+    fn linear() {
+        let nodes = [("main", None), ("a", Some(0)), ("b", Some(1))];
+        assert_eq!(draw(&nodes), "* b\n* a\n* main");
+    }
+
+    #[test]
+    fn fork() {
+        let nodes = [("main", None), ("a", Some(0)), ("b", Some(0))];
+        assert_eq!(draw(&nodes), "* b\n| * a\n|/\n* main");
+    }
+
+    #[test]
+    fn fork_with_long_branches() {
+        let nodes = [
+            ("main", None),
+            ("a1", Some(0)),
+            ("a2", Some(1)),
+            ("b1", Some(0)),
+            ("b2", Some(3)),
+        ];
+        assert_eq!(draw(&nodes), "* b2\n* b1\n| * a2\n| * a1\n|/\n* main");
+    }
+
+    #[test]
+    fn three_children() {
+        let nodes = [
+            ("main", None),
+            ("a", Some(0)),
+            ("b", Some(0)),
+            ("c", Some(0)),
+        ];
+        assert_eq!(draw(&nodes), "* c\n| * b\n| | * a\n|/ /\n|/\n* main");
+    }
+
+    #[test]
+    fn nested_fork() {
+        let nodes = [
+            ("main", None),
+            ("a", Some(0)),
+            ("b", Some(1)),
+            ("c", Some(1)),
+            ("d", Some(0)),
+        ];
+        assert_eq!(draw(&nodes), "* d\n| * c\n| | * b\n| |/\n| * a\n|/\n* main");
+    }
+
+    #[test]
+    fn multiple_roots() {
+        let nodes = [
+            ("main", None),
+            ("a", Some(0)),
+            ("other", None),
+            ("b", Some(2)),
+        ];
+        assert_eq!(draw(&nodes), "* b\n* other\n* a\n* main");
+    }
+}
@@ -0,0 +1,130 @@
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+
+use crate::commit;
+use crate::metadata::{Metadata, NoteBatch, NOTE_REF};
+use crate::prompt;
+use crate::stack::Stack;
+
+/// A note that isn't attached to any commit in the stack
+struct Orphan {
+    annotated: Oid,
+    metadata: Metadata,
+    patch_id: Option<Oid>,
+}
+
+/// A commit in the stack that lost its metadata, and the orphaned note it probably had
+pub struct Lost {
+    pub commit: Oid,
+    pub title: String,
+    /// The commit the orphaned note is attached to
+    pub annotated: Oid,
+    pub metadata: Metadata,
+    /// Why the note is thought to belong to the commit
+    pub reason: &'static str,
+}
+
+/// Find commits in the stack without fel metadata that lost it when they were rewritten
+/// without copying notes. Commits are matched to orphaned notes by patch-id, or by appearing
+/// in a note's submit history
+pub fn find_lost(stack: &Stack, repo: &Repository) -> Result<Vec<Lost>> {
+    let stack_ids: Vec<_> = stack.iter().map(|commit| commit.id()).collect();
+
+    let mut orphans = Vec::new();
+    let notes = match repo.notes(Some(NOTE_REF)) {
+        Ok(notes) => notes,
+        Err(error) if error.code() == git2::ErrorCode::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error).context("failed to list notes"),
+    };
+
+    for note in notes {
+        let (_, annotated) = note.context("failed to read note")?;
+        if stack_ids.contains(&annotated) {
+            continue;
+        }
+
+        let Ok(commit) = repo.find_commit(annotated) else {
+            continue;
+        };
+
+        let Ok(metadata) = Metadata::new(repo, &commit) else {
+            tracing::debug!(?annotated, "skipping unreadable note");
+            continue;
+        };
+
+        orphans.push(Orphan {
+            annotated,
+            patch_id: commit::patch_id(repo, annotated).ok(),
+            metadata,
+        });
+    }
+
+    let mut lost = Vec::new();
+    for commit in stack.iter().filter(|commit| commit.metadata.pr.is_none()) {
+        let id = commit.id().to_string();
+        let patch_id = commit::patch_id(repo, commit.id()).ok();
+
+        let found = orphans.iter().find_map(|orphan| {
+            let in_history = orphan.metadata.history.iter().flatten().any(|h| *h == id);
+            if in_history {
+                Some((orphan, "appears in its history"))
+            } else if patch_id.is_some() && orphan.patch_id == patch_id {
+                Some((orphan, "has the same patch-id"))
+            } else {
+                None
+            }
+        });
+
+        if let Some((orphan, reason)) = found {
+            lost.push(Lost {
+                commit: commit.id(),
+                title: commit.title.clone(),
+                annotated: orphan.annotated,
+                metadata: orphan.metadata.clone(),
+                reason,
+            });
+        }
+    }
+
+    Ok(lost)
+}
+
+impl Lost {
+    /// Describe the match, like `Link fix parser to PR #12 from 1a2b3c4d, which has the same
+    /// patch-id`
+    pub fn describe(&self) -> String {
+        let pr = self
+            .metadata
+            .pr
+            .map(|pr| format!("PR #{pr}"))
+            .unwrap_or("unsubmitted metadata".to_string());
+        format!(
+            "{} to {pr} from {}, which {}",
+            self.title,
+            &self.annotated.to_string()[..8],
+            self.reason
+        )
+    }
+}
+
+/// Re-attach fel metadata to commits in the stack that lost it when they were rewritten
+/// without notes.rewriteRef set, asking about each one unless `yes` is set
+pub fn rescue(stack: &Stack, repo: &Repository, yes: bool) -> Result<()> {
+    let mut notes = NoteBatch::default();
+    let mut rescued = 0;
+    for lost in find_lost(stack, repo)? {
+        let question = format!("Link {}?", lost.describe());
+        if yes || prompt::confirm(&question)? {
+            notes
+                .add(&lost.metadata, lost.commit)
+                .context("failed to write rescued metadata")?;
+            rescued += 1;
+        }
+    }
+    notes
+        .write(repo)
+        .context("failed to write rescued metadata")?;
+
+    println!("rescued metadata for {rescued} commits");
+    Ok(())
+}
@@ -0,0 +1,87 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+
+use crate::codeowners::{self, Owners};
+use crate::commit;
+use crate::config::OwnerRule;
+use crate::stack::Stack;
+
+/// Trailer requesting a review map for a commit. Its value is a comma separated list of
+/// path patterns to include, or `all`
+pub const TRAILER: &str = "Reviewed-ranges";
+
+/// A file changed by a commit, and the users and teams that should review it
+pub struct ReviewItem {
+    path: String,
+    owners: Vec<String>,
+}
+
+/// Collect the files to review for every commit in `stack` that asks for a review map
+pub fn review_maps(
+    stack: &Stack,
+    repo: &Repository,
+    owner_rules: &[OwnerRule],
+) -> Result<HashMap<Oid, Vec<ReviewItem>>> {
+    let mut maps = HashMap::new();
+    let mut owners = None;
+
+    for commit in stack.iter() {
+        let Some(ranges) = commit.trailer(TRAILER) else {
+            continue;
+        };
+
+        let owners = match &owners {
+            Some(owners) => owners,
+            None => {
+                owners.insert(Owners::load(repo, owner_rules).context("failed to load owners")?)
+            }
+        };
+
+        let ranges = match ranges.trim() {
+            "" | "all" => None,
+            ranges => Some(
+                ranges
+                    .split(',')
+                    .map(|range| codeowners::globs(range.trim()))
+                    .collect::<Result<Vec<_>>>()?,
+            ),
+        };
+
+        let items = commit::changed_files(repo, commit.id())?
+            .into_iter()
+            .filter(|path| match &ranges {
+                Some(ranges) => ranges.iter().any(|range| range.is_match(path)),
+                None => true,
+            })
+            .map(|path| ReviewItem {
+                owners: owners.owners(&path).to_vec(),
+                path,
+            })
+            .collect();
+
+        maps.insert(commit.id(), items);
+    }
+
+    Ok(maps)
+}
+
+/// Render a review checklist, keeping the boxes that were already checked in `body`
+pub fn render(items: &[ReviewItem], body: &str) -> String {
+    let mut map = String::from("**Review map**\n\n");
+    for item in items {
+        let path = format!("`{}`", item.path);
+        let checked = body
+            .lines()
+            .any(|line| line.trim_start().starts_with("- [x]") && line.contains(&path));
+
+        map.push_str(&format!(
+            "- [{}] {path} {}\n",
+            if checked { "x" } else { " " },
+            item.owners.join(" ")
+        ));
+    }
+    map.push('\n');
+    map
+}
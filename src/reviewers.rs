@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+use octocrab::models::pulls::PullRequest;
+use octocrab::Octocrab;
+use serde_json::json;
+
+use crate::codeowners::Owners;
+use crate::commit;
+use crate::config::Config;
+use crate::gh::GHRepo;
+use crate::stack::Stack;
+
+/// Users and teams to request reviews from on a PR
+#[derive(Default, Clone, Debug)]
+pub struct Reviewers {
+    pub users: Vec<String>,
+    /// Team slugs, without the org
+    pub teams: Vec<String>,
+}
+
+impl Reviewers {
+    /// Add a reviewer written as `user`, `@user` or `@org/team`. Email addresses, which
+    /// CODEOWNERS allows, can't be requested as reviewers and are skipped
+    pub fn add(&mut self, reviewer: &str) {
+        let reviewer = reviewer.trim().trim_start_matches('@');
+        if reviewer.is_empty() || reviewer.contains('@') {
+            return;
+        }
+
+        let (list, name) = match reviewer.split_once('/') {
+            Some((_, team)) => (&mut self.teams, team),
+            None => (&mut self.users, reviewer),
+        };
+
+        if !list.iter().any(|existing| existing == name) {
+            list.push(name.to_string());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.users.is_empty() && self.teams.is_empty()
+    }
+
+    /// Add a team written as `org/team`, `@org/team` or just its slug
+    pub fn add_team(&mut self, team: &str) {
+        let team = team.trim().trim_start_matches('@');
+        let slug = team.rsplit_once('/').map(|(_, slug)| slug).unwrap_or(team);
+        if !slug.is_empty() && !self.teams.iter().any(|existing| existing == slug) {
+            self.teams.push(slug.to_string());
+        }
+    }
+}
+
+/// Trailer listing teams to request reviews from, separated by commas
+pub const TEAM_TRAILER: &str = "Team-Reviewers";
+
+/// Collect the reviewers for each commit in `stack` from the defaults in config, the
+/// `Team-Reviewers` and `Fel-Reviewers` trailers and, if enabled, the owners of the files
+/// the commit changes
+pub fn for_stack(
+    stack: &Stack,
+    repo: &Repository,
+    config: &Config,
+) -> Result<HashMap<Oid, Reviewers>> {
+    let owners = match config.submit.codeowners_reviewers {
+        true => Some(Owners::load(repo, &config.owners).context("failed to load owners")?),
+        false => None,
+    };
+
+    let mut reviewers = HashMap::new();
+    for commit in stack.iter() {
+        let mut commit_reviewers = Reviewers::default();
+        for user in &config.submit.default_reviewers {
+            commit_reviewers.add(user);
+        }
+        for team in &config.submit.default_team_reviewers {
+            commit_reviewers.add_team(team);
+        }
+        for team in commit
+            .trailer(TEAM_TRAILER)
+            .into_iter()
+            .flat_map(|t| t.split(','))
+        {
+            commit_reviewers.add_team(team);
+        }
+        for reviewer in &commit.options.reviewers {
+            commit_reviewers.add(reviewer);
+        }
+
+        if let Some(owners) = &owners {
+            for path in commit::changed_files(repo, commit.id())? {
+                for owner in owners.owners(&path) {
+                    commit_reviewers.add(owner);
+                }
+            }
+        }
+
+        reviewers.insert(commit.id(), commit_reviewers);
+    }
+    Ok(reviewers)
+}
+
+/// Returns the reviewers in `reviewers` that haven't been asked to review `pr` yet, other than
+/// the PR's author, who GitHub won't accept
+pub fn unrequested(pr: &PullRequest, reviewers: &Reviewers) -> Reviewers {
+    let author = pr.user.as_ref().map(|user| user.login.as_str());
+    let requested_users: Vec<_> = pr.requested_reviewers.iter().flatten().collect();
+    let requested_teams: Vec<_> = pr.requested_teams.iter().flatten().collect();
+
+    Reviewers {
+        users: reviewers
+            .users
+            .iter()
+            .filter(|user| Some(user.as_str()) != author)
+            .filter(|user| !requested_users.iter().any(|r| &&r.login == user))
+            .cloned()
+            .collect(),
+        teams: reviewers
+            .teams
+            .iter()
+            .filter(|team| !requested_teams.iter().any(|r| &&r.slug == team))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Request reviews on PR `number` from `users` and `teams`
+pub async fn request(
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    number: u64,
+    users: &[String],
+    teams: &[String],
+) -> Result<()> {
+    tracing::debug!(pr = number, ?users, ?teams, "requesting reviews");
+    let route = format!(
+        "/repos/{}/{}/pulls/{number}/requested_reviewers",
+        gh_repo.owner, gh_repo.repo
+    );
+    let _: serde_json::Value = octocrab
+        .post(
+            route,
+            Some(&json!({ "reviewers": users, "team_reviewers": teams })),
+        )
+        .await
+        .context("failed to request reviews")?;
+
+    Ok(())
+}
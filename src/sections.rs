@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::{Oid, Repository};
+
+use crate::config::Section;
+use crate::stack::Stack;
+
+/// Run every configured section command for each commit in the stack, and return the
+/// sections to add to each commit's PR body, in config order
+pub fn render(
+    stack: &Stack,
+    repo: &Repository,
+    sections: &[Section],
+) -> Result<HashMap<Oid, String>> {
+    let mut rendered = HashMap::new();
+    if sections.is_empty() {
+        return Ok(rendered);
+    }
+
+    let workdir = repo.workdir().context("repo has no working directory")?;
+    for (index, commit) in stack.iter().enumerate() {
+        let mut body = String::new();
+        for section in sections {
+            tracing::debug!(section = section.name, commit = ?commit.id(), "rendering section");
+            let output = Command::new("sh")
+                .arg("-c")
+                .arg(&section.command)
+                .current_dir(workdir)
+                .env("FEL_SECTION", &section.name)
+                .env("FEL_COMMIT", commit.id().to_string())
+                .env("FEL_PARENT", commit.parent().to_string())
+                .env("FEL_INDEX", index.to_string())
+                .env("FEL_STACK", stack.name())
+                .output()
+                .with_context(|| format!("failed to run section {}", section.name))?;
+            anyhow::ensure!(
+                output.status.success(),
+                "section {} failed for {}: {}",
+                section.name,
+                commit.title,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            );
+
+            let text = String::from_utf8_lossy(&output.stdout);
+            let text = text.trim();
+            if !text.is_empty() {
+                body.push_str(&format!("<!-- fel:{} -->\n{text}\n\n", section.name));
+            }
+        }
+        rendered.insert(commit.id(), body);
+    }
+
+    Ok(rendered)
+}
@@ -0,0 +1,304 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use git2::{Oid, Remote, Repository};
+use octocrab::Octocrab;
+
+use crate::color;
+use crate::commit::Commit;
+use crate::config::Config;
+use crate::forge::{Forge, GitHub};
+use crate::gh::GHRepo;
+use crate::metadata::{Metadata, NOTE_REF};
+use crate::pulls::Update;
+use crate::remotes;
+use crate::stack::Stack;
+use crate::state::State;
+use crate::submit;
+
+const TITLE: &str = "[fel selftest] Throwaway change";
+
+const BODY: &str = "Opened by `fel selftest` to check that fel can push branches and open, \
+update and close PRs in this repo. It is closed and its branch deleted when the test finishes, \
+and it's safe to close if it was left open.";
+
+/// Check that fel can work in `gh_repo`, by submitting a throwaway stack of two draft PRs
+/// against `upstream`, pushing a new revision of it, and closing the PRs and deleting their
+/// branches again. Each step is reported as it finishes. Returns true if every step worked
+pub async fn selftest(
+    repo: &Repository,
+    remote: &mut Remote<'_>,
+    upstream: &str,
+    octocrab: Arc<Octocrab>,
+    gh_repo: &GHRepo,
+    config: &Config,
+) -> Result<bool> {
+    let mut report = Report::default();
+    report.check("token", whoami(&octocrab)).await;
+    report.check("access", access(&octocrab, gh_repo)).await;
+    if report.failed {
+        report.skip(&["submit", "update", "clean up"]);
+        return Ok(false);
+    }
+
+    let remote_name = remote.name().context("remote name not utf-8")?.to_string();
+    let base = repo
+        .refname_to_id(&format!("refs/remotes/{remote_name}/{upstream}"))
+        .with_context(|| format!("failed to find {remote_name}/{upstream}, fetch it first"))?;
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let name = format!("fel-selftest-{now}");
+    let config = scratch_config(config);
+
+    // Every commit made, so their notes can be removed again
+    let mut made = Vec::new();
+    let first = scratch_commit(repo, base, 1, "")?;
+    let second = scratch_commit(repo, first, 2, "")?;
+    made.extend([first, second]);
+
+    let submitted = report
+        .check("submit", async {
+            let prs: Vec<u64> = submit_scratch(
+                repo,
+                remote,
+                &[first, second],
+                &name,
+                upstream,
+                &octocrab,
+                gh_repo,
+                &config,
+            )
+            .await?;
+            Ok(match config.submit.draft {
+                true => format!("opened {} as drafts", links(&prs)),
+                false => format!("opened {}", links(&prs)),
+            })
+        })
+        .await;
+
+    if submitted && !config.submit.allow_force {
+        report.skip_because("update", "the policy forbids force pushes");
+    } else if submitted {
+        // Amend the top commit, carrying its note over like a rebase would
+        let amended = scratch_commit(repo, first, 2, " (amended)")?;
+        Metadata::find(repo, second)?.write(repo, amended)?;
+        made.push(amended);
+        report
+            .check("update", async {
+                let prs: Vec<u64> = submit_scratch(
+                    repo,
+                    remote,
+                    &[first, amended],
+                    &name,
+                    upstream,
+                    &octocrab,
+                    gh_repo,
+                    &config,
+                )
+                .await?;
+                Ok(format!("pushed a new revision of {}", links(&prs[1..])))
+            })
+            .await;
+    } else {
+        report.skip(&["update"]);
+    }
+
+    let forge = GitHub::new(octocrab.clone(), gh_repo.clone(), config.api);
+    report
+        .check(
+            "clean up",
+            clean_up(repo, remote, &forge, &name, &made, config.proxy.as_deref()),
+        )
+        .await;
+
+    Ok(!report.failed)
+}
+
+/// Submit the commits `ids` as the stack `name`, returning their PRs
+#[allow(clippy::too_many_arguments)]
+async fn submit_scratch(
+    repo: &Repository,
+    remote: &mut Remote<'_>,
+    ids: &[Oid],
+    name: &str,
+    upstream: &str,
+    octocrab: &Arc<Octocrab>,
+    gh_repo: &GHRepo,
+    config: &Config,
+) -> Result<Vec<u64>> {
+    let commits = ids
+        .iter()
+        .map(|&id| Commit::new(repo.find_commit(id)?, repo, false))
+        .collect::<Result<_>>()?;
+    let stack = Stack::from_commits(commits, name, upstream);
+    submit::submit(
+        &stack,
+        remote,
+        octocrab.clone(),
+        gh_repo,
+        repo,
+        config,
+        true,
+    )
+    .await?;
+
+    let mut prs = Vec::new();
+    for &id in ids {
+        prs.extend(Metadata::find(repo, id)?.pr);
+    }
+    Ok(prs)
+}
+
+/// Prints each step of the self test as it finishes
+#[derive(Default)]
+struct Report {
+    failed: bool,
+}
+
+impl Report {
+    /// Run a step, printing what it did or why it failed. Returns true if it worked
+    async fn check(&mut self, step: &str, run: impl Future<Output = Result<String>>) -> bool {
+        match run.await {
+            Ok(detail) => {
+                println!("{} {step}: {detail}", color::success().paint("ok     "));
+                true
+            }
+            Err(error) => {
+                println!("{} {step}: {error:#}", color::failure().paint("failed "));
+                self.failed = true;
+                false
+            }
+        }
+    }
+
+    fn skip(&self, steps: &[&str]) {
+        for step in steps {
+            println!("{} {step}", color::dimmed().paint("skipped"));
+        }
+    }
+
+    fn skip_because(&self, step: &str, reason: &str) {
+        println!("{} {step}: {reason}", color::dimmed().paint("skipped"));
+    }
+}
+
+async fn whoami(octocrab: &Octocrab) -> Result<String> {
+    let user = octocrab
+        .current()
+        .user()
+        .await
+        .context("GitHub didn't accept the token")?;
+    Ok(format!("authenticated as {}", user.login))
+}
+
+async fn access(octocrab: &Octocrab, gh_repo: &GHRepo) -> Result<String> {
+    let repo = octocrab
+        .repos(&gh_repo.owner, &gh_repo.repo)
+        .get()
+        .await
+        .with_context(|| format!("can't see {}/{}", gh_repo.owner, gh_repo.repo))?;
+    let push = repo.permissions.is_some_and(|permissions| permissions.push);
+    anyhow::ensure!(
+        push,
+        "the token can't push to {}/{}",
+        gh_repo.owner,
+        gh_repo.repo
+    );
+    Ok(format!("can push to {}/{}", gh_repo.owner, gh_repo.repo))
+}
+
+/// Config for the throwaway stack, which opens drafts and leaves out anything that would
+/// notify people or run the user's commands, unless the repo's policy requires it
+fn scratch_config(config: &Config) -> Config {
+    let mut config = config.clone();
+    let policy = config.policy.clone().unwrap_or_default();
+    let submit = &mut config.submit;
+    submit.draft = policy.draft.unwrap_or(true);
+    submit.allow_force = policy.allow_force.unwrap_or(true);
+    submit.keep_revisions = false;
+    submit.labels = policy.labels;
+    submit.default_reviewers.clear();
+    submit.default_team_reviewers.clear();
+    submit.codeowners_reviewers = false;
+    submit.discussion_category = None;
+    submit.overview_issue = false;
+    submit.title_template = None;
+    config.hooks = Default::default();
+    config.sections.clear();
+    config
+}
+
+/// Commit a file only the self test uses on top of `parent`, without touching the working
+/// tree or any branch
+fn scratch_commit(repo: &Repository, parent: Oid, number: usize, suffix: &str) -> Result<Oid> {
+    let parent = repo.find_commit(parent)?;
+    let contents = format!("Throwaway change {number} from `fel selftest`{suffix}\n");
+    let blob = repo.blob(contents.as_bytes())?;
+
+    let mut dir = match parent.tree()?.get_name(".fel-selftest") {
+        Some(entry) => repo.treebuilder(Some(&repo.find_tree(entry.id())?))?,
+        None => repo.treebuilder(None)?,
+    };
+    dir.insert(format!("change-{number}.md"), blob, 0o100644)?;
+    let dir = dir.write()?;
+    let mut root = repo.treebuilder(Some(&parent.tree()?))?;
+    root.insert(".fel-selftest", dir, 0o040000)?;
+    let tree = repo.find_tree(root.write()?)?;
+
+    let sig = repo.signature().context("failed to get signature")?;
+    let message = format!("{TITLE} {number}{suffix}\n\n{BODY}\n");
+    Ok(repo.commit(None, &sig, &sig, &message, &tree, &[&parent])?)
+}
+
+/// Close the PRs of the throwaway stack `name`, delete its branches, and forget its commits
+async fn clean_up(
+    repo: &Repository,
+    remote: &mut Remote<'_>,
+    forge: &dyn Forge,
+    name: &str,
+    made: &[Oid],
+    proxy: Option<&str>,
+) -> Result<String> {
+    let mut prs = Vec::new();
+    for &id in made {
+        if let Some(pr) = Metadata::find(repo, id)?.pr.filter(|pr| !prs.contains(pr)) {
+            prs.push(pr);
+        }
+    }
+    for &pr in &prs {
+        let close = Update {
+            close: true,
+            ..Default::default()
+        };
+        forge
+            .update_pr(pr, close)
+            .await
+            .with_context(|| format!("failed to close #{pr}"))?;
+    }
+
+    let mut state = State::load(repo)?;
+    let branches = state
+        .stack(name)
+        .map(|stack| stack.branches.clone())
+        .unwrap_or_default();
+    remotes::delete_branches(remote, &branches, proxy)?;
+
+    state.remove_stack(name);
+    state.save()?;
+    let sig = repo.signature().context("failed to get signature")?;
+    for &id in made {
+        repo.note_delete(id, Some(NOTE_REF), &sig, &sig).ok();
+    }
+
+    Ok(format!(
+        "closed {} and deleted {} branches",
+        links(&prs),
+        branches.len()
+    ))
+}
+
+fn links(prs: &[u64]) -> String {
+    let links: Vec<_> = prs.iter().map(|pr| format!("#{pr}")).collect();
+    links.join(", ")
+}
@@ -1,35 +1,57 @@
 use anyhow::{Context, Result};
-use git2::{BranchType, Repository, Sort};
+use git2::{BranchType, ObjectType, Oid, Repository, Sort};
 
+use crate::metadata::{Metadata, NoteBatch};
 use crate::{commit::Commit, config::Config};
 
 pub struct Stack {
     commits: Vec<Commit>,
     name: String,
     default_upstream: String,
+    merge_base: Oid,
+    upstream_head: Oid,
+    base_tag: Option<String>,
+    depends_on: Option<Dependency>,
+    lossy_utf8: bool,
+}
+
+/// Another stack a stack is built on top of
+#[derive(Clone, Debug)]
+pub struct Dependency {
+    /// Name of the other stack
+    pub stack: String,
+    /// PR of the commit the stack is built on
+    pub pr: u64,
+    /// Branch of that PR, which the bottom of the stack is made against
+    pub branch: String,
 }
 
 impl Stack {
-    pub fn new(repo: &Repository, config: &Config) -> Result<Self> {
+    pub fn new(repo: &Repository, config: &Config) -> crate::Result<Self> {
         // Find the local HEAD
         let head = repo.head().context("failed to get head")?;
         let head_commit = head.peel_to_commit().context("failed to get head commit")?;
         let branch_name = head.shorthand().context("invalid shorthand")?.to_string();
         tracing::debug!(branch_name, ?head_commit, "found HEAD");
 
-        // Find the remote HEAD
-        let default = repo
-            .find_branch(
-                &format!("{}/{}", config.default_remote, config.default_upstream),
-                BranchType::Remote,
-            )
-            .context("failed to find default branch")?;
+        // Find the remote HEAD, or the tag the stack is based on
+        let upstream = format!("{}/{}", config.default_remote, config.default_upstream);
+        let tag = format!("refs/tags/{}", config.default_upstream);
+        let (default, base_tag) = match repo.find_branch(&upstream, BranchType::Remote) {
+            Ok(branch) => (branch.into_reference(), None),
+            Err(_) => match repo.find_reference(&tag) {
+                Ok(tag) => (tag, Some(config.default_upstream.clone())),
+                Err(_) => {
+                    let error = anyhow::anyhow!("failed to find branch {upstream} or tag {tag}");
+                    return Err(error.into());
+                }
+            },
+        };
 
         let default_commit = default
-            .get()
             .peel_to_commit()
             .context("failed to get default commit")?;
-        tracing::debug!(?default_commit, "found default HEAD");
+        tracing::debug!(?default_commit, ?base_tag, "found default HEAD");
 
         // Calculate the first common ancestor
         let merge_base = repo
@@ -49,16 +71,73 @@ impl Stack {
             .map(|oid| {
                 let id = oid.context("failed to walk oid")?;
                 let commit = repo.find_commit(id).context("failed to find commit")?;
-                Commit::new(commit, repo)
+                Commit::new(commit, repo, config.lossy_utf8)
             })
             .collect::<Result<_>>()
             .context("failed to get commits in stack")?;
 
-        Ok(Self {
+        // Commits at the bottom submitted in another stack that still exists belong to that
+        // stack, and this one is built on top of it
+        let mut commits = commits;
+        let depended = commits
+            .iter()
+            .take_while(|commit| in_other_stack(repo, commit, &branch_name))
+            .count();
+        let depends_on = match depended {
+            0 => None,
+            count => {
+                let tip = commits
+                    .drain(..count)
+                    .next_back()
+                    .expect("count is not zero");
+                tracing::debug!(?tip.metadata, "stack depends on another stack");
+                Some((
+                    tip.id(),
+                    Dependency {
+                        stack: tip.metadata.stack.expect("checked above"),
+                        pr: tip.metadata.pr.expect("checked above"),
+                        branch: tip.metadata.branch.expect("checked above"),
+                    },
+                ))
+            }
+        };
+
+        let mut stack = Self::from_commits(commits, branch_name, &config.default_upstream);
+        stack.merge_base = merge_base;
+        stack.upstream_head = default_commit.id();
+        stack.base_tag = base_tag;
+        stack.lossy_utf8 = config.lossy_utf8;
+        if let Some((tip, dependency)) = depends_on {
+            stack.merge_base = tip;
+            stack.upstream_head = tip;
+            stack.default_upstream = dependency.branch.clone();
+            stack.depends_on = Some(dependency);
+        }
+        Ok(stack)
+    }
+
+    /// Create a stack from commits ordered from the bottom of the stack up, rather than
+    /// discovering them from HEAD. The stack is based on the parent of its first commit
+    pub fn from_commits(
+        commits: Vec<Commit>,
+        name: impl Into<String>,
+        upstream: impl Into<String>,
+    ) -> Self {
+        let base = commits
+            .first()
+            .map(|commit| *commit.parent())
+            .unwrap_or(Oid::zero());
+
+        Self {
             commits,
-            name: branch_name,
-            default_upstream: config.default_upstream.clone(),
-        })
+            name: name.into(),
+            default_upstream: upstream.into(),
+            merge_base: base,
+            upstream_head: base,
+            base_tag: None,
+            depends_on: None,
+            lossy_utf8: false,
+        }
     }
 
     /// Returns true if this stack does not have a branch associated with it
@@ -66,14 +145,79 @@ impl Stack {
         self.name == "HEAD"
     }
 
-    /// Create a new branch with the same head as this stack
-    pub fn dev_branch(&mut self, repo: &Repository) -> Result<()> {
-        let head_commit = self.commits.first().context("no commits")?;
-        let head_commit = repo
+    /// Returns the commit the stack is based on
+    pub fn merge_base(&self) -> Oid {
+        self.merge_base
+    }
+
+    /// Returns true if the upstream branch has moved on since this stack was based on it
+    pub fn is_stale(&self) -> bool {
+        self.merge_base != self.upstream_head
+    }
+
+    /// Returns a name for a stack without a branch that stays the same across submits, by
+    /// reusing the anonymous name it was last submitted with
+    pub fn anonymous_name(&self) -> Option<String> {
+        let previous = self.commits.iter().rev().find_map(|commit| {
+            commit
+                .metadata
+                .stack
+                .clone()
+                .filter(|stack| stack.starts_with("anon-"))
+        });
+        previous.or_else(|| {
+            let bottom = self.commits.first()?;
+            Some(format!("anon-{}", &bottom.id().to_string()[..8]))
+        })
+    }
+
+    /// Returns true if any commit in the stack is a `fixup!`, `squash!` or `amend!` commit
+    pub fn has_fixups(&self) -> bool {
+        self.commits.iter().any(|commit| {
+            ["fixup! ", "squash! ", "amend! "]
+                .iter()
+                .any(|prefix| commit.title.starts_with(prefix))
+        })
+    }
+
+    /// Returns true if any commit in the stack is a merge commit
+    pub fn has_merges(&self) -> bool {
+        self.commits.iter().any(Commit::is_merge)
+    }
+
+    /// Create a new branch with the same head as this stack, named by rendering `template`
+    /// with a slug of the top commit's title as `{slug}` and its short sha as `{sha}`. The
+    /// name is recorded in the top commit's metadata
+    pub fn dev_branch(&mut self, repo: &Repository, template: &str) -> Result<()> {
+        let head_commit = self.commits.last_mut().context("no commits")?;
+        let sha = head_commit.id().to_string();
+        let slug = match slug(&head_commit.title) {
+            slug if slug.is_empty() => sha[..4].to_string(),
+            slug => slug,
+        };
+        let base = template
+            .replace("{slug}", &slug)
+            .replace("{sha}", &sha[..7]);
+
+        // Add a suffix until the name is free
+        let mut name = base.clone();
+        let mut suffix = 2;
+        while repo.find_branch(&name, BranchType::Local).is_ok() {
+            name = format!("{base}-{suffix}");
+            suffix += 1;
+        }
+
+        let commit = repo
             .find_commit(head_commit.id())
             .context("find head commit")?;
-        self.name = format!("dev-{}", &head_commit.id().to_string()[..4]);
-        let branch = repo.branch(&self.name, &head_commit, false)?;
+        let branch = repo.branch(&name, &commit, false)?;
+        head_commit.metadata.dev_branch = Some(name.clone());
+        head_commit
+            .metadata
+            .write(repo, head_commit.id())
+            .context("failed to record dev branch")?;
+
+        self.set_name(name);
         let branch = branch.into_reference();
         let refname = branch.name().context("branch name not utf-8")?;
         repo.set_head(refname)?;
@@ -81,19 +225,236 @@ impl Stack {
         Ok(())
     }
 
-    pub fn iter(&self) -> std::slice::Iter<Commit> {
+    pub fn iter(&self) -> std::slice::Iter<'_, Commit> {
         self.commits.iter()
     }
 
+    /// Read every commit's note again, after something like a submit changed them
+    pub fn refresh(&mut self, repo: &Repository) -> Result<()> {
+        for commit in &mut self.commits {
+            commit.metadata = Metadata::find(repo, commit.id())?;
+        }
+        Ok(())
+    }
+
+    /// Returns the commit at `index`, counting up from the bottom of the stack
+    pub fn get(&self, index: usize) -> Option<&Commit> {
+        self.commits.get(index)
+    }
+
     pub fn name(&self) -> &str {
         &self.name
     }
 
+    pub fn set_name(&mut self, name: impl Into<String>) {
+        self.name = name.into();
+    }
+
     pub fn upstream(&self) -> &str {
         &self.default_upstream
     }
 
+    /// Returns the branch the segment of the stack holding the commit at `index` targets: the
+    /// base of the nearest commit at or below it that names one with `Fel-Base`, or upstream
+    pub fn segment_base(&self, index: usize) -> &str {
+        self.commits
+            .iter()
+            .take(index + 1)
+            .rev()
+            .find_map(|commit| commit.options.base.as_deref())
+            .unwrap_or(self.upstream())
+    }
+
+    /// Make the PR of the commit at `index` against `base` from now on, like a `Fel-Base` trailer
+    /// would, or go back to its trailer or the commit below it with `None`
+    pub fn set_base(
+        &mut self,
+        repo: &Repository,
+        index: usize,
+        base: Option<String>,
+    ) -> Result<()> {
+        let commit = self
+            .commits
+            .get_mut(index)
+            .with_context(|| format!("no commit at index {index}"))?;
+        commit.set_base(repo, base)
+    }
+
+    /// Returns the stack this stack is built on top of, if any
+    pub fn depends_on(&self) -> Option<&Dependency> {
+        self.depends_on.as_ref()
+    }
+
+    /// Returns the tag the stack is based on, if it isn't based on a branch
+    pub fn base_tag(&self) -> Option<&str> {
+        self.base_tag.as_deref()
+    }
+
+    /// Change the branch PRs at the bottom of the stack are made against
+    pub fn set_upstream(&mut self, upstream: impl Into<String>) {
+        self.default_upstream = upstream.into();
+    }
+
+    /// Replace the message of the commit at `index`, and recreate the commits above it on top
+    /// of the new one. Their trees don't change, so neither does the working tree. Metadata is
+    /// copied to the new commits and the stack's branch is moved to the new top
+    pub fn reword(&mut self, repo: &Repository, index: usize, message: &str) -> Result<()> {
+        anyhow::ensure!(index < self.commits.len(), "no commit at index {index}");
+        self.rewrite(repo, index, None, Some(message), "fel: reword")
+    }
+
+    /// Returns the first commit in the stack that doesn't change anything
+    pub fn first_empty(&self) -> Option<&Commit> {
+        self.commits.iter().find(|commit| commit.is_empty())
+    }
+
+    /// Leave empty commits out of the stack without changing the repo. The commit above each
+    /// one is made against the commit below it instead
+    pub fn skip_empty(&mut self) {
+        while let Some(index) = self.commits.iter().position(Commit::is_empty) {
+            let empty = self.commits.remove(index);
+            tracing::debug!(commit = ?empty.id(), "skipping empty commit");
+            if let Some(next) = self.commits.get_mut(index) {
+                next.set_parent(*empty.parent());
+            }
+        }
+    }
+
+    /// Remove empty commits from the repo, recreating the commits above them. Empty commits
+    /// have the same tree as their parent, so the working tree doesn't change
+    pub fn drop_empty(&mut self, repo: &Repository) -> Result<()> {
+        while let Some(index) = self.commits.iter().position(Commit::is_empty) {
+            let empty = self.commits.remove(index);
+            tracing::debug!(commit = ?empty.id(), "dropping empty commit");
+            let parent = repo
+                .find_commit(*empty.parent())
+                .context("find parent of empty commit")?;
+            self.rewrite(repo, index, Some(parent), None, "fel: drop empty commit")?;
+        }
+
+        Ok(())
+    }
+
+    /// Recreate the commits from `index` up, on top of `parent` if it's given, with `message`
+    /// for the commit at `index`, and move the stack's branch to the new top
+    fn rewrite(
+        &mut self,
+        repo: &Repository,
+        index: usize,
+        parent: Option<git2::Commit>,
+        message: Option<&str>,
+        reflog: &str,
+    ) -> Result<()> {
+        let mut parent = parent;
+        let mut notes = NoteBatch::default();
+        for i in index..self.commits.len() {
+            let old = repo
+                .find_commit(self.commits[i].id())
+                .context("find commit")?;
+            let parents = match parent.take() {
+                Some(parent) => vec![parent],
+                None => old.parents().collect(),
+            };
+            let message = message.filter(|_| i == index);
+            let id = recommit(repo, &old, &parents, message).context("failed to create commit")?;
+            notes
+                .add(&self.commits[i].metadata, id)
+                .context("failed to copy metadata")?;
+            parent = Some(repo.find_commit(id).context("find new commit")?);
+        }
+        notes.write(repo).context("failed to copy metadata")?;
+
+        let mut commit = parent.clone();
+        for i in (index..self.commits.len()).rev() {
+            let new = commit.context("rewritten commit missing")?;
+            commit = new.parent(0).ok();
+            self.commits[i] = Commit::new(new, repo, self.lossy_utf8)?;
+        }
+
+        let top = parent.context("no commits rewritten")?.id();
+        match self.is_detached() {
+            true => repo.set_head_detached(top)?,
+            false => {
+                repo.head()
+                    .context("failed to get head")?
+                    .set_target(top, reflog)
+                    .context("failed to move branch")?;
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn len(&self) -> usize {
         self.commits.len()
     }
+
+    pub fn is_empty(&self) -> bool {
+        self.commits.is_empty()
+    }
+}
+
+/// Create a copy of `old` on top of `parents`, with `message` if it's given. Otherwise the raw
+/// bytes of the old message and its encoding are kept, so messages that aren't UTF-8 survive
+fn recommit(
+    repo: &Repository,
+    old: &git2::Commit,
+    parents: &[git2::Commit],
+    message: Option<&str>,
+) -> Result<Oid> {
+    let tree = old.tree().context("find tree")?;
+    let parents: Vec<_> = parents.iter().collect();
+    let buffer = repo.commit_create_buffer(&old.author(), &old.committer(), "", &tree, &parents)?;
+
+    // The buffer ends with the blank line between the headers and the empty message
+    let mut raw = buffer.strip_suffix(b"\n").unwrap_or(&buffer).to_vec();
+    let message = match message {
+        Some(message) => message.as_bytes(),
+        None => {
+            if let Some(encoding) = old.message_encoding() {
+                raw.extend_from_slice(format!("encoding {encoding}\n").as_bytes());
+            }
+            old.message_raw_bytes()
+        }
+    };
+    raw.push(b'\n');
+    raw.extend_from_slice(message);
+
+    let odb = repo.odb().context("failed to open object database")?;
+    Ok(odb.write(ObjectType::Commit, &raw)?)
+}
+
+/// Returns true if `commit` was submitted in a stack other than `name` that still has a branch
+fn in_other_stack(repo: &Repository, commit: &Commit, name: &str) -> bool {
+    let Some(stack) = &commit.metadata.stack else {
+        return false;
+    };
+
+    stack != name
+        && commit.metadata.pr.is_some()
+        && commit.metadata.branch.is_some()
+        && repo.find_branch(stack, BranchType::Local).is_ok()
+}
+
+/// Turn a commit title into something usable in a branch name, like `fix-the-parser`
+fn slug(title: &str) -> String {
+    const MAX_LEN: usize = 40;
+
+    let mut slug = String::new();
+    for c in title.chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    // Cut long titles at a word boundary
+    if slug.len() > MAX_LEN {
+        slug.truncate(MAX_LEN);
+        if let Some(end) = slug.rfind('-') {
+            slug.truncate(end);
+        }
+    }
+    slug.trim_end_matches('-').to_string()
 }
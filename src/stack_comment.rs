@@ -0,0 +1,63 @@
+use anyhow::{Context, Result};
+
+use crate::forge::{Forge, ForgeErrorKind};
+
+/// Starts the comment fel keeps the stack tree in, so it can be found again on the next submit
+const MARKER: &str = "<!-- fel:stack -->";
+
+/// Post `footer` as a comment on PR `pr`, or edit the comment fel already posted there if it's
+/// out of date. Returns the id of the comment
+pub async fn upsert(forge: &dyn Forge, pr: u64, footer: &str) -> Result<u64> {
+    let body = format!("{MARKER}\n{footer}");
+    let comments = forge
+        .list_comments(pr)
+        .await
+        .with_context(|| format!("failed to list comments on PR {pr}"))?;
+    let existing = comments
+        .iter()
+        .find(|comment| comment.body.starts_with(MARKER));
+
+    match existing {
+        Some(comment) if comment.body == body => Ok(comment.id),
+        Some(comment) => {
+            tracing::debug!(pr, id = comment.id, "updating stack comment");
+            forge
+                .edit_comment(comment.id, &body)
+                .await
+                .with_context(|| format!("failed to update stack comment on PR {pr}"))?;
+            Ok(comment.id)
+        }
+        None => {
+            tracing::debug!(pr, "creating stack comment");
+            let id = forge
+                .comment(pr, &body)
+                .await
+                .with_context(|| format!("failed to comment on PR {pr}"))?;
+            Ok(id)
+        }
+    }
+}
+
+/// Delete the stack comment `id` from PR `pr`, unless it's already gone
+pub async fn remove(forge: &dyn Forge, pr: u64, id: u64) -> Result<()> {
+    tracing::debug!(pr, id, "deleting stack comment");
+    match forge.delete_comment(id).await {
+        Err(crate::Error::Forge { error, .. }) if error.kind == ForgeErrorKind::NotFound => Ok(()),
+        result => result.with_context(|| format!("failed to delete stack comment on PR {pr}")),
+    }
+}
+
+/// Delete every stack comment on PR `pr`, whether or not a note knows about it
+pub async fn remove_all(forge: &dyn Forge, pr: u64) -> Result<()> {
+    let comments = forge
+        .list_comments(pr)
+        .await
+        .with_context(|| format!("failed to list comments on PR {pr}"))?;
+    for comment in comments
+        .iter()
+        .filter(|comment| comment.body.starts_with(MARKER))
+    {
+        remove(forge, pr, comment.id).await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use git2::Repository;
+
+/// State fel keeps about every stack in a repo, in `.git/fel/state.json`. Notes hold what is
+/// true of one commit, this holds what spans commits and stacks
+pub struct State {
+    path: PathBuf,
+    data: StateData,
+}
+
+#[derive(serde::Deserialize, serde::Serialize, Default)]
+struct StateData {
+    #[serde(default)]
+    stacks: BTreeMap<String, StackState>,
+    pending: Option<Pending>,
+    pending_pick: Option<PendingPick>,
+}
+
+/// A restack that stopped on a conflict, waiting for `fel continue` or `fel abort`
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+pub struct Pending {
+    /// What was being rebased onto
+    pub onto: String,
+    /// Arguments of the fel command that was restacking, run again once the restack finishes
+    pub resume: Option<Vec<String>>,
+}
+
+/// A `fel cherry-pick` that stopped on a conflict, waiting for `fel continue` or `fel abort`
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+pub struct PendingPick {
+    /// The PR being picked
+    pub number: u64,
+    /// Commits of the PR being picked, in order
+    pub picks: Vec<String>,
+    /// `HEAD` before picking, which the picks are on top of
+    pub base: String,
+    /// Whether uncommitted changes were stashed, to restore once the pick finishes
+    pub stashed: bool,
+}
+
+/// What fel knows about one stack, by name
+#[derive(serde::Deserialize, serde::Serialize, Clone, Debug, Default)]
+pub struct StackState {
+    pub remote: Option<String>,
+    pub upstream: Option<String>,
+    /// Unix time the stack was last submitted
+    pub submitted_at: Option<u64>,
+    /// PRs in the stack as of its last submit, from the bottom up
+    #[serde(default)]
+    pub prs: Vec<u64>,
+    /// Every branch fel has pushed for the stack
+    #[serde(default)]
+    pub branches: Vec<String>,
+}
+
+impl State {
+    /// Load the state of `repo`, or start empty if there isn't any yet
+    pub fn load(repo: &Repository) -> Result<Self> {
+        let path = repo.path().join("fel/state.json");
+        let data = match fs::read_to_string(&path) {
+            Ok(data) => serde_json::from_str(&data)
+                .with_context(|| format!("invalid state in {}", path.display()))?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => StateData::default(),
+            Err(error) => return Err(error).context("failed to read state"),
+        };
+
+        Ok(Self { path, data })
+    }
+
+    pub fn stack(&self, name: &str) -> Option<&StackState> {
+        self.data.stacks.get(name)
+    }
+
+    pub fn stack_mut(&mut self, name: &str) -> &mut StackState {
+        self.data.stacks.entry(name.to_string()).or_default()
+    }
+
+    /// Forget everything about the stack `name`
+    pub fn remove_stack(&mut self, name: &str) {
+        self.data.stacks.remove(name);
+    }
+
+    /// Move what fel knows about the stack `from` to `to`, unless `to` already has an entry
+    pub fn rename_stack(&mut self, from: &str, to: &str) {
+        if let Some(stack) = self.data.stacks.remove(from) {
+            self.data.stacks.entry(to.to_string()).or_insert(stack);
+        }
+    }
+
+    pub fn pending(&self) -> Option<&Pending> {
+        self.data.pending.as_ref()
+    }
+
+    pub fn set_pending(&mut self, pending: Option<Pending>) {
+        self.data.pending = pending;
+    }
+
+    pub fn pending_pick(&self) -> Option<&PendingPick> {
+        self.data.pending_pick.as_ref()
+    }
+
+    pub fn set_pending_pick(&mut self, pick: Option<PendingPick>) {
+        self.data.pending_pick = pick;
+    }
+
+    /// Write the state back. It's written to a temporary file first, so a crash can't leave
+    /// it half written
+    pub fn save(&self) -> Result<()> {
+        let dir = self.path.parent().context("state has no directory")?;
+        fs::create_dir_all(dir).context("failed to create fel dir")?;
+
+        let data = serde_json::to_string_pretty(&self.data).context("failed to serialize state")?;
+        let tmp = self.path.with_extension("json.tmp");
+        fs::write(&tmp, data).context("failed to write state")?;
+        fs::rename(&tmp, &self.path).context("failed to replace state")?;
+        Ok(())
+    }
+}
+
+impl StackState {
+    /// Record a submit of the stack with `prs` and `branches`
+    pub fn submitted(&mut self, prs: Vec<u64>, branches: impl IntoIterator<Item = String>) {
+        self.submitted_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|now| now.as_secs());
+        self.prs = prs;
+        for branch in branches {
+            if !self.branches.contains(&branch) {
+                self.branches.push(branch);
+            }
+        }
+    }
+}
@@ -0,0 +1,408 @@
+use ansi_term::ANSIString;
+use anyhow::{Context, Result};
+use git2::{ObjectType, Oid};
+use octocrab::Octocrab;
+use serde_json::json;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::color;
+use crate::config;
+use crate::gh::{self, GHRepo};
+use crate::render::{self, TreeNode};
+use crate::stack::Stack;
+
+/// The state of a PR that decides whether it can land
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PrStatus {
+    pub number: u64,
+    /// `OPEN`, `CLOSED` or `MERGED`
+    pub state: String,
+    pub is_draft: bool,
+    /// `APPROVED`, `CHANGES_REQUESTED` or `REVIEW_REQUIRED`, or missing when the repo doesn't
+    /// require reviews
+    pub review_decision: Option<String>,
+    /// `MERGEABLE`, `CONFLICTING` or `UNKNOWN` while GitHub is still computing it
+    pub mergeable: String,
+    commits: Commits,
+    latest_reviews: Reviews,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Reviews {
+    nodes: Vec<Review>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Review {
+    state: String,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Commits {
+    nodes: Vec<CommitNode>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct CommitNode {
+    commit: CheckedCommit,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CheckedCommit {
+    status_check_rollup: Option<Rollup>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+struct Rollup {
+    state: String,
+}
+
+impl PrStatus {
+    /// Returns the combined state of the checks on the PR's head, if it has any
+    pub fn checks(&self) -> Option<&str> {
+        self.commits
+            .nodes
+            .first()?
+            .commit
+            .status_check_rollup
+            .as_ref()
+            .map(|rollup| rollup.state.as_str())
+    }
+
+    /// Returns the number of reviewers whose latest review approves the PR
+    pub fn approvals(&self) -> usize {
+        self.latest_reviews
+            .nodes
+            .iter()
+            .filter(|review| review.state == "APPROVED")
+            .count()
+    }
+
+    /// Returns true if the PR is open, approved if the repo requires it, passing its checks
+    /// and has no conflicts
+    pub fn is_ready(&self) -> bool {
+        self.state == "OPEN"
+            && !self.is_draft
+            && self.review_decision.as_deref() != Some("CHANGES_REQUESTED")
+            && self.review_decision.as_deref() != Some("REVIEW_REQUIRED")
+            && matches!(self.checks(), None | Some("SUCCESS"))
+            && self.mergeable == "MERGEABLE"
+    }
+}
+
+/// Fetch the status of every PR in `prs` in one query, which may take `timeout`
+pub async fn fetch(
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    prs: &[u64],
+    timeout: Duration,
+) -> Result<Vec<PrStatus>> {
+    if prs.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let fields = prs
+        .iter()
+        .map(|pr| {
+            format!(
+                "pr{pr}: pullRequest(number: {pr}) {{
+                    number state isDraft reviewDecision mergeable
+                    commits(last: 1) {{ nodes {{ commit {{ statusCheckRollup {{ state }} }} }} }}
+                    latestReviews(first: 100) {{ nodes {{ state }} }}
+                }}"
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let query = format!(
+        "query($owner: String!, $name: String!) {{
+            repository(owner: $owner, name: $name) {{ {fields} }}
+        }}"
+    );
+
+    let query = gh::graphql(
+        octocrab,
+        &query,
+        json!({ "owner": gh_repo.owner, "name": gh_repo.repo }),
+    );
+    let data = gh::timeout(timeout, "getting PR status", query)
+        .await?
+        .context("failed to get PR status")?;
+
+    prs.iter()
+        .map(|pr| {
+            serde_json::from_value(data["repository"][format!("pr{pr}")].clone())
+                .with_context(|| format!("invalid status for PR {pr}"))
+        })
+        .collect()
+}
+
+/// How `fel status` prints the stack
+#[derive(clap::ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    /// A tree for people
+    #[default]
+    Text,
+    /// A JSON document for scripts and bug reports
+    Json,
+}
+
+/// The status of one commit in `--format json`
+#[derive(serde::Serialize)]
+struct JsonCommit {
+    index: usize,
+    id: String,
+    title: String,
+    branch: Option<String>,
+    pr: Option<u64>,
+    url: Option<String>,
+    state: Option<String>,
+    draft: Option<bool>,
+    review_decision: Option<String>,
+    approvals: Option<usize>,
+    checks: Option<String>,
+    mergeable: Option<String>,
+    waiting_secs: Option<u64>,
+    changed: bool,
+}
+
+#[derive(serde::Serialize)]
+struct JsonStack {
+    repo: String,
+    stack: String,
+    upstream: String,
+    ready: bool,
+    commits: Vec<JsonCommit>,
+}
+
+/// Print the status of each PR in the stack, from the top down. Returns true if every commit
+/// has been submitted and every PR is ready to land. With `anonymize`, names, titles and urls
+/// in JSON output are replaced by salted hashes of them
+#[allow(clippy::too_many_arguments)]
+pub async fn status(
+    stack: &Stack,
+    last_submit: Option<u64>,
+    config: &config::Status,
+    format: Format,
+    anonymize: bool,
+    octocrab: &Octocrab,
+    gh_repo: &GHRepo,
+    timeout: Duration,
+) -> Result<bool> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("clock is before 1970")?
+        .as_secs();
+
+    let prs: Vec<_> = stack
+        .iter()
+        .filter_map(|commit| commit.metadata.pr)
+        .collect();
+    let statuses = fetch(octocrab, gh_repo, &prs, timeout).await?;
+    if format == Format::Json {
+        return json(stack, &statuses, gh_repo, anonymize, now);
+    }
+
+    let submitted = match last_submit {
+        Some(at) => color::dimmed()
+            .paint(format!(" (submitted {} ago)", age(now.saturating_sub(at))))
+            .to_string(),
+        None => String::new(),
+    };
+    println!(
+        "{}{submitted}",
+        color::warning().paint(format!("* {}", stack.name()))
+    );
+    let mut ready = true;
+    let mut labels = vec![color::warning().paint(stack.upstream()).to_string()];
+    for commit in stack.iter() {
+        let status = commit
+            .metadata
+            .pr
+            .and_then(|pr| statuses.iter().find(|status| status.number == pr));
+        let Some(status) = status else {
+            ready = false;
+            labels.push(format!(
+                "{} {}",
+                commit.title,
+                color::failure().paint("not submitted")
+            ));
+            continue;
+        };
+
+        ready &= status.is_ready() && !commit.changed();
+        let mut states = vec![state(status)];
+        if status.state == "OPEN" {
+            states.push(review(status));
+            states.push(checks(status));
+            states.push(mergeable(status));
+        }
+        if status.state == "OPEN"
+            && !status.is_draft
+            && status.review_decision.as_deref() != Some("APPROVED")
+        {
+            if let Some(updated_at) = commit.metadata.updated_at {
+                states.push(waiting(now.saturating_sub(updated_at), config));
+            }
+        }
+        if commit.changed() {
+            states.push(color::warning().paint("local changes"));
+        }
+
+        let states: Vec<_> = states.iter().map(ANSIString::to_string).collect();
+        labels.push(format!(
+            "#{} {} [{}]",
+            status.number,
+            commit.title,
+            states.join(", ")
+        ));
+    }
+
+    // Each commit sits on the one below it, and the bottom one on upstream
+    let nodes: Vec<_> = labels
+        .iter()
+        .enumerate()
+        .map(|(index, label)| TreeNode {
+            label,
+            parent: index.checked_sub(1),
+        })
+        .collect();
+    for line in render::tree(&nodes) {
+        println!("{line}");
+    }
+
+    Ok(ready)
+}
+
+fn state(status: &PrStatus) -> ANSIString<'static> {
+    match (status.state.as_str(), status.is_draft) {
+        ("MERGED", _) => color::dimmed().paint("merged"),
+        ("CLOSED", _) => color::failure().paint("closed"),
+        (_, true) => color::dimmed().paint("draft"),
+        _ => color::success().paint("open"),
+    }
+}
+
+fn review(status: &PrStatus) -> ANSIString<'static> {
+    let approvals = match status.approvals() {
+        1 => " (1 approval)".to_string(),
+        n => format!(" ({n} approvals)"),
+    };
+    match status.review_decision.as_deref() {
+        Some("APPROVED") => color::success().paint(format!("approved{approvals}")),
+        Some("CHANGES_REQUESTED") => {
+            color::failure().paint(format!("changes requested{approvals}"))
+        }
+        Some(_) => color::warning().paint(format!("review required{approvals}")),
+        None => color::dimmed().paint(format!("no review required{approvals}")),
+    }
+}
+
+/// Print the status of the stack as JSON
+fn json(
+    stack: &Stack,
+    statuses: &[PrStatus],
+    gh_repo: &GHRepo,
+    anonymize: bool,
+    now: u64,
+) -> Result<bool> {
+    // Hashing keeps equal values equal, so the structure of the stack survives. The salt is
+    // random and never printed, so a guessed branch name or title can't be checked against it
+    let salt = RandomState::new().build_hasher().finish();
+    let redact = |text: &str| match anonymize {
+        true => Oid::hash_object(ObjectType::Blob, format!("{salt:016x}{text}").as_bytes())
+            .map(|id| id.to_string()[..12].to_string())
+            .unwrap_or_default(),
+        false => text.to_string(),
+    };
+
+    let mut ready = true;
+    let mut commits = Vec::new();
+    for (index, commit) in stack.iter().enumerate() {
+        let status = commit
+            .metadata
+            .pr
+            .and_then(|pr| statuses.iter().find(|status| status.number == pr));
+        ready &= status.is_some_and(PrStatus::is_ready) && !commit.changed();
+
+        commits.push(JsonCommit {
+            index,
+            id: redact(&commit.id().to_string()),
+            title: redact(&commit.title),
+            branch: commit.metadata.branch.as_deref().map(redact),
+            pr: commit.metadata.pr,
+            url: commit.metadata.pr_url.as_deref().map(redact),
+            state: status.map(|status| status.state.clone()),
+            draft: status.map(|status| status.is_draft),
+            review_decision: status.and_then(|status| status.review_decision.clone()),
+            approvals: status.map(PrStatus::approvals),
+            checks: status.and_then(|status| status.checks().map(str::to_string)),
+            mergeable: status.map(|status| status.mergeable.clone()),
+            waiting_secs: commit
+                .metadata
+                .updated_at
+                .map(|updated_at| now.saturating_sub(updated_at)),
+            changed: commit.changed(),
+        });
+    }
+
+    let output = JsonStack {
+        repo: redact(&format!("{}/{}", gh_repo.owner, gh_repo.repo)),
+        stack: redact(stack.name()),
+        upstream: redact(stack.upstream()),
+        ready,
+        commits,
+    };
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&output).context("failed to serialize status")?
+    );
+
+    Ok(ready)
+}
+
+/// A duration short enough for a status line, like `5m`, `3h` or `2d`
+fn age(secs: u64) -> String {
+    let hours = secs / 3600;
+    match hours {
+        0 => format!("{}m", secs / 60),
+        1..=47 => format!("{hours}h"),
+        _ => format!("{}d", hours / 24),
+    }
+}
+
+/// How long a PR has waited for review, highlighted past the configured thresholds
+fn waiting(secs: u64, config: &config::Status) -> ANSIString<'static> {
+    let hours = secs / 3600;
+    let text = format!("waiting {}", age(secs));
+
+    let past = |threshold: Option<u64>| threshold.is_some_and(|threshold| hours >= threshold);
+    if past(config.review_alert_hours) {
+        color::failure().paint(text)
+    } else if past(config.review_warn_hours) {
+        color::warning().paint(text)
+    } else {
+        color::dimmed().paint(text)
+    }
+}
+
+fn checks(status: &PrStatus) -> ANSIString<'static> {
+    match status.checks() {
+        Some("SUCCESS") => color::success().paint("checks passed"),
+        Some("FAILURE" | "ERROR") => color::failure().paint("checks failed"),
+        Some(_) => color::warning().paint("checks pending"),
+        None => color::dimmed().paint("no checks"),
+    }
+}
+
+fn mergeable(status: &PrStatus) -> ANSIString<'static> {
+    match status.mergeable.as_str() {
+        "MERGEABLE" => color::success().paint("mergeable"),
+        "CONFLICTING" => color::failure().paint("conflicts"),
+        _ => color::warning().paint("mergeability unknown"),
+    }
+}
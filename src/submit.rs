@@ -1,52 +1,182 @@
-use ansi_term::Colour::{Green, Red, Yellow};
-use ansi_term::{Color, Style};
+use ansi_term::Style;
 use anyhow::{Context, Result};
 use futures::{stream::FuturesUnordered, TryStreamExt};
 use git2::{Oid, Remote, Repository};
-use indicatif::{MultiProgress, ProgressBar, ProgressFinish, ProgressStyle};
-use octocrab::pulls::PullRequestHandler;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressFinish};
+use octocrab::models::pulls::{PullRequest, ReviewState};
+use octocrab::models::IssueState;
 use octocrab::Octocrab;
 use parking_lot::RwLock;
-use tera::Tera;
-use tokio::sync::{watch, Notify};
+use tokio::sync::watch;
 
+use crate::actor::GitActor;
 use crate::auth;
+use crate::color;
 use crate::commit::Commit;
-use crate::config::Config;
-use crate::gh::GHRepo;
+use crate::config::{self, Config, Footer, FooterPlacement, OverviewLink, RevisionComments};
+use crate::discussion::{self, Discussion};
+use crate::dropped;
+use crate::events;
+use crate::forge::{Forge, GitHub};
+use crate::gh::{self, GHRepo};
+use crate::hooks;
 use crate::metadata::Metadata;
+use crate::overview::{self, Overview};
+use crate::prompt;
+use crate::protected::Protected;
+use crate::pulls;
 use crate::push::BatchedPusher;
-use crate::stack::Stack;
+use crate::remotes;
+use crate::render::{self, PrInfo, RenderStore, StackRenderer, TeraRenderer};
+use crate::review_map::{self, ReviewItem};
+use crate::reviewers::{self, Reviewers};
+use crate::sections;
+use crate::stack::{Dependency, Stack};
+use crate::stack_comment;
+use crate::state::State;
+use crate::summary::{self, CommitSummary, Outcome};
+use crate::upstream;
+use crate::validate;
 
 use std::borrow::Cow;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-const BODY_DELIM: &str = "[#]:fel";
+/// The marker fel writes before the footer, unless `submit.footer_delimiter` replaces it
+pub const BODY_DELIM: &str = "[#]:fel";
 
-#[derive(serde::Serialize, Clone)]
-struct PrInfo {
-    number: u64,
-    title: String,
+/// Returns the markers that may start the footer of a PR body, the one written on submit
+/// first, then `submit.legacy_delimiters` and the built in one
+pub fn delimiters(config: &config::Submit) -> Vec<&str> {
+    let current = config
+        .footer_delimiter
+        .as_deref()
+        .filter(|delimiter| !delimiter.trim().is_empty())
+        .unwrap_or(BODY_DELIM);
+    let mut delimiters = vec![current];
+    for delimiter in config.legacy_delimiters.iter().map(String::as_str) {
+        if !delimiter.trim().is_empty() && !delimiters.contains(&delimiter) {
+            delimiters.push(delimiter);
+        }
+    }
+    if !delimiters.contains(&BODY_DELIM) {
+        delimiters.push(BODY_DELIM);
+    }
+    delimiters
+}
+
+/// Returns the part of a PR body written by the author, without anything fel added. The
+/// footer starts at the first of `delimiters` in the body, so footers left behind under older
+/// markers are dropped along with the current one
+pub fn strip_footer<'a>(body: &'a str, delimiters: &[impl AsRef<str>]) -> &'a str {
+    let end = delimiters
+        .iter()
+        .filter_map(|delimiter| body.find(delimiter.as_ref()))
+        .min()
+        .unwrap_or(body.len());
+    body[..end].trim_end()
+}
+
+/// `submit.branch_prefix` that stands for the login of the authenticated user
+const ME: &str = "@me";
+
+/// Wall clock time spent in each phase of a submit, and on each commit. PRs and footers are
+/// worked on concurrently, so those phases took as long as the slowest commit
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    pub push: Duration,
+    pub prs: Duration,
+    pub footers: Duration,
+    pub metadata: Duration,
+    /// In stack order, bottom first
+    pub commits: Vec<CommitProfile>,
+}
+
+/// Time spent on a single commit's PR
+#[derive(Clone, Debug)]
+pub struct CommitProfile {
+    pub id: Oid,
+    pub pr: u64,
+    /// Creating or fetching the PR, labeling it and requesting reviews
+    pub pr_time: Duration,
+    /// Updating the PR's body and base, and commenting on it
+    pub footer_time: Duration,
 }
 
 struct Submit {
     octocrab: Arc<Octocrab>,
     gh_repo: GHRepo,
 
-    use_indexed_branches: bool,
-    branch_prefix: Option<String>,
+    naming: BranchNaming,
+    draft: bool,
+    /// Set when the repo's policy decides, which trailers can't override
+    forced_draft: Option<bool>,
+    labels: Vec<String>,
     stack_name: String,
     stack_upstream: String,
+    depends_on: Option<Dependency>,
+    /// The branch or tag the stack was submitted against, which is different from
+    /// `stack_upstream` when fel made a base branch for a tag
+    upstream: String,
+    /// The remote PRs are made against
+    remote: String,
+    /// Owner of the fork branches are pushed to, when it isn't the repo PRs are made against
+    fork_owner: Option<String>,
+
+    /// PRs whose reviewed head will be replaced by this submit
+    reviewed: HashSet<u64>,
+
+    revision_comments: RevisionComments,
+    authoritative_commits: bool,
+    revision_comment_template: String,
+    /// Owns the repo for the tasks submitting each commit
+    git: GitActor,
+
+    renderer: Arc<dyn StackRenderer>,
+    title_template: Option<String>,
+    stack_size: usize,
+
+    discussion_category: Option<String>,
+    discussion_threshold: usize,
+    discussion: RwLock<Option<Discussion>>,
+    overview_issue: bool,
+    overview_link: OverviewLink,
+    overview: RwLock<Option<Overview>>,
+    review_maps: HashMap<Oid, Vec<ReviewItem>>,
+    /// Where PRs are opened and updated
+    forge: Arc<dyn Forge>,
+    /// How long each request made directly through `octocrab` may take
+    timeout: Duration,
+    /// When `submit.deadline` passes, after which nothing new is started
+    deadline: Option<Instant>,
+    /// Output of the configured section commands for each commit
+    sections: HashMap<Oid, String>,
+    reviewers: HashMap<Oid, Reviewers>,
 
     pusher: BatchedPusher,
+    footer: Footer,
+    footer_placement: FooterPlacement,
+    /// The footer marker to write first, then older ones to recognize
+    delimiters: Vec<String>,
     footer_rx: watch::Receiver<Option<String>>,
 
     branch_names: RwLock<HashMap<git2::Oid, watch::Receiver<Option<String>>>>,
     pr_info: RwLock<HashMap<git2::Oid, watch::Receiver<Option<PrInfo>>>>,
 }
 
+/// The result of submitting a single commit
+struct Submitted {
+    id: Oid,
+    metadata: Metadata,
+    outcome: Outcome,
+    /// Time spent creating or fetching the PR
+    pr_time: Duration,
+    /// Time spent updating the PR body
+    footer_time: Duration,
+}
+
 struct SubmitProgress {
     oid: Oid,
     title: String,
@@ -72,30 +202,37 @@ impl SubmitProgress {
     }
 
     fn update(&self) -> Result<()> {
-        self.do_update(Yellow, true)
+        self.do_update(color::warning(), true)
     }
 
     fn set_message(&self, msg: impl Into<Cow<'static, str>>) {
+        let msg = msg.into();
+        self.event(&msg);
         self.pb.set_message(msg)
     }
 
-    fn finish(&self, message: impl Into<Cow<'static, str>>, color: Color) -> Result<()> {
-        self.do_update(color, false)?;
+    fn finish(&self, message: impl Into<Cow<'static, str>>, style: Style) -> Result<()> {
+        let message = message.into();
+        self.event(&message);
+        self.do_update(style, false)?;
         self.pb.finish_with_message(message);
         Ok(())
     }
 
-    fn do_update(&self, color: Color, show_spinner: bool) -> Result<()> {
-        let bullet = Yellow.paint(format!(
+    fn event(&self, message: &str) {
+        let commit = self.oid.to_string();
+        events::progress("commit", Some(&commit), self.pr_num, message);
+    }
+
+    fn do_update(&self, style: Style, show_spinner: bool) -> Result<()> {
+        let bullet = color::warning().paint(format!(
             "* {}",
             self.pr_num
                 .map(|pr| format!("#{pr}"))
                 .unwrap_or(self.oid.to_string()[..8].to_string())
         ));
 
-        let url = Style::default()
-            .dimmed()
-            .paint(self.pr_url.clone().unwrap_or_default());
+        let url = color::dimmed().paint(self.pr_url.clone().unwrap_or_default());
         self.pb.set_prefix(format!(
             "{} {url}",
             self.pr_title.as_ref().unwrap_or(&self.title)
@@ -103,10 +240,10 @@ impl SubmitProgress {
 
         let spinner = if show_spinner { "{spinner} " } else { "" };
 
-        let style = ProgressStyle::default_spinner()
+        let style = render::spinner()
             .template(&format!(
                 "{bullet} {} {{prefix}}",
-                color.paint(format!("[{spinner}{{msg}}]")),
+                style.paint(format!("[{spinner}{{msg}}]")),
             ))
             .context("invalid style")?;
 
@@ -116,21 +253,34 @@ impl SubmitProgress {
     }
 }
 
-impl Submit {
-    fn pulls(&self) -> PullRequestHandler {
-        self.octocrab.pulls(&self.gh_repo.owner, &self.gh_repo.repo)
+/// How `[submit]` names the branches commits are pushed to
+#[derive(Clone)]
+struct BranchNaming {
+    stack_name: String,
+    use_indexed_branches: bool,
+    allow_force: bool,
+    keep_revisions: bool,
+    prefix: Option<String>,
+}
+
+impl BranchNaming {
+    fn new(stack: &Stack, config: &config::Submit) -> Self {
+        Self {
+            stack_name: stack.name().to_string(),
+            use_indexed_branches: config.use_indexed_branches,
+            allow_force: config.allow_force,
+            keep_revisions: config.keep_revisions,
+            prefix: config.branch_prefix.clone(),
+        }
     }
 
-    async fn submit_commit(
-        &self,
-        commit: Commit,
-        index: usize,
-        progress: &mut SubmitProgress,
-        branch_name_tx: watch::Sender<Option<String>>,
-        pr_info_tx: watch::Sender<Option<PrInfo>>,
-    ) -> Result<(Oid, Metadata)> {
+    /// Returns the branch `commit`, at `index` in the stack, is pushed to, whether that's a
+    /// force push, and the immutable copy of its revision if one is kept
+    fn branches(&self, commit: &Commit, index: usize) -> (String, bool, Option<String>) {
+        let changed = commit.changed();
+        let revision = commit.metadata.revision.unwrap_or(0) + u32::from(changed);
+
         // Figure out the branch name
-        let force_push = commit.metadata.branch.is_some();
         let branch_name = commit.metadata.branch.clone().unwrap_or_else(|| {
             let branch_name = match self.use_indexed_branches {
                 true => format!("fel/{}/{index}", &self.stack_name),
@@ -139,23 +289,81 @@ impl Submit {
                 }
             };
 
-            match self.branch_prefix.as_ref() {
+            match self.prefix.as_ref() {
                 Some(prefix) => format!("{prefix}/{branch_name}"),
                 None => branch_name,
             }
         });
 
+        // If we can't force push, every new revision of the commit goes to a new branch
+        let (branch_name, force_push) = match self.allow_force {
+            true => (branch_name, commit.metadata.branch.is_some()),
+            false if changed => (revision_branch(&branch_name, revision), false),
+            false => (branch_name, false),
+        };
+
+        // Keep an immutable copy of new revisions, unless the head branch already is one
+        let revision_branch = (self.keep_revisions && self.allow_force && changed)
+            .then(|| format!("{branch_name}-v{revision}"));
+
+        (branch_name, force_push, revision_branch)
+    }
+}
+
+impl Submit {
+    /// Wait for a GitHub `request` for at most `api.timeout`
+    async fn timed<F: Future>(&self, doing: &str, request: F) -> Result<F::Output> {
+        gh::timeout(self.timeout, doing, request).await
+    }
+
+    /// Fail if `submit.deadline` has passed. The submit is only stopped before it starts
+    /// something new, so whatever it already did to a PR still finishes and is recorded
+    fn check_deadline(&self, doing: &str) -> Result<()> {
+        match self.deadline {
+            Some(deadline) if Instant::now() >= deadline => {
+                let doing = format!("past submit.deadline, before {doing}");
+                Err(crate::Error::timeout(doing).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn submit_commit(
+        &self,
+        commit: Commit,
+        index: usize,
+        progress: &mut SubmitProgress,
+        branch_name_tx: watch::Sender<Option<String>>,
+        pr_info_tx: watch::Sender<Option<PrInfo>>,
+    ) -> Result<Submitted> {
+        let changed = commit.changed();
+        let revision = commit.metadata.revision.unwrap_or(0) + u32::from(changed);
+        let (branch_name, force_push, revision_branch) = self.naming.branches(&commit, index);
+
         // Push the branch to remote
         progress.set_message("pushing branch");
-        self.pusher
-            .push(commit.id(), branch_name.clone(), force_push)
-            .await
-            .context("push branch")?;
+        let push_head = self
+            .pusher
+            .push(commit.id(), branch_name.clone(), force_push);
+        match &revision_branch {
+            Some(revision_branch) => {
+                let push_revision = self
+                    .pusher
+                    .push(commit.id(), revision_branch.clone(), false);
+                futures::try_join!(push_head, push_revision).context("push branch")?;
+            }
+            None => push_head.await.context("push branch")?,
+        }
 
         branch_name_tx.send_replace(Some(branch_name.clone()));
 
-        // Now we need to figure out the branch name of the parent
-        let base_branch = if index == 0 {
+        // Now we need to figure out the branch name of the parent, unless the commit
+        // names its own base with `Fel-Base`
+        let base_branch = if let Some(base) = &commit.options.base {
+            base.clone()
+        } else if index == 0 || self.fork_owner.is_some() {
+            // PRs can't be based on branches in a fork, so every PR from a fork is made
+            // against upstream
             self.stack_upstream.clone()
         } else {
             let mut rx = self
@@ -173,27 +381,87 @@ impl Submit {
             branch.clone().context("branch was none")?
         };
 
+        let template_title = self.title_template.as_ref().map(|template| {
+            template
+                .replace("{index}", &(index + 1).to_string())
+                .replace("{total}", &self.stack_size.to_string())
+                .replace("{title}", &commit.title)
+        });
+
         // Now we can create the PR
+        self.check_deadline(&format!("submitting PR for {}", commit.title))?;
+        let pr_start = Instant::now();
         let created_pr;
         let pr = match commit.metadata.pr {
-            Some(pr) => {
+            Some(pr) if self.naming.allow_force || !changed => {
                 progress.set_message(format!("fetching PR {pr}"));
                 created_pr = false;
-                self.pulls()
-                    .get(pr)
+                self.forge
+                    .get_pr(pr)
                     .await
                     .context("failed to get existing PR")?
             }
+            Some(old_pr) => {
+                // GitHub doesn't allow changing the head of a PR, so the new revision
+                // branch gets a new PR and the old one is closed in favor of it
+                progress.set_message(format!("replacing PR {old_pr}"));
+                created_pr = true;
+                let old = self
+                    .forge
+                    .get_pr(old_pr)
+                    .await
+                    .context("failed to get existing PR")?;
+
+                let old_body = old.body.unwrap_or_default();
+                let old_body = strip_footer(&old_body, &self.delimiters);
+
+                let title = match &template_title {
+                    Some(title) => title,
+                    None => old.title.as_ref().unwrap_or(&commit.title),
+                };
+                let pr = self
+                    .create_pr(
+                        title,
+                        &branch_name,
+                        &base_branch,
+                        old_body,
+                        old.draft.unwrap_or_default(),
+                    )
+                    .await?;
+
+                self.forge
+                    .comment(
+                        old_pr,
+                        &format!("Revision {revision} was submitted as #{}", pr.number),
+                    )
+                    .await
+                    .context("failed to comment on superseded pr")?;
+
+                let close = pulls::Update {
+                    close: true,
+                    ..Default::default()
+                };
+                self.forge
+                    .update_pr(old_pr, close)
+                    .await
+                    .context("failed to close superseded pr")?;
+
+                pr
+            }
             None => {
                 progress.set_message("creating PR");
                 created_pr = true;
                 tracing::debug!(branch_name, base_branch, "creating PR");
-                self.pulls()
-                    .create(&commit.title, &branch_name, &base_branch)
-                    .body(&commit.body)
-                    .send()
-                    .await
-                    .context("failed to create pr")?
+                self.create_pr(
+                    template_title.as_ref().unwrap_or(&commit.title),
+                    &branch_name,
+                    &base_branch,
+                    &commit.body,
+                    self.forced_draft
+                        .or(commit.options.draft)
+                        .unwrap_or(self.draft),
+                )
+                .await?
             }
         };
 
@@ -201,70 +469,379 @@ impl Submit {
         progress.pr_title = pr.title.clone();
         progress.pr_url = pr.html_url.as_ref().map(|url| url.to_string());
         progress.update()?;
+        // Every footer waits on this, so send it before the other PR updates
         pr_info_tx.send_replace(Some(PrInfo {
             number: pr.number,
-            title: pr.title.unwrap_or_default(),
+            title: template_title
+                .clone()
+                .or(pr.title.clone())
+                .unwrap_or_default(),
+            url: pr
+                .html_url
+                .as_ref()
+                .map(|url| url.to_string())
+                .unwrap_or_default(),
+            commit: commit.id().to_string(),
+            branch: branch_name.clone(),
+            revision,
+            author: commit.author.clone(),
+            index,
+            base: commit.options.base.clone(),
+            base_url: commit
+                .options
+                .base
+                .as_ref()
+                .map(|base| self.gh_repo.branch_url(base)),
+            picked_from: commit.metadata.picked_from_pr,
+            backports: commit.metadata.backports.clone(),
+            status: match (&pr.state, pr.merged_at, pr.draft) {
+                (_, Some(_), _) => "merged",
+                (Some(IssueState::Closed), _, _) => "closed",
+                (_, _, Some(true)) => "draft",
+                _ => "open",
+            },
+            custom: commit.metadata.custom.clone(),
         }));
 
-        // We may not have known the pr numbers of every commit in the stack until after
-        // we created all the prs, so now we need to update the prs with the footer
-        // We also may need to update the base branch to restack the prs
-        // TODO If the commit messages are authoritaive we can skip this step and do
-        // this all with only one round trip
-        let footer = self
-            .footer_rx
-            .clone()
-            .wait_for(|footer| footer.is_some())
-            .await
-            .context("wait for footer")?
-            .clone()
-            .context("footer was none")?;
+        let mut labels: Vec<String> = Vec::new();
+        for label in self.labels.iter().chain(&commit.options.labels) {
+            if !labels.contains(label) && !pr.labels.iter().flatten().any(|l| &l.name == label) {
+                labels.push(label.clone());
+            }
+        }
+        if !labels.is_empty() {
+            progress.set_message("adding labels");
+            self.forge
+                .add_labels(pr.number, &labels)
+                .await
+                .context("failed to add labels")?;
+        }
 
-        let original_body = pr.body.clone().unwrap_or_default();
-        let original_body = original_body.split(BODY_DELIM).next().unwrap_or_default();
+        if created_pr {
+            let reviewers = self
+                .reviewers
+                .get(&commit.id())
+                .map(|reviewers| reviewers::unrequested(&pr, reviewers))
+                .unwrap_or_default();
+            if !reviewers.is_empty() {
+                progress.set_message("requesting reviews");
+                self.forge
+                    .request_reviews(pr.number, &reviewers.users, &reviewers.teams)
+                    .await
+                    .context("failed to request reviews")?;
+            }
+        }
 
-        let body = format!("{original_body}\n\n{BODY_DELIM}\n\n{footer}");
+        let pr_time = pr_start.elapsed();
 
-        progress.set_message("updating PR footer");
-        self.pulls()
-            .update(pr.number)
-            .base(base_branch)
-            .body(body)
-            .send()
-            .await
-            .context("failed to update pr")?;
+        // A PR's body only has to wait for the PRs it references. The full tree references
+        // every PR in the stack, and a new overview issue is only made once they all exist, so
+        // only those bodies wait for the footer. Everything else is updated right away
+        let full_footer =
+            self.footer == Footer::Full && self.footer_placement == FooterPlacement::Body;
+        let new_overview = self.overview_issue
+            && self.overview_link != OverviewLink::None
+            && self.overview.read().is_none();
+        let needs_footer = full_footer || new_overview;
+
+        let footer_start = Instant::now();
+        let current_body = pr.body.clone().unwrap_or_default();
+        let mut current_base = pr.base.ref_field.clone();
+        // Titles are re-rendered every submit, since the stack may have been reordered
+        let mut title = template_title.filter(|title| Some(title) != pr.title.as_ref());
+
+        // Restacking doesn't reference other PRs, so when the body has to wait it's done
+        // first, and the body follows once the footer is rendered
+        if needs_footer && (base_branch != current_base || title.is_some()) {
+            progress.set_message("updating PR base");
+            let update = pulls::Update {
+                base: Some(base_branch.clone()),
+                title: title.take(),
+                ..Default::default()
+            };
+            self.forge
+                .update_pr(pr.number, update)
+                .await
+                .context("failed to update pr")?;
+            current_base = base_branch.clone();
+        }
+
+        let waited = Instant::now();
+        let footer = match needs_footer {
+            true => self.rendered_footer().await,
+            false => Some(String::new()),
+        };
+        let mut wait_time = waited.elapsed();
+        let rendered = footer.is_some();
+        let footer = footer.unwrap_or_default();
+
+        let original_body = match self.authoritative_commits {
+            true => commit.body.trim_end(),
+            false => strip_footer(&current_body, &self.delimiters),
+        };
+
+        let review_map = self
+            .review_maps
+            .get(&commit.id())
+            .map(|items| review_map::render(items, &current_body))
+            .unwrap_or_default();
+
+        // A new overview was waited for above, an existing one is known from the start
+        let overview = self.overview.read().clone();
+        let top = index + 1 == self.stack_size;
+        let link = match (self.overview_link, &overview) {
+            (OverviewLink::Closes, Some(overview)) if top => {
+                format!("\nCloses #{}\n", overview.number)
+            }
+            (OverviewLink::Closes | OverviewLink::Refs, Some(overview)) => {
+                format!("\nRefs #{}\n", overview.number)
+            }
+            _ => String::new(),
+        };
 
-        let mut history = commit.metadata.history.clone().unwrap_or(Vec::new());
-        if Some(commit.id().to_string()) == commit.metadata.commit {
-            progress.finish("up to date", Green)?;
+        let sections = self
+            .sections
+            .get(&commit.id())
+            .map(String::as_str)
+            .unwrap_or_default();
+        let compact = format!(
+            "Part of stack {} ({}/{})\n",
+            self.stack_name,
+            index + 1,
+            self.stack_size
+        );
+        let footer = match (self.footer, self.footer_placement) {
+            (Footer::Full, FooterPlacement::Body) => footer,
+            (Footer::Compact, FooterPlacement::Body) => compact.clone(),
+            _ => String::new(),
+        };
+        let added = format!("{sections}{review_map}{footer}{link}");
+        // Without anything to add, the body is left as the author wrote it
+        let body = match added.is_empty() {
+            true => original_body.to_string(),
+            false => format!("{original_body}\n\n{}\n\n{added}", self.delimiters[0]),
+        };
+
+        // Most PRs in a resubmitted stack don't change, so only update the ones that did.
+        // Without the footer the body is left for the next submit
+        let changed_body = rendered && body != current_body;
+        if changed_body || base_branch != current_base || title.is_some() {
+            progress.set_message("updating PR footer");
+            let update = pulls::Update {
+                base: Some(base_branch),
+                title,
+                body: rendered.then_some(body),
+                close: false,
+            };
+            self.forge
+                .update_pr(pr.number, update)
+                .await
+                .context("failed to update pr")?;
+        }
+
+        // The comment holds what the body would have, so it waits for the footer the same way
+        let comment = match (self.footer, self.footer_placement) {
+            (Footer::Full, FooterPlacement::Comment) => {
+                let waited = Instant::now();
+                let footer = self.rendered_footer().await;
+                wait_time += waited.elapsed();
+                footer
+            }
+            (Footer::Compact, FooterPlacement::Comment) => Some(compact),
+            _ => Some(String::new()),
+        };
+        let stack_comment = match comment.as_deref() {
+            // Without the footer the comment is left for the next submit
+            None => commit.metadata.stack_comment,
+            // The tree moved back to the body, or was turned off, so the comment is stale
+            Some("") => {
+                if let Some(id) = commit.metadata.stack_comment {
+                    progress.set_message("removing stack comment");
+                    stack_comment::remove(self.forge.as_ref(), pr.number, id).await?;
+                }
+                None
+            }
+            Some(comment) => {
+                progress.set_message("updating stack comment");
+                Some(stack_comment::upsert(self.forge.as_ref(), pr.number, comment).await?)
+            }
+        };
+
+        // Let reviewers know what changed since the revision they last saw
+        let comment = match self.revision_comments {
+            RevisionComments::Always => true,
+            RevisionComments::OnContentChange => !self.rebased(&commit).await,
+            RevisionComments::Never => false,
+        };
+        if changed && !created_pr && comment {
+            let diff_url = match (&revision_branch, &commit.metadata.commit) {
+                (Some(branch), _) if revision > 1 => Some(self.gh_repo.compare_url(
+                    &self.head(&format!("{branch_name}-v{}", revision - 1)),
+                    &self.head(branch),
+                )),
+                (_, Some(previous)) => {
+                    Some(self.gh_repo.compare_url(previous, &commit.id().to_string()))
+                }
+                _ => None,
+            };
+
+            let mut context = tera::Context::new();
+            context.insert("revision", &revision);
+            context.insert("diff_url", &diff_url);
+            context.insert("previous", &commit.metadata.commit);
+            context.insert("commit", &commit.id().to_string());
+            context.insert("title", &commit.title);
+            context.insert("reviewed", &self.reviewed.contains(&pr.number));
+            let message = render::tera("revision_comment.md", &self.revision_comment_template)?
+                .render("revision_comment.md", &context)
+                .context("render revision comment")?;
+
+            progress.set_message("commenting on PR");
+            self.forge
+                .comment(pr.number, &message)
+                .await
+                .context("failed to comment on pr")?;
+        }
+
+        let footer_time = footer_start.elapsed() - wait_time;
+
+        // The discussion and a new overview are only known once the footer is rendered, and
+        // every commit's note records them
+        self.rendered_footer().await;
+        let discussion = self.discussion.read().clone();
+        let overview = self.overview.read().clone();
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .context("clock is before 1970")?
+            .as_secs();
+        let mut history = commit.metadata.history.clone().unwrap_or_default();
+        let outcome = if !changed {
+            progress.finish("up to date", color::success())?;
+            Outcome::UpToDate
         } else {
+            history.push(commit.id().to_string());
             if created_pr {
-                progress.finish("created", Yellow)?;
+                progress.finish("created", color::warning())?;
+                Outcome::Created
             } else {
-                progress.finish("updated", Yellow)?;
+                progress.finish("updated", color::warning())?;
+                Outcome::Updated
             }
-            history.push(commit.id().to_string());
-        }
+        };
 
         // TODO Update the metadata after the commit
         let metadata = Metadata {
             pr: Some(pr.number),
             branch: Some(branch_name),
-            revision: Some(commit.metadata.revision.unwrap_or(0) + 1),
+            revision: Some(revision),
             commit: Some(commit.id().to_string()),
             history: Some(history),
             pr_url: Some(pr.html_url.map(|url| url.to_string()).unwrap_or_default()),
+            discussion_id: discussion.as_ref().map(|discussion| discussion.id.clone()),
+            discussion_url: discussion.map(|discussion| discussion.url),
+            overview_issue: overview.as_ref().map(|overview| overview.number),
+            overview_url: overview.map(|overview| overview.url),
+            stack_comment,
+            remote: Some(self.remote.clone()),
+            upstream: Some(self.upstream.clone()),
+            stack: Some(self.stack_name.clone()),
+            depends_on: self
+                .depends_on
+                .as_ref()
+                .map(|dependency| dependency.stack.clone()),
+            dev_branch: commit.metadata.dev_branch.clone(),
+            submitted_at: commit.metadata.submitted_at.or(Some(now)),
+            updated_at: match changed {
+                true => Some(now),
+                false => commit.metadata.updated_at.or(Some(now)),
+            },
+            ..commit.metadata.clone()
         };
 
-        Ok::<_, anyhow::Error>((commit.id(), metadata))
+        // Notes are queued as each commit finishes, and written together once they all have
+        self.git
+            .add_note(metadata.clone(), commit.id())
+            .await
+            .context("failed to write commit metadata")?;
+
+        Ok::<_, anyhow::Error>(Submitted {
+            id: commit.id(),
+            metadata,
+            outcome,
+            pr_time,
+            footer_time,
+        })
+    }
+
+    /// Returns true if `commit` changed since it was last submitted, but its diff didn't
+    async fn rebased(&self, commit: &Commit) -> bool {
+        let Some(previous) = commit.metadata.commit.as_ref().filter(|_| commit.changed()) else {
+            return false;
+        };
+
+        // If the previous revision is gone we can't tell, so assume it changed
+        let patch_ids = async {
+            let previous = self.git.patch_id(Oid::from_str(previous)?).await?;
+            Ok::<_, anyhow::Error>((previous, self.git.patch_id(commit.id()).await?))
+        };
+        matches!(patch_ids.await, Ok((previous, current)) if previous == current)
+    }
+
+    /// Returns the PRs in `stack` whose current head has been reviewed and would be replaced
+    /// by a new revision
+    async fn reviewed_prs(&self, stack: &Stack) -> Result<HashSet<u64>> {
+        let mut reviewed = HashSet::new();
+        for commit in stack.iter().filter(|commit| commit.changed()) {
+            let (Some(pr), Some(head)) = (commit.metadata.pr, &commit.metadata.commit) else {
+                continue;
+            };
+
+            let reviews = self
+                .forge
+                .list_reviews(pr)
+                .await
+                .with_context(|| format!("failed to list reviews of PR {pr}"))?;
+
+            let head_reviewed = reviews.iter().any(|review| {
+                review.commit_id.as_ref() == Some(head)
+                    && matches!(
+                        review.state,
+                        Some(
+                            ReviewState::Approved
+                                | ReviewState::ChangesRequested
+                                | ReviewState::Commented
+                        )
+                    )
+            });
+
+            if head_reviewed {
+                reviewed.insert(pr);
+            }
+        }
+
+        Ok(reviewed)
     }
 
+    /// Returns the number of branches that will be pushed to submit `stack`
+    fn push_count(&self, stack: &Stack) -> usize {
+        let revisions = match self.naming.keep_revisions && self.naming.allow_force {
+            true => stack.iter().filter(|commit| commit.changed()).count(),
+            false => 0,
+        };
+        stack.len() + revisions
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn new(
         stack: &Stack,
         octocrab: Arc<Octocrab>,
         gh_repo: &GHRepo,
         config: &Config,
         footer_rx: watch::Receiver<Option<String>>,
+        git: GitActor,
+        forge: Arc<dyn Forge>,
+        renderer: Arc<dyn StackRenderer>,
     ) -> Self {
         let pusher = BatchedPusher::default();
         let branch_names = RwLock::new(HashMap::new());
@@ -272,18 +849,118 @@ impl Submit {
 
         Self {
             pusher,
-            use_indexed_branches: config.submit.use_indexed_branches,
-            branch_prefix: config.submit.branch_prefix.clone(),
+            naming: BranchNaming::new(stack, &config.submit),
+            draft: config.submit.draft,
+            forced_draft: config.policy.as_ref().and_then(|policy| policy.draft),
+            labels: config.submit.labels.clone(),
             octocrab,
             gh_repo: gh_repo.clone(),
             stack_name: stack.name().to_string(),
             stack_upstream: stack.upstream().to_string(),
+            depends_on: stack.depends_on().cloned(),
+            upstream: config.default_upstream.clone(),
+            remote: config.default_remote.clone(),
+            fork_owner: None,
+            reviewed: HashSet::new(),
+            revision_comments: config.submit.revision_comments,
+            authoritative_commits: config.submit.authoritative_commits,
+            revision_comment_template: config
+                .submit
+                .revision_comment_template
+                .clone()
+                .unwrap_or(include_str!("../templates/revision_comment.md").to_string()),
+            git,
+            review_maps: HashMap::new(),
+            sections: HashMap::new(),
+            forge,
+            timeout: config.api.timeout(),
+            deadline: config
+                .submit
+                .deadline
+                .map(|deadline| Instant::now() + Duration::from_secs(deadline)),
+            reviewers: HashMap::new(),
+            discussion_category: config.submit.discussion_category.clone(),
+            discussion_threshold: config.submit.discussion_threshold,
+            discussion: RwLock::new(stack.iter().find_map(|commit| {
+                Some(Discussion {
+                    id: commit.metadata.discussion_id.clone()?,
+                    url: commit.metadata.discussion_url.clone()?,
+                })
+            })),
+            title_template: config.submit.title_template.clone(),
+            stack_size: stack.len(),
+            overview_issue: config.submit.overview_issue,
+            overview_link: config.submit.overview_link,
+            overview: RwLock::new(stack.iter().find_map(|commit| {
+                Some(Overview {
+                    number: commit.metadata.overview_issue?,
+                    url: commit.metadata.overview_url.clone()?,
+                })
+            })),
+            renderer,
+            footer: config.submit.footer,
+            footer_placement: config.submit.footer_placement,
+            delimiters: delimiters(&config.submit)
+                .into_iter()
+                .map(str::to_string)
+                .collect(),
             branch_names,
             pr_info,
             footer_rx,
         }
     }
 
+    /// Create a PR for `branch`, or adopt the open PR for it if a submit from another machine,
+    /// or an earlier request that timed out, already created one
+    async fn create_pr(
+        &self,
+        title: &str,
+        branch: &str,
+        base: &str,
+        body: &str,
+        draft: bool,
+    ) -> Result<PullRequest> {
+        let result = self
+            .forge
+            .create_pr(title, &self.head(branch), base, body, draft)
+            .await;
+
+        match result {
+            Err(err) if pulls::already_exists(&err) => {
+                tracing::info!(branch, "PR already exists, adopting it");
+                let owner = self.fork_owner.as_ref().unwrap_or(&self.gh_repo.owner);
+                self.forge
+                    .list_open_prs(owner, branch)
+                    .await
+                    .context("failed to find existing pr")?
+                    .into_iter()
+                    .next()
+                    .with_context(|| format!("a PR already exists for {branch} but wasn't found"))
+            }
+            result => result.context("failed to create pr"),
+        }
+    }
+
+    /// Wait for the footer, which is rendered once every PR in the stack is known. Returns
+    /// None if it never will be, because another commit or rendering failed. That fails the
+    /// submit, but the PRs that were submitted still finish without it and are recorded
+    async fn rendered_footer(&self) -> Option<String> {
+        self.footer_rx
+            .clone()
+            .wait_for(|footer| footer.is_some())
+            .await
+            .ok()?
+            .clone()
+    }
+
+    /// Returns how GitHub refers to `branch` from the repo PRs are made against
+    fn head(&self, branch: &str) -> String {
+        match &self.fork_owner {
+            Some(owner) => format!("{owner}:{branch}"),
+            None => branch.to_string(),
+        }
+    }
+
     async fn render_footer(
         &self,
         commits: Vec<Oid>,
@@ -308,23 +985,188 @@ impl Submit {
             );
         }
 
-        // TODO This is totally overkill
-        let mut tera = Tera::default();
-        tera.add_raw_template("footer.html", include_str!("../templates/footer.html"))?;
-        let mut context = tera::Context::new();
-        context.insert("prs", &prs);
-        context.insert("stack_name", &self.stack_name);
-        context.insert("upstream", &self.stack_upstream);
-        let footer = tera
-            .render("footer.html", &context)
-            .context("render footer")?;
-        tracing::debug!(footer, "rendered footer");
+        // A bottom commit with its own base takes the stack off upstream
+        let upstream = prs
+            .last()
+            .and_then(|bottom| bottom.base.clone())
+            .unwrap_or_else(|| self.stack_upstream.clone());
+        let mut store = RenderStore {
+            stack_name: self.stack_name.clone(),
+            stack_size: prs.len(),
+            upstream_url: self.gh_repo.branch_url(&upstream),
+            // The whole stack is the diff from upstream to the branch of its top commit
+            stack_url: prs
+                .first()
+                .map(|tip| self.gh_repo.compare_url(&upstream, &self.head(&tip.branch))),
+            upstream,
+            depends_on_stack: self
+                .depends_on
+                .as_ref()
+                .map(|dependency| dependency.stack.clone()),
+            depends_on_pr: self.depends_on.as_ref().map(|dependency| dependency.pr),
+            prs,
+            ..Default::default()
+        };
+
+        // Updating the overview and discussion is slow, so when they already exist the
+        // footer only needs their urls and is sent to every PR before they're updated.
+        // New ones have to be created first to find out their urls
+        let existing_overview = self.overview.read().clone();
+        store.overview_url = match (self.overview_issue, &existing_overview) {
+            (true, Some(overview)) => Some(overview.url.clone()),
+            (true, None) => Some(self.update_overview(&store, None).await?.url),
+            (false, _) => None,
+        };
 
+        let tree = self.renderer.footer(&store)?;
+
+        // Large stacks get the full tree in a discussion, and just a link to it in each PR
+        let category = self
+            .discussion_category
+            .as_ref()
+            .filter(|_| store.prs.len() >= self.discussion_threshold);
+        let existing_discussion = self.discussion.read().clone();
+        store.url = match (category, &existing_discussion) {
+            (Some(_), Some(discussion)) => Some(discussion.url.clone()),
+            (Some(category), None) => {
+                Some(self.update_discussion(category, None, &tree).await?.url)
+            }
+            (None, _) => None,
+        };
+
+        let footer = match store.url {
+            Some(_) => self.renderer.discussion_footer(&store)?,
+            None => tree.clone(),
+        };
+        tracing::debug!(footer, "rendered footer");
         footer_tx.send_replace(Some(footer));
+
+        let overview = async {
+            match (self.overview_issue, existing_overview) {
+                (true, Some(overview)) => self
+                    .update_overview(&store, Some(overview.number))
+                    .await
+                    .map(drop),
+                _ => Ok(()),
+            }
+        };
+        let discussion = async {
+            match (category, existing_discussion) {
+                (Some(category), Some(discussion)) => self
+                    .update_discussion(category, Some(&discussion.id), &tree)
+                    .await
+                    .map(drop),
+                _ => Ok(()),
+            }
+        };
+        futures::try_join!(overview, discussion)?;
+
         Ok::<_, anyhow::Error>(())
     }
+
+    /// Create or update the overview issue with the PRs in `store`
+    async fn update_overview(
+        &self,
+        store: &RenderStore,
+        existing: Option<u64>,
+    ) -> Result<Overview> {
+        let body = self.renderer.overview(store)?;
+        let overview = self
+            .timed(
+                "updating the overview issue",
+                overview::upsert(
+                    &self.octocrab,
+                    &self.gh_repo,
+                    existing,
+                    &format!("Stack: {}", self.stack_name),
+                    &body,
+                ),
+            )
+            .await??;
+        *self.overview.write() = Some(overview.clone());
+        Ok(overview)
+    }
+
+    /// Create or update the discussion mirroring the stack `tree`
+    async fn update_discussion(
+        &self,
+        category: &str,
+        existing: Option<&str>,
+        tree: &str,
+    ) -> Result<Discussion> {
+        let discussion = self
+            .timed(
+                "updating the stack discussion",
+                discussion::upsert(
+                    &self.octocrab,
+                    &self.gh_repo,
+                    existing,
+                    category,
+                    &format!("Stack: {}", self.stack_name),
+                    tree,
+                ),
+            )
+            .await?
+            .context("failed to mirror stack to discussion")?;
+        *self.discussion.write() = Some(discussion.clone());
+        Ok(discussion)
+    }
+}
+
+/// Warn if merging a PR in the GitHub UI would copy its body, and so the footer, into the
+/// merge commit message. `fel land` strips the footer, but GitHub can't be told to
+async fn warn_merge_messages(octocrab: &Octocrab, gh_repo: &GHRepo) {
+    let route = format!("/repos/{}/{}", gh_repo.owner, gh_repo.repo);
+    let settings: serde_json::Value = match octocrab.get(route, None::<&()>).await {
+        Ok(settings) => settings,
+        Err(error) => {
+            tracing::debug!(?error, "failed to get repo merge settings");
+            return;
+        }
+    };
+
+    let uses_body = ["merge_commit_message", "squash_merge_commit_message"]
+        .iter()
+        .any(|setting| settings[setting] == "PR_BODY");
+    if !uses_body {
+        return;
+    }
+    let message = format!(
+        "{}/{} puts PR descriptions in merge commits, land with `fel land` to keep the fel \
+        footer out of them",
+        gh_repo.owner, gh_repo.repo
+    );
+    // JSON logs are the only thing on stderr, so the warning is one of them
+    match events::json() {
+        true => tracing::warn!("{message}"),
+        false => eprintln!("{} {message}", color::warning().paint("warning:")),
+    }
+}
+
+/// Show the step of the submit the whole stack is in
+fn stage(pb: &ProgressBar, phase: &str, message: &'static str) {
+    events::progress(phase, None, None, message);
+    pb.set_message(message);
 }
 
+/// Returns the name of the branch holding `revision` of a commit whose branch is `branch`,
+/// replacing any revision suffix `branch` already has. The revision is a suffix rather than a
+/// path component, since `<branch>/v<revision>` can't exist next to `<branch>` itself
+fn revision_branch(branch: &str, revision: u32) -> String {
+    // Revisions used to be pushed to `/v<revision>`, which notes may still name
+    let base = ["-v", "/v"]
+        .into_iter()
+        .find_map(|separator| {
+            let (base, suffix) = branch.rsplit_once(separator)?;
+            suffix.parse::<u32>().is_ok().then_some(base)
+        })
+        .unwrap_or(branch);
+    format!("{base}-v{revision}")
+}
+
+/// Push a branch for every commit in `stack` to `remote`, open or update a PR for each one in
+/// `gh_repo`, and record them in the commits' notes. Unless `force` is set, asks before
+/// replacing a revision that has already been reviewed
 pub async fn submit(
     stack: &Stack,
     remote: &mut Remote<'_>,
@@ -332,13 +1174,269 @@ pub async fn submit(
     gh_repo: &GHRepo,
     repo: &Repository,
     config: &Config,
-) -> Result<()> {
-    let progress = MultiProgress::new();
+    force: bool,
+) -> crate::Result<Profile> {
+    let forge = GitHub::new(octocrab.clone(), gh_repo.clone(), config.api).cached(repo);
+    let renderer = TeraRenderer::new(config.submit.footer_template.clone());
+    submit_with(
+        stack,
+        remote,
+        Arc::new(forge),
+        Arc::new(renderer),
+        octocrab,
+        gh_repo,
+        repo,
+        config,
+        force,
+    )
+    .await
+}
+
+/// What submitting a commit would do to its PR
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PlannedPr {
+    Create,
+    /// Open a new PR for the new revision, and close the old one in favor of it
+    Replace(u64),
+    Update(u64),
+    UpToDate(u64),
+}
+
+/// What submitting a commit would do
+pub struct Planned {
+    pub id: Oid,
+    pub title: String,
+    /// The branch the commit is pushed to, and whether that's a force push
+    pub branch: String,
+    pub force_push: bool,
+    /// The immutable copy of a new revision, if one is kept
+    pub revision_branch: Option<String>,
+    /// The branch its PR is made against
+    pub base: String,
+    pub pr: PlannedPr,
+}
+
+/// Plan submitting `stack` from the commits' notes and the config alone, without reaching the
+/// remote or GitHub. So `submit.branch_prefix = "@me"` isn't resolved, and a stack pushed to a
+/// fork is planned as if it weren't
+pub fn plan(stack: &Stack, config: &Config) -> Vec<Planned> {
+    let naming = BranchNaming::new(stack, &config.submit);
+    let mut planned: Vec<Planned> = Vec::with_capacity(stack.len());
+    for (index, commit) in stack.iter().enumerate() {
+        let (branch, force_push, revision_branch) = naming.branches(commit, index);
+        let base = match (&commit.options.base, planned.last()) {
+            (Some(base), _) => base.clone(),
+            (None, Some(parent)) => parent.branch.clone(),
+            (None, None) => stack.upstream().to_string(),
+        };
+        let pr = match commit.metadata.pr {
+            None => PlannedPr::Create,
+            Some(pr) if !commit.changed() => PlannedPr::UpToDate(pr),
+            Some(pr) if naming.allow_force => PlannedPr::Update(pr),
+            Some(pr) => PlannedPr::Replace(pr),
+        };
+        planned.push(Planned {
+            id: commit.id(),
+            title: commit.title.clone(),
+            branch,
+            force_push,
+            revision_branch,
+            base,
+            pr,
+        });
+    }
+    planned
+}
+
+/// Check `stack` and run the pre_submit hooks, before it's sent anywhere
+pub fn prepare(stack: &Stack, config: &Config) -> Result<()> {
+    validate::validate(stack, &config.validate)?;
+    hooks::run("pre_submit", &config.hooks.pre_submit, stack)?;
+    Ok(())
+}
+
+/// Submit `stack` the way `fel submit` does: [`prepare`] it, give a stack on a tag a branch to
+/// be made against, push through the push remote, close the PRs of dropped commits and run the
+/// post_submit hooks. `remote` is the remote PRs are made against
+pub async fn run(
+    stack: &mut Stack,
+    repo: &Repository,
+    mut remote: Remote<'_>,
+    octocrab: Arc<Octocrab>,
+    gh_repo: &GHRepo,
+    config: &Config,
+    force: bool,
+) -> Result<Profile> {
+    prepare(stack, config)?;
+
+    // PRs can only be made against branches, so stacks on a tag get a branch for it
+    if let Some(tag) = stack.base_tag().map(str::to_string) {
+        let branch = upstream::push_base_branch(repo, &mut remote, &tag, config.proxy.as_deref())
+            .context("failed to push base branch")?;
+        stack.set_upstream(branch);
+    }
+
+    let mut remote =
+        remotes::configured(repo, remote, gh_repo, config).context("failed to find push remote")?;
+    let profile = submit(
+        stack,
+        &mut remote,
+        octocrab.clone(),
+        gh_repo,
+        repo,
+        config,
+        force,
+    )
+    .await
+    .context("failed to submit")?;
+    stack
+        .refresh(repo)
+        .context("failed to read the submitted notes")?;
+
+    dropped::close_dropped(
+        stack,
+        repo,
+        config.submit.close_dropped,
+        &octocrab,
+        gh_repo,
+        config.api,
+    )
+    .await
+    .context("failed to close dropped PRs")?;
+
+    hooks::run("post_submit", &config.hooks.post_submit, stack)?;
+
+    Ok(profile)
+}
+
+/// Like `submit`, but work with PRs through `forge` and render footers and the overview issue
+/// with `renderer` instead of `submit.footer_template`. Features only GitHub has, like
+/// discussions and the overview issue, still go through `octocrab`
+#[allow(clippy::too_many_arguments)]
+pub async fn submit_with(
+    stack: &Stack,
+    remote: &mut Remote<'_>,
+    forge: Arc<dyn Forge>,
+    renderer: Arc<dyn StackRenderer>,
+    octocrab: Arc<Octocrab>,
+    gh_repo: &GHRepo,
+    repo: &Repository,
+    config: &Config,
+    force: bool,
+) -> crate::Result<Profile> {
+    Ok(submit_stack(
+        stack, remote, forge, renderer, octocrab, gh_repo, repo, config, force,
+    )
+    .await?)
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn submit_stack(
+    stack: &Stack,
+    remote: &mut Remote<'_>,
+    forge: Arc<dyn Forge>,
+    renderer: Arc<dyn StackRenderer>,
+    octocrab: Arc<Octocrab>,
+    gh_repo: &GHRepo,
+    repo: &Repository,
+    config: &Config,
+    force: bool,
+) -> Result<Profile> {
     let (footer_tx, footer_rx) = watch::channel(None);
 
-    let submit = Arc::new(Submit::new(stack, octocrab, gh_repo, config, footer_rx));
+    let git = GitActor::spawn(repo.path())?;
+    let mut submit = Submit::new(
+        stack, octocrab, gh_repo, config, footer_rx, git, forge, renderer,
+    );
+    submit.fork_owner = gh::get_repo(remote)
+        .ok()
+        .filter(|push_repo| !push_repo.same_repo(gh_repo))
+        .map(|push_repo| push_repo.owner);
+    // Only new branches get the prefix, so only look the user up when there are any
+    let new_branches = stack.iter().any(|commit| commit.metadata.branch.is_none());
+    if submit.naming.prefix.as_deref() == Some(ME) && new_branches {
+        let login = submit
+            .forge
+            .current_user()
+            .await
+            .context("failed to get the authenticated user for submit.branch_prefix")?;
+        submit.naming.prefix = Some(login);
+    }
 
-    let notify = Arc::new(Notify::new());
+    // A bad prefix or note must not be able to push over a real branch
+    let rules = submit
+        .timed(
+            "getting branch protection rules",
+            submit.forge.protected_branches(),
+        )
+        .await?;
+    let protected = Protected::new(
+        [stack.upstream(), &config.default_upstream]
+            .into_iter()
+            .map(str::to_string)
+            .chain(config.submit.protected_branches.iter().cloned())
+            .chain(rules),
+    )?;
+    for (index, commit) in stack.iter().enumerate() {
+        let (branch, _, revision_branch) = submit.naming.branches(commit, index);
+        for branch in std::iter::once(branch).chain(revision_branch) {
+            protected.check(&branch)?;
+        }
+    }
+
+    // Nor can a trailer take a PR somewhere the repo's policy doesn't allow
+    if let Some(policy) = &config.policy {
+        for commit in stack.iter() {
+            if let Some(base) = &commit.options.base {
+                policy
+                    .check_upstream(base)
+                    .with_context(|| format!("invalid base for {}", commit.title))?;
+            }
+        }
+    }
+
+    submit.reviewed = submit
+        .reviewed_prs(stack)
+        .await
+        .context("failed to check for reviews")?;
+
+    submit.review_maps = review_map::review_maps(stack, repo, &config.owners)
+        .context("failed to build review maps")?;
+
+    submit.sections =
+        sections::render(stack, repo, &config.sections).context("failed to render sections")?;
+
+    submit.reviewers =
+        reviewers::for_stack(stack, repo, config).context("failed to find reviewers")?;
+
+    if config.land.strip_footer {
+        // Only a warning, so a slow answer isn't worth failing the submit over
+        let check = warn_merge_messages(&submit.octocrab, gh_repo);
+        if let Err(error) = submit.timed("getting merge settings", check).await {
+            tracing::debug!("{error:#}");
+        }
+    }
+
+    if !submit.reviewed.is_empty() && !force {
+        let prs: Vec<_> = submit.reviewed.iter().map(|pr| format!("#{pr}")).collect();
+        let question = format!(
+            "{} already reviewed, push new revisions anyway? (use --force to skip this check)",
+            prs.join(", ")
+        );
+        anyhow::ensure!(prompt::confirm(&question)?, "submit aborted");
+    }
+
+    hooks::run("pre_push", &config.hooks.pre_push, stack)?;
+
+    // Progress bars are replaced by events in JSON mode
+    let progress = match events::draw_progress() {
+        true => MultiProgress::new(),
+        false => MultiProgress::with_draw_target(ProgressDrawTarget::hidden()),
+    };
+    let submit = Arc::new(submit);
+
+    // Tasks may start after the remote connects, so they need to see that it already has
+    let (connected_tx, connected_rx) = watch::channel(false);
 
     let tasks: FuturesUnordered<_> = stack
         .iter()
@@ -360,49 +1458,56 @@ pub async fn submit(
             let mut progress = SubmitProgress::new(&commit, pb).unwrap();
             progress.set_message("connecting to remote");
 
-            let notify = notify.clone();
+            let mut connected = connected_rx.clone();
             let submit = submit.clone();
             tokio::spawn(async move {
                 // Wait for the remote connection before proceding
-                notify.notified().await;
+                connected
+                    .wait_for(|connected| *connected)
+                    .await
+                    .context("wait for remote connection")?;
 
                 let result = submit
                     .submit_commit(commit, index, &mut progress, branch_name_tx, pr_info_tx)
-                    .await;
+                    .await
+                    .with_context(|| {
+                        format!(
+                            "failed to submit {} {}",
+                            &progress.oid.to_string()[..8],
+                            progress.title
+                        )
+                    });
 
                 if result.is_err() {
-                    progress.finish("failed", Red)?;
+                    progress.finish("failed", color::failure())?;
                 }
                 result
             })
         })
         .collect();
 
-    tokio::spawn({
-        let progress = progress.clone();
+    let footer_task = tokio::spawn({
         let submit = submit.clone();
         let commits = stack.iter().map(|c| c.id()).collect();
-        async move {
-            if let Err(error) = submit.render_footer(commits, footer_tx).await {
-                progress
-                    .println(format!("failed to render footer: {:?}", error))
-                    .ok();
-            }
-        }
+        async move { submit.render_footer(commits, footer_tx).await }
     });
 
     let upstream_pb = progress.insert_from_back(
         0,
         ProgressBar::new_spinner().with_finish(ProgressFinish::AndLeave),
     );
-    let style = ProgressStyle::default_spinner()
+    let style = render::spinner()
         .template("{prefix} {spinner} {msg}")
         .context("invalid style")?;
     upstream_pb.enable_steady_tick(Duration::from_millis(100));
     upstream_pb.set_style(style.clone());
-    upstream_pb.set_prefix(Yellow.paint(format!("* {}", stack.upstream())).to_string());
+    upstream_pb.set_prefix(
+        color::warning()
+            .paint(format!("* {}", stack.upstream()))
+            .to_string(),
+    );
 
-    let style = ProgressStyle::default_spinner()
+    let style = render::spinner()
         .template("{prefix} {msg}")
         .context("invalid style")?;
     let branch_pb = progress.insert(
@@ -410,33 +1515,113 @@ pub async fn submit(
         ProgressBar::new_spinner().with_finish(indicatif::ProgressFinish::AndLeave),
     );
     branch_pb.set_style(style);
-    branch_pb.set_prefix(Yellow.paint(format!("* {}", stack.name())).to_string());
+    branch_pb.set_prefix(
+        color::warning()
+            .paint(format!("* {}", stack.name()))
+            .to_string(),
+    );
 
-    upstream_pb.set_message("Connecting to remote");
+    stage(&upstream_pb, "connect", "Connecting to remote");
+    submit.check_deadline("connecting to the remote")?;
     let mut conn = remote
-        .connect_auth(git2::Direction::Push, Some(auth::callbacks()), None)
+        .connect_auth(
+            git2::Direction::Push,
+            Some(auth::callbacks()),
+            Some(auth::proxy_options(config.proxy.as_deref())),
+        )
         .context("failed to connect to repo")?;
-    notify.notify_waiters();
+    connected_tx.send_replace(true);
+
+    stage(&upstream_pb, "push", "Pushing branches");
+    submit.check_deadline("pushing branches")?;
+    let push_start = Instant::now();
+    submit
+        .pusher
+        .wait_for(
+            submit.push_count(stack),
+            conn.remote(),
+            config.proxy.as_deref(),
+        )
+        .await?;
 
-    upstream_pb.set_message("Pushing branches");
-    submit.pusher.wait_for(stack.len(), conn.remote()).await?;
+    let push_time = push_start.elapsed();
 
-    upstream_pb.set_message("Updating PRs");
+    stage(&upstream_pb, "prs", "Updating PRs");
     let results: Vec<_> = tasks.try_collect().await.context("failed to join")?;
+    let footer = footer_task.await.context("failed to join")?;
 
-    // Update all of the commit notes with the new metadata
-    // We have to to this on this thread because Repository
-    // is not thread safe.
-    upstream_pb.set_message("Writing metadata");
+    stage(&upstream_pb, "metadata", "Writing metadata");
+    let metadata_start = Instant::now();
+    let mut summaries = Vec::with_capacity(results.len());
+    let mut profile = Profile {
+        push: push_time,
+        ..Default::default()
+    };
+    let mut branches = Vec::new();
+    let mut failed = None;
     for result in results.into_iter() {
-        let (id, metadata) = result.context("push failed")?;
+        // The commits that were submitted still get their notes, so the next submit updates
+        // their PRs instead of opening new ones
+        let submitted = match result {
+            Ok(submitted) => submitted,
+            Err(error) => {
+                failed.get_or_insert(error.context("push failed"));
+                continue;
+            }
+        };
+        branches.extend(submitted.metadata.branch.clone());
 
-        metadata
-            .write(repo, id)
-            .context("failed to write commit metadata")?;
+        let pr = submitted
+            .metadata
+            .pr
+            .context("submitted commit has no PR")?;
+        profile.prs = profile.prs.max(submitted.pr_time);
+        profile.footers = profile.footers.max(submitted.footer_time);
+        profile.commits.push(CommitProfile {
+            id: submitted.id,
+            pr,
+            pr_time: submitted.pr_time,
+            footer_time: submitted.footer_time,
+        });
+        summaries.push(CommitSummary {
+            id: submitted.id,
+            pr,
+            outcome: submitted.outcome,
+        });
+    }
+    submit
+        .git
+        .write_notes()
+        .await
+        .context("failed to write commit metadata")?;
+    if let Err(error) = footer {
+        failed.get_or_insert(error.context("failed to render footer"));
+    }
+    if let Some(error) = failed {
+        return Err(error);
     }
 
     upstream_pb.finish_with_message("");
 
-    Ok(())
+    // Results arrive in whatever order the PRs finished, show them in stack order
+    let order: Vec<_> = stack.iter().map(|commit| commit.id()).collect();
+    summaries.sort_by_key(|summary| order.iter().position(|id| *id == summary.id));
+    profile
+        .commits
+        .sort_by_key(|commit| order.iter().position(|id| *id == commit.id));
+
+    let mut state = State::load(repo)?;
+    let entry = state.stack_mut(stack.name());
+    entry.remote = Some(config.default_remote.clone());
+    entry.upstream = Some(config.default_upstream.clone());
+    entry.submitted(
+        summaries.iter().map(|summary| summary.pr).collect(),
+        branches,
+    );
+    state.save().context("failed to save state")?;
+    profile.metadata = metadata_start.elapsed();
+
+    summary::print(repo, stack, &summaries, &profile).context("failed to print summary")?;
+
+    Ok(profile)
 }
@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use git2::{DiffStats, Oid, Repository};
+
+use crate::color;
+use crate::commit;
+use crate::stack::Stack;
+use crate::submit::Profile;
+
+/// What happened to a PR during a submit
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Created,
+    Updated,
+    UpToDate,
+}
+
+/// The result of submitting a single commit
+pub struct CommitSummary {
+    pub id: Oid,
+    pub pr: u64,
+    pub outcome: Outcome,
+}
+
+/// Print a summary of a finished submit, with the size of each commit so unexpectedly
+/// large ones stand out
+pub fn print(
+    repo: &Repository,
+    stack: &Stack,
+    commits: &[CommitSummary],
+    profile: &Profile,
+) -> Result<()> {
+    let count = |outcome| commits.iter().filter(|c| c.outcome == outcome).count();
+    println!(
+        "\nSubmitted {} PRs: {} created, {} updated, {} up to date",
+        commits.len(),
+        count(Outcome::Created),
+        count(Outcome::Updated),
+        count(Outcome::UpToDate),
+    );
+
+    for summary in commits.iter().rev() {
+        let commit = stack
+            .iter()
+            .find(|commit| commit.id() == summary.id)
+            .context("submitted commit not in stack")?;
+        let stats = commit::diff(repo, summary.id)?
+            .stats()
+            .context("failed to get diff stats")?;
+
+        let outcome = match summary.outcome {
+            Outcome::Created => color::warning().paint("created"),
+            Outcome::Updated => color::warning().paint("updated"),
+            Outcome::UpToDate => color::success().paint("up to date"),
+        };
+        println!(
+            "  #{} {} {} [{outcome}]",
+            summary.pr,
+            commit.title,
+            diffstat(&stats)
+        );
+    }
+
+    if let Some(tip) = stack.iter().last() {
+        let base = repo
+            .find_commit(stack.merge_base())
+            .and_then(|commit| commit.tree())
+            .context("failed to get merge base tree")?;
+        let tip = repo
+            .find_commit(tip.id())
+            .and_then(|commit| commit.tree())
+            .context("failed to get stack tree")?;
+        let stats = repo
+            .diff_tree_to_tree(Some(&base), Some(&tip), None)
+            .and_then(|diff| diff.stats())
+            .context("failed to diff stack")?;
+        println!("Stack {} against {}", diffstat(&stats), stack.upstream());
+    }
+
+    let dimmed = color::dimmed();
+    println!(
+        "{}",
+        dimmed.paint(format!(
+            "push {:.1?}, PRs {:.1?}, footers {:.1?}",
+            profile.push, profile.prs, profile.footers
+        ))
+    );
+
+    Ok(())
+}
+
+/// Print how long finding the stack and each phase of submitting it took, then how long each
+/// commit's PR and footer took, slowest first
+pub fn print_profile(stack: &Stack, discovery: Duration, profile: &Profile) {
+    println!("\nProfile");
+    let phases = [
+        ("stack discovery", discovery),
+        ("push", profile.push),
+        ("PRs", profile.prs),
+        ("footers", profile.footers),
+        ("metadata", profile.metadata),
+    ];
+    for (phase, time) in phases {
+        println!("  {phase:<16} {time:>10.1?}");
+    }
+
+    let mut commits: Vec<_> = profile.commits.iter().collect();
+    commits.sort_by_key(|commit| std::cmp::Reverse(commit.pr_time + commit.footer_time));
+    for commit in commits {
+        let title = stack
+            .iter()
+            .find(|c| c.id() == commit.id)
+            .map_or("", |c| c.title.as_str());
+        println!(
+            "  {:>16} {:>10.1?} PR {:>10.1?} footer  {}",
+            format!("#{}", commit.pr),
+            commit.pr_time,
+            commit.footer_time,
+            color::dimmed().paint(title),
+        );
+    }
+}
+
+fn diffstat(stats: &DiffStats) -> String {
+    format!(
+        "{} {} ({} files)",
+        color::success().paint(format!("+{}", stats.insertions())),
+        color::failure().paint(format!("-{}", stats.deletions())),
+        stats.files_changed()
+    )
+}
@@ -0,0 +1,369 @@
+use std::collections::HashSet;
+use std::fs;
+use std::process::Command;
+
+use anyhow::{Context, Result};
+use git2::{
+    FetchOptions, Oid, PushOptions, Remote, Repository, RepositoryState, Status, StatusOptions,
+};
+
+use crate::auth;
+use crate::commit;
+use crate::error::FelError;
+use crate::metadata::Metadata;
+use crate::stack::Stack;
+use crate::state::{Pending, State};
+
+/// Update the remote tracking branch for `upstream` from `remote`. If `upstream` is a tag the
+/// repo already has, the tag is fetched instead
+pub fn fetch(
+    repo: &Repository,
+    remote: &mut Remote,
+    upstream: &str,
+    proxy: Option<&str>,
+) -> Result<()> {
+    let remote_name = remote.name().context("remote name not utf-8")?.to_string();
+    let tag = format!("refs/tags/{upstream}");
+    let refspec = match repo.find_reference(&tag) {
+        Ok(_) => format!("+{tag}:{tag}"),
+        Err(_) => format!("+refs/heads/{upstream}:refs/remotes/{remote_name}/{upstream}"),
+    };
+    tracing::debug!(refspec, "fetching upstream");
+
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(auth::callbacks());
+    options.proxy_options(auth::proxy_options(proxy));
+    remote
+        .fetch(&[&refspec], Some(&mut options), None)
+        .context("failed to fetch")?;
+
+    Ok(())
+}
+
+/// Fetch the head of PR `number` into `refs/fel/pull/<number>`, which works for PRs from forks
+/// too. Returns the commit it points at
+pub fn fetch_pull(
+    repo: &Repository,
+    remote: &mut Remote,
+    number: u64,
+    proxy: Option<&str>,
+) -> Result<Oid> {
+    let local = format!("refs/fel/pull/{number}");
+    let refspec = format!("+refs/pull/{number}/head:{local}");
+    tracing::debug!(refspec, "fetching PR");
+
+    let mut options = FetchOptions::new();
+    options.remote_callbacks(auth::callbacks());
+    options.proxy_options(auth::proxy_options(proxy));
+    remote
+        .fetch(&[&refspec], Some(&mut options), None)
+        .with_context(|| format!("failed to fetch PR {number}"))?;
+
+    repo.refname_to_id(&local)
+        .with_context(|| format!("PR {number} not found on the remote"))
+}
+
+/// Push the commit `tag` points at to a `fel/base/<tag>` branch on `remote`, since PRs can only
+/// be made against branches. Returns the name of the branch
+pub fn push_base_branch(
+    repo: &Repository,
+    remote: &mut Remote,
+    tag: &str,
+    proxy: Option<&str>,
+) -> Result<String> {
+    let commit = repo
+        .find_reference(&format!("refs/tags/{tag}"))
+        .and_then(|tag| tag.peel_to_commit())
+        .with_context(|| format!("failed to find tag {tag}"))?;
+    let branch = format!("fel/base/{tag}");
+    let local = format!("refs/fel/base/{tag}");
+    repo.reference(&local, commit.id(), true, "fel: base branch for tag")
+        .context("failed to create base ref")?;
+    tracing::debug!(tag, branch, "pushing base branch");
+
+    let mut rejected = None;
+    {
+        let mut callbacks = auth::callbacks();
+        callbacks.push_update_reference(|_, status| {
+            rejected = status.map(str::to_string);
+            Ok(())
+        });
+        let mut options = PushOptions::new();
+        options.remote_callbacks(callbacks);
+        options.proxy_options(auth::proxy_options(proxy));
+        remote
+            .push(
+                &[format!("{local}:refs/heads/{branch}")],
+                Some(&mut options),
+            )
+            .context("failed to push base branch")?;
+    }
+
+    match rejected {
+        Some(reason) => Err(FelError::PushRejected(format!(
+            "{branch} was rejected: {reason}, does it point at another commit?"
+        ))
+        .into()),
+        None => Ok(branch),
+    }
+}
+
+/// Returns how many commits at the bottom of `stack` are already in `onto`. Commits are
+/// matched by patch-id, so ones that landed through a squash or rebase merge count too
+pub fn landed(repo: &Repository, stack: &Stack, onto: &str) -> Result<usize> {
+    let onto = repo
+        .revparse_single(onto)
+        .and_then(|object| object.peel_to_commit())
+        .with_context(|| format!("failed to find {onto}"))?;
+
+    let mut walk = repo.revwalk().context("failed to walk upstream")?;
+    walk.push(onto.id())?;
+    walk.hide(stack.merge_base())?;
+    let mut upstream = HashSet::new();
+    for id in walk {
+        let id = id.context("failed to walk upstream")?;
+        if repo.find_commit(id)?.parent_count() == 1 {
+            upstream.insert(commit::patch_id(repo, id)?);
+        }
+    }
+
+    let mut landed = 0;
+    for commit in stack.iter() {
+        if !upstream.contains(&commit::patch_id(repo, commit.id())?) {
+            break;
+        }
+        landed += 1;
+    }
+    Ok(landed)
+}
+
+/// Squash `fixup!` and `squash!` commits above `base` into the commits they name, like
+/// `git rebase --autosquash`. Squashed messages are kept as they are, without an editor
+pub fn autosquash(repo: &Repository, base: Oid, resume: bool, autostash: bool) -> Result<()> {
+    tracing::debug!(?base, "autosquashing");
+    let base = base.to_string();
+    let mut command = start_rebase(repo, &base, autostash)?;
+    command
+        .args(["--interactive", "--autosquash", &base])
+        .env("GIT_SEQUENCE_EDITOR", "true")
+        .env("GIT_EDITOR", "true");
+    rebase(repo, &base, command, resume.then(resume_args))
+}
+
+/// Rebase the commits of the current branch above `base` onto `onto`, dropping `base` and
+/// everything below it
+pub fn restack_above(
+    repo: &Repository,
+    onto: &str,
+    base: Oid,
+    resume: bool,
+    autostash: bool,
+) -> Result<()> {
+    tracing::debug!(onto, ?base, "restacking");
+    let mut command = start_rebase(repo, onto, autostash)?;
+    command.args(["--onto", onto, &base.to_string()]);
+    rebase(repo, onto, command, resume.then(resume_args))
+}
+
+/// Rebase the current branch onto `onto`. This shells out to git, since libgit2 doesn't
+/// copy notes along with rewritten commits. With `resume`, `fel continue` runs the current
+/// command again if the rebase stops on a conflict. With `autostash`, uncommitted changes are
+/// stashed first and restored once the rebase finishes or is aborted
+pub fn restack(repo: &Repository, onto: &str, resume: bool, autostash: bool) -> Result<()> {
+    tracing::debug!(onto, "restacking");
+    let mut command = start_rebase(repo, onto, autostash)?;
+    command.arg(onto);
+    rebase(repo, onto, command, resume.then(resume_args))
+}
+
+/// Finish a restack that stopped on a conflict, once the conflicts are resolved. Returns the
+/// arguments of the command to run again now that it's done, if any
+pub fn continue_restack(repo: &Repository) -> Result<Option<Vec<String>>> {
+    let state = State::load(repo)?;
+    let pending = state
+        .pending()
+        .cloned()
+        .context("there is no restack to continue")?;
+
+    // The rebase may already have been finished with git
+    if in_rebase(repo) {
+        let mut command = git_rebase(repo)?;
+        command.arg("--continue");
+        rebase(repo, &pending.onto, command, pending.resume.clone())?;
+    }
+
+    let mut state = State::load(repo)?;
+    state.set_pending(None);
+    state.save()?;
+    Ok(pending.resume)
+}
+
+/// Roll back a restack that stopped on a conflict, leaving the stack and its notes as they
+/// were before it started
+pub fn abort_restack(repo: &Repository) -> Result<()> {
+    let mut state = State::load(repo)?;
+    anyhow::ensure!(
+        state.pending().is_some() || in_rebase(repo),
+        "there is no restack to abort"
+    );
+
+    if in_rebase(repo) {
+        let status = git_rebase(repo)?
+            .arg("--abort")
+            .status()
+            .context("failed to run git rebase")?;
+        anyhow::ensure!(status.success(), "git rebase --abort failed");
+    }
+
+    state.set_pending(None);
+    state.save()
+}
+
+fn resume_args() -> Vec<String> {
+    std::env::args().skip(1).collect()
+}
+
+/// `git rebase` in the working tree, with rerere recording how conflicts are resolved and
+/// replaying them when the same conflict comes up again
+fn git_rebase(repo: &Repository) -> Result<Command> {
+    let workdir = repo.workdir().context("repo has no working directory")?;
+    let mut command = Command::new("git");
+    command
+        .args([
+            "-c",
+            "rerere.enabled=true",
+            "-c",
+            "rerere.autoUpdate=true",
+            "rebase",
+        ])
+        .current_dir(workdir);
+    Ok(command)
+}
+
+/// Returns the tracked files with uncommitted changes in the working tree or index
+pub fn dirty(repo: &Repository) -> Result<Vec<String>> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(false).include_ignored(false);
+    let statuses = repo
+        .statuses(Some(&mut options))
+        .context("failed to get status")?;
+    Ok(statuses
+        .iter()
+        .filter(|entry| entry.status() != Status::CURRENT)
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .collect())
+}
+
+/// Refuse to go on if there are uncommitted changes to tracked files, naming them. `action` is
+/// what would have been done
+pub fn ensure_clean(repo: &Repository, action: &str) -> Result<()> {
+    let dirty = dirty(repo)?;
+    anyhow::ensure!(
+        dirty.is_empty(),
+        "can't {action} with uncommitted changes to {}, commit or stash them first, or pass \
+        --autostash",
+        dirty.join(", ")
+    );
+    Ok(())
+}
+
+/// Start a `git rebase` onto `onto`. With `autostash` uncommitted changes are stashed and
+/// restored by git, otherwise there can't be any
+fn start_rebase(repo: &Repository, onto: &str, autostash: bool) -> Result<Command> {
+    let mut command = git_rebase(repo)?;
+    match autostash {
+        true => {
+            command.arg("--autostash");
+        }
+        false => ensure_clean(repo, &format!("rebase onto {onto}"))?,
+    }
+    Ok(command)
+}
+
+/// Run a `git rebase` command. Whenever it stops on conflicts rerere resolved, it is
+/// continued. If it stops on one rerere can't resolve, the rebase is left in progress for
+/// `fel continue` or `fel abort`, which runs `resume` once it finishes
+fn rebase(
+    repo: &Repository,
+    onto: &str,
+    mut command: Command,
+    resume: Option<Vec<String>>,
+) -> Result<()> {
+    let mut last_stop = None;
+    loop {
+        let status = command.status().context("failed to run git rebase")?;
+        if status.success() {
+            return Ok(());
+        }
+        // Anything but a conflict stops without leaving a rebase in progress
+        anyhow::ensure!(in_rebase(repo), "git rebase onto {onto} failed");
+
+        let stop = stopped_at(repo);
+        let mut index = repo.index().context("failed to open index")?;
+        index.read(true).context("failed to read index")?;
+        if index.has_conflicts() || stop == last_stop {
+            break;
+        }
+        tracing::info!(?stop, "rerere resolved every conflict, continuing");
+        last_stop = stop;
+        command = git_rebase(repo)?;
+        command.arg("--continue").env("GIT_EDITOR", "true");
+    }
+
+    let mut state = State::load(repo)?;
+    state.set_pending(Some(Pending {
+        onto: onto.to_string(),
+        resume,
+    }));
+    state.save()?;
+
+    Err(FelError::Conflict(describe_stop(repo, onto)?).into())
+}
+
+fn in_rebase(repo: &Repository) -> bool {
+    matches!(
+        repo.state(),
+        RepositoryState::Rebase
+            | RepositoryState::RebaseInteractive
+            | RepositoryState::RebaseMerge
+            | RepositoryState::ApplyMailboxOrRebase
+    )
+}
+
+/// Returns the commit a rebase in progress stopped at
+fn stopped_at(repo: &Repository) -> Option<Oid> {
+    ["rebase-merge/stopped-sha", "rebase-apply/original-commit"]
+        .iter()
+        .find_map(|file| fs::read_to_string(repo.path().join(file)).ok())
+        .and_then(|sha| Oid::from_str(sha.trim()).ok())
+}
+
+/// Describe where a rebase onto `onto` stopped, with the commit, its PR and the conflicting
+/// paths
+fn describe_stop(repo: &Repository, onto: &str) -> Result<String> {
+    let commit = match stopped_at(repo).and_then(|id| repo.find_commit(id).ok()) {
+        Some(commit) => {
+            let title = commit.summary().unwrap_or_default().to_string();
+            match Metadata::find(repo, commit.id())?.pr {
+                Some(pr) => format!("{title} (#{pr})"),
+                None => title,
+            }
+        }
+        None => "a commit".to_string(),
+    };
+
+    let index = repo.index().context("failed to open index")?;
+    let mut paths = Vec::new();
+    for conflict in index.conflicts().context("failed to read conflicts")? {
+        let conflict = conflict.context("failed to read conflict")?;
+        let entry = conflict.our.or(conflict.their).or(conflict.ancestor);
+        if let Some(entry) = entry {
+            paths.push(String::from_utf8_lossy(&entry.path).to_string());
+        }
+    }
+
+    Ok(match paths.is_empty() {
+        true => format!("{commit} doesn't apply onto {onto}"),
+        false => format!("{commit} conflicts with {onto} in {}", paths.join(", ")),
+    })
+}
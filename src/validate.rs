@@ -0,0 +1,70 @@
+use anyhow::{Context, Result};
+use regex::Regex;
+
+use crate::config::Validate;
+use crate::stack::Stack;
+
+/// Titles following <https://www.conventionalcommits.org>, like `fix(parser)!: message`
+const CONVENTIONAL_TITLE: &str = r"^\w+(\([^)]+\))?!?: \S";
+
+/// Check every commit message in the stack against the configured rules, reporting every
+/// violation at once rather than stopping at the first
+pub fn validate(stack: &Stack, config: &Validate) -> Result<()> {
+    let compile = |pattern: &Option<String>| {
+        pattern
+            .as_deref()
+            .map(Regex::new)
+            .transpose()
+            .with_context(|| format!("invalid pattern {pattern:?}"))
+    };
+
+    let title = compile(&config.title)?;
+    let body = compile(&config.body)?;
+    let conventional = match config.conventional {
+        true => Some(Regex::new(CONVENTIONAL_TITLE).context("invalid conventional pattern")?),
+        false => None,
+    };
+
+    let mut violations = Vec::new();
+    for commit in stack.iter() {
+        let mut violation = |message: String| {
+            violations.push(format!(
+                "{} {}: {message}",
+                &commit.id().to_string()[..8],
+                commit.title
+            ))
+        };
+
+        if let Some(title) = &title {
+            if !title.is_match(&commit.title) {
+                violation(format!("title does not match `{title}`"));
+            }
+        }
+
+        if let Some(body) = &body {
+            if !body.is_match(&commit.body) {
+                violation(format!("body does not match `{body}`"));
+            }
+        }
+
+        if let Some(conventional) = &conventional {
+            if !conventional.is_match(&commit.title) {
+                violation("title is not a conventional commit".to_string());
+            }
+        }
+
+        if let Some(max) = config.max_title_length {
+            if commit.title.chars().count() > max {
+                violation(format!("title is longer than {max} characters"));
+            }
+        }
+    }
+
+    anyhow::ensure!(
+        violations.is_empty(),
+        "invalid commit messages:\n{}",
+        violations.join("\n")
+    );
+
+    Ok(())
+}
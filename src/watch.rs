@@ -0,0 +1,143 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use git2::Repository;
+
+use crate::color;
+use crate::config::Config;
+use crate::events;
+use crate::forge::Forge;
+use crate::pulls::Update;
+use crate::stack::Stack;
+use crate::submit;
+
+/// Poll the stack's PRs every `interval` until interrupted. When PRs merge, retarget the ones
+/// left open onto what is now below them, take the merged PRs out of their footers, and tell
+/// the user the local stack needs restacking
+pub async fn watch(
+    repo: &Repository,
+    config: &Config,
+    forge: &dyn Forge,
+    interval: Duration,
+) -> Result<()> {
+    let mut reported = HashSet::new();
+    let delimiters = submit::delimiters(&config.submit);
+    loop {
+        // Reload every time, so the stack follows local restacks and new commits
+        let stack = Stack::new(repo, config).context("failed to get stack")?;
+        if let Err(error) = sync(&stack, forge, &delimiters, &mut reported).await {
+            tracing::warn!("failed to sync {}: {error:#}", stack.name());
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Bring the open PRs of `stack` in line with the ones that merged, reporting every merged PR
+/// not already in `reported`. Footers start at the first of `delimiters`
+async fn sync(
+    stack: &Stack,
+    forge: &dyn Forge,
+    delimiters: &[&str],
+    reported: &mut HashSet<u64>,
+) -> Result<()> {
+    let mut prs = Vec::new();
+    let mut bases = Vec::new();
+    for commit in stack.iter() {
+        if let Some(number) = commit.metadata.pr {
+            let pr = forge
+                .get_pr(number)
+                .await
+                .with_context(|| format!("failed to get PR {number}"))?;
+            prs.push(pr);
+            bases.push(commit.options.base.clone());
+        }
+    }
+
+    let merged: Vec<_> = prs
+        .iter()
+        .filter(|pr| pr.merged_at.is_some())
+        .map(|pr| pr.number)
+        .collect();
+    for &number in &merged {
+        if reported.insert(number) {
+            let message = format!(
+                "#{number} merged, fetch and rebase {} onto {}",
+                stack.name(),
+                stack.upstream()
+            );
+            events::progress("merged", None, Some(number), &message);
+            if !events::json() {
+                println!("{} {message}\x07", color::warning().paint("watch:"));
+            }
+        }
+    }
+
+    // Each open PR belongs on the nearest PR below it that hasn't merged, or the base of its
+    // segment of the stack
+    let mut base = stack.upstream().to_string();
+    for (pr, segment) in prs.iter().zip(bases) {
+        if let Some(segment) = segment {
+            base = segment;
+        }
+        if pr.merged_at.is_some() {
+            continue;
+        }
+
+        let mut update = Update::default();
+        if pr.base.ref_field != base {
+            update.base = Some(base.clone());
+        }
+        update.body = pr
+            .body
+            .as_deref()
+            .and_then(|body| without_merged(body, delimiters, &merged));
+
+        if update.base.is_some() || update.body.is_some() {
+            tracing::info!(pr = pr.number, base = ?update.base, "updating PR after merge");
+            forge
+                .update_pr(pr.number, update)
+                .await
+                .with_context(|| format!("failed to update PR {}", pr.number))?;
+        }
+        base = pr.head.ref_field.clone();
+    }
+
+    Ok(())
+}
+
+/// Returns `body` with the lines linking to `merged` PRs removed from its footer, or None if
+/// the footer doesn't link to any of them
+fn without_merged(body: &str, delimiters: &[&str], merged: &[u64]) -> Option<String> {
+    let author = submit::strip_footer(body, delimiters);
+    let (author, footer) = body.split_at(author.len());
+    let links: Vec<_> = merged.iter().map(|number| format!(">#{number} ")).collect();
+
+    let mut changed = false;
+    let mut kept = String::with_capacity(footer.len());
+    for line in footer.split_inclusive('\n') {
+        match links.iter().any(|link| line.contains(link.as_str())) {
+            true => changed = true,
+            false => kept.push_str(line),
+        }
+    }
+
+    changed.then(|| format!("{author}{kept}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn drops_merged_prs_from_footer() {
+        let body = "Fix #1 in the parser\n\n[#]:fel\n\n<pre>\n\
+            * <a href=\"u\">#3 top</a>\n\
+            * <a href=\"u\">#1 bottom</a>\n</pre>\n";
+        let updated = without_merged(body, &[submit::BODY_DELIM], &[1]).unwrap();
+        assert!(updated.starts_with("Fix #1 in the parser\n\n[#]:fel"));
+        assert!(updated.contains(">#3 top") && !updated.contains(">#1 bottom"));
+
+        assert!(without_merged(&updated, &[submit::BODY_DELIM], &[1]).is_none());
+    }
+}
@@ -0,0 +1,76 @@
+use std::process::Command;
+
+use anyhow::{Context, Result};
+
+use crate::gh::GHRepo;
+use crate::stack::Stack;
+
+/// Open PRs in the browser. `target` is either an index into the stack or a PR number
+/// prefixed with `#`, and defaults to the commit at the top of the stack
+pub fn web(stack: &Stack, target: Option<&str>, all: bool, gh_repo: &GHRepo) -> Result<()> {
+    let urls = match (all, target) {
+        (true, _) => stack
+            .iter()
+            .filter_map(|commit| commit.metadata.pr)
+            .map(|pr| gh_repo.pr_url(pr))
+            .collect(),
+        (false, Some(target)) if target.starts_with('#') => {
+            let pr = target[1..]
+                .parse()
+                .with_context(|| format!("invalid PR number {target}"))?;
+            vec![gh_repo.pr_url(pr)]
+        }
+        (false, target) => {
+            let commit = match target {
+                Some(index) => {
+                    let index = index
+                        .parse()
+                        .with_context(|| format!("invalid stack index {index}"))?;
+                    stack
+                        .get(index)
+                        .with_context(|| format!("no commit at index {index}"))?
+                }
+                None => stack.iter().last().context("stack is empty")?,
+            };
+
+            let pr = commit
+                .metadata
+                .pr
+                .with_context(|| format!("{} has not been submitted", commit.title))?;
+            vec![gh_repo.pr_url(pr)]
+        }
+    };
+
+    anyhow::ensure!(
+        !urls.is_empty(),
+        "no PRs have been submitted for this stack"
+    );
+    for url in urls {
+        open(&url)?;
+    }
+
+    Ok(())
+}
+
+/// Open `url` in the default browser
+pub fn open(url: &str) -> Result<()> {
+    tracing::debug!(url, "opening url");
+
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(windows) {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+
+    let status = command
+        .arg(url)
+        .status()
+        .context("failed to open browser")?;
+    anyhow::ensure!(status.success(), "failed to open {url}");
+
+    Ok(())
+}
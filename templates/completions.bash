@@ -0,0 +1,19 @@
+
+_fel_dynamic() {
+    local cur="${COMP_WORDS[COMP_CWORD]}"
+    if [[ ${COMP_CWORD} -eq 2 ]]; then
+        case "${COMP_WORDS[1]}" in
+            range-diff|ready)
+                COMPREPLY=($(compgen -W "$(fel __complete indices 2>/dev/null | cut -f1)" -- "$cur"))
+                return
+                ;;
+            web)
+                COMPREPLY=($(compgen -W "$(fel __complete indices 2>/dev/null | cut -f1) $(fel __complete prs 2>/dev/null | cut -f1)" -- "$cur"))
+                return
+                ;;
+        esac
+    fi
+    _fel "$@"
+}
+
+complete -F _fel_dynamic -o bashdefault -o default fel
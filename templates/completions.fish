@@ -0,0 +1,3 @@
+
+complete -c fel -n '__fish_seen_subcommand_from range-diff ready web' -f -a '(fel __complete indices 2>/dev/null)'
+complete -c fel -n '__fish_seen_subcommand_from web' -f -a '(fel __complete prs 2>/dev/null)'
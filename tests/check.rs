@@ -0,0 +1,51 @@
+mod common;
+
+use common::github::MockGitHub;
+use common::test_repo::TestRepo;
+use fel_core::check;
+use fel_core::forge::GitHub;
+use fel_core::submit;
+use hyper::Method;
+use serde_json::json;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn publish_falls_back_to_statuses() {
+    let test = TestRepo::with_stack(&["Add parser"]);
+    let github = MockGitHub::start();
+    let config = test.config();
+    let mut remote = test.repo.find_remote("origin").unwrap();
+    let octocrab = github.octocrab();
+    submit::submit(
+        &test.stack(),
+        &mut remote,
+        octocrab.clone(),
+        &github.gh_repo(),
+        &test.repo,
+        &config,
+        true,
+    )
+    .await
+    .unwrap();
+
+    // GitHub only lets apps create check runs
+    let message = "You must authenticate via a GitHub App.";
+    github.fail(Method::POST, "check-runs", 403, message, json!(null));
+    let stack = test.stack();
+    let forge = GitHub::new(octocrab.clone(), github.gh_repo(), config.api);
+    check::check(&stack, &forge, &octocrab, &github.gh_repo(), &config, true)
+        .await
+        .unwrap();
+
+    // The mock doesn't know PR heads, so the check fails on them
+    let status = github
+        .requests()
+        .into_iter()
+        .find(|request| request.path.contains("/statuses/"))
+        .expect("no commit status posted");
+    assert_eq!(status.body["context"], "fel stack");
+    assert_eq!(status.body["state"], "failure");
+    assert_eq!(
+        status.body["description"],
+        "head  isn't the commit in the stack, submit again"
+    );
+}
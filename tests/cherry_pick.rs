@@ -0,0 +1,75 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+
+use common::test_repo::TestRepo;
+use fel_core::cherry_pick;
+use fel_core::error::FelError;
+use fel_core::state::State;
+use git2::Signature;
+
+/// Run git in the working tree of `test`
+fn git(test: &TestRepo, args: &[&str]) {
+    let status = Command::new("git")
+        .args(args)
+        .current_dir(test.path())
+        .status()
+        .unwrap();
+    assert!(status.success(), "git {args:?} failed");
+}
+
+#[test]
+fn continue_finishes_conflicted_pick() {
+    let test = TestRepo::with_stack(&["Add parser"]);
+
+    // PR 7 adds the same file with other contents
+    let repo = &test.repo;
+    let upstream = repo.revparse_single("origin/main").unwrap();
+    let upstream = upstream.peel_to_commit().unwrap();
+    let mut tree = repo.treebuilder(Some(&upstream.tree().unwrap())).unwrap();
+    let blob = repo.blob(b"their parser\n").unwrap();
+    tree.insert("add_parser", blob, 0o100644).unwrap();
+    let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+    let sig = Signature::now("someone", "someone@fel.invalid").unwrap();
+    let theirs = repo
+        .commit(None, &sig, &sig, "Their parser", &tree, &[&upstream])
+        .unwrap();
+    git(
+        &test,
+        &[
+            "push",
+            "--quiet",
+            "origin",
+            &format!("{theirs}:refs/pull/7/head"),
+        ],
+    );
+
+    let mut remote = repo.find_remote("origin").unwrap();
+    let error = cherry_pick::cherry_pick(
+        repo,
+        &mut remote,
+        &test.stack(),
+        "origin/main",
+        7,
+        None,
+        false,
+    )
+    .unwrap_err();
+    assert!(matches!(
+        FelError::classify(&error),
+        Some(FelError::Conflict(_))
+    ));
+
+    fs::write(test.path().join("add_parser"), "both parsers\n").unwrap();
+    git(&test, &["add", "add_parser"]);
+    let pick = State::load(repo).unwrap().pending_pick().cloned().unwrap();
+    std::env::set_var("GIT_EDITOR", "true");
+    assert_eq!(cherry_pick::continue_pick(repo, &pick).unwrap(), 1);
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap().id();
+    let metadata = test.metadata(head);
+    assert_eq!(metadata.picked_from, Some(theirs.to_string()));
+    assert_eq!(metadata.picked_from_pr, Some(7));
+    assert!(State::load(repo).unwrap().pending_pick().is_none());
+}
@@ -0,0 +1,399 @@
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use fel_core::gh::GHRepo;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use octocrab::{Octocrab, OctocrabBuilder};
+use serde_json::{json, Value};
+
+pub const OWNER: &str = "fel";
+pub const REPO: &str = "test";
+
+/// A request the mock received
+#[derive(Clone, Debug)]
+pub struct Logged {
+    pub method: Method,
+    pub path: String,
+    pub body: Value,
+}
+
+impl Logged {
+    /// Returns true if this was a `method` request to the repo's `route`, like `pulls/1`
+    pub fn is(&self, method: &Method, route: &str) -> bool {
+        self.method == method && self.path == format!("/repos/{OWNER}/{REPO}/{route}")
+    }
+}
+
+/// A response to send instead of the usual one, the next time a route is requested
+struct Failure {
+    method: Method,
+    route: String,
+    status: StatusCode,
+    body: Value,
+}
+
+#[derive(Default)]
+struct State {
+    prs: Vec<Value>,
+    comments: Vec<(u64, String)>,
+    log: Vec<Logged>,
+    failures: Vec<Failure>,
+}
+
+/// Answers the parts of the GitHub REST API submitting uses, for the repo `OWNER/REPO`: getting,
+/// creating, updating and listing PRs, listing, posting, editing and deleting comments on them,
+/// labeling them, and getting the repo and the authenticated user. PRs are kept in memory, never
+/// have reviews, and every request is logged
+pub struct MockGitHub {
+    addr: SocketAddr,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockGitHub {
+    /// Start serving on a free local port, until the runtime shuts down
+    pub fn start() -> Self {
+        let state = Arc::new(Mutex::new(State::default()));
+        let make_service = make_service_fn({
+            let state = state.clone();
+            move |_| {
+                let state = state.clone();
+                async move {
+                    Ok::<_, Infallible>(service_fn(move |request| handle(state.clone(), request)))
+                }
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let addr = server.local_addr();
+        tokio::spawn(server);
+        Self { addr, state }
+    }
+
+    /// A client whose requests go to the mock
+    pub fn octocrab(&self) -> Arc<Octocrab> {
+        let octocrab = OctocrabBuilder::default()
+            .base_uri(format!("http://{}", self.addr))
+            .unwrap()
+            .personal_token("test".to_string())
+            .build()
+            .unwrap();
+        Arc::new(octocrab)
+    }
+
+    pub fn gh_repo(&self) -> GHRepo {
+        GHRepo {
+            host: "github.com".to_string(),
+            owner: OWNER.to_string(),
+            repo: REPO.to_string(),
+        }
+    }
+
+    /// Every PR, in the order they were created
+    pub fn prs(&self) -> Vec<Value> {
+        self.state.lock().unwrap().prs.clone()
+    }
+
+    /// The comments on PR `number` as they are now, oldest first
+    pub fn comments(&self, number: u64) -> Vec<String> {
+        let state = self.state.lock().unwrap();
+        state
+            .comments
+            .iter()
+            .filter(|(pr, _)| *pr == number)
+            .map(|(_, body)| body.clone())
+            .collect()
+    }
+
+    /// Every request received so far, oldest first
+    pub fn requests(&self) -> Vec<Logged> {
+        self.state.lock().unwrap().log.clone()
+    }
+
+    /// Answer the next `method` request to the repo's `route` with a GitHub error
+    pub fn fail(&self, method: Method, route: &str, status: u16, message: &str, errors: Value) {
+        self.state.lock().unwrap().failures.push(Failure {
+            method,
+            route: format!("/repos/{OWNER}/{REPO}/{route}"),
+            status: StatusCode::from_u16(status).unwrap(),
+            body: json!({
+                "message": message,
+                "errors": errors,
+                "documentation_url": "https://docs.github.com/rest",
+            }),
+        });
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let query = request.uri().query().unwrap_or_default().to_string();
+    let bytes = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let body = serde_json::from_slice(&bytes).unwrap_or(Value::Null);
+
+    let mut state = state.lock().unwrap();
+    state.log.push(Logged {
+        method: method.clone(),
+        path: path.clone(),
+        body: body.clone(),
+    });
+
+    let failure = state
+        .failures
+        .iter()
+        .position(|failure| failure.method == method && failure.route == path);
+    if let Some(failure) = failure {
+        let failure = state.failures.remove(failure);
+        return Ok(respond(failure.status, failure.body));
+    }
+
+    let segments: Vec<_> = path.trim_matches('/').split('/').collect();
+    let (status, response) = match (&method, &segments[..]) {
+        (&Method::GET, ["user"]) => (StatusCode::OK, user()),
+        (&Method::GET, ["repos", OWNER, REPO]) => (StatusCode::OK, repo()),
+        (&Method::GET, ["repos", OWNER, REPO, "pulls", number]) => match state.pr(number) {
+            Some(pr) => (StatusCode::OK, pr.clone()),
+            None => not_found(),
+        },
+        (&Method::PATCH, ["repos", OWNER, REPO, "pulls", number]) => state.update(number, &body),
+        (&Method::POST, ["repos", OWNER, REPO, "pulls"]) => state.create(&body),
+        (&Method::GET, ["repos", OWNER, REPO, "pulls"]) => state.list(&query),
+        (&Method::GET, ["repos", OWNER, REPO, "pulls", _, "reviews"]) => {
+            (StatusCode::OK, json!([]))
+        }
+        (&Method::POST, ["repos", OWNER, REPO, "issues", number, "comments"]) => {
+            state.comment(number, &body)
+        }
+        (&Method::GET, ["repos", OWNER, REPO, "issues", number, "comments"]) => {
+            state.list_comments(number)
+        }
+        (&Method::PATCH, ["repos", OWNER, REPO, "issues", "comments", id]) => {
+            state.edit_comment(id, &body)
+        }
+        (&Method::DELETE, ["repos", OWNER, REPO, "issues", "comments", id]) => {
+            state.delete_comment(id)
+        }
+        (&Method::POST, ["repos", OWNER, REPO, "issues", _, "labels"]) => {
+            (StatusCode::OK, json!([]))
+        }
+        (&Method::POST, ["repos", OWNER, REPO, "check-runs" | "statuses", ..]) => {
+            (StatusCode::CREATED, body)
+        }
+        _ => not_found(),
+    };
+    Ok(respond(status, response))
+}
+
+impl State {
+    fn pr(&mut self, number: &str) -> Option<&mut Value> {
+        let number: u64 = number.parse().ok()?;
+        self.prs.iter_mut().find(|pr| pr["number"] == number)
+    }
+
+    fn create(&mut self, body: &Value) -> (StatusCode, Value) {
+        let head = body["head"].as_str().unwrap_or_default();
+        let branch = head.split_once(':').map_or(head, |(_, branch)| branch);
+        let exists = self
+            .prs
+            .iter()
+            .any(|pr| pr["head"]["ref"] == branch && pr["state"] == "open");
+        if exists {
+            return validation_failed(&format!("A pull request already exists for {head}."));
+        }
+
+        let number = self.prs.len() as u64 + 1;
+        let pr = json!({
+            "url": format!("https://api.github.com/repos/{OWNER}/{REPO}/pulls/{number}"),
+            "html_url": format!("https://github.com/{OWNER}/{REPO}/pull/{number}"),
+            "id": number,
+            "number": number,
+            "state": "open",
+            "title": body["title"],
+            "body": body["body"],
+            "draft": body["draft"].as_bool().unwrap_or(false),
+            "labels": [],
+            "head": { "ref": branch, "sha": "" },
+            "base": { "ref": body["base"], "sha": "" },
+        });
+        self.prs.push(pr.clone());
+        (StatusCode::CREATED, pr)
+    }
+
+    fn update(&mut self, number: &str, body: &Value) -> (StatusCode, Value) {
+        let Some(pr) = self.pr(number) else {
+            return not_found();
+        };
+        for field in ["title", "body", "state"] {
+            if let Some(value) = body.get(field) {
+                pr[field] = value.clone();
+            }
+        }
+        if let Some(base) = body.get("base") {
+            pr["base"]["ref"] = base.clone();
+        }
+        (StatusCode::OK, pr.clone())
+    }
+
+    fn list(&self, query: &str) -> (StatusCode, Value) {
+        let head = query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix("head="))
+            .map(decode)
+            .unwrap_or_default();
+        let branch = head
+            .split_once(':')
+            .map_or(head.as_str(), |(_, branch)| branch);
+        let prs: Vec<_> = self
+            .prs
+            .iter()
+            .filter(|pr| pr["head"]["ref"] == branch && pr["state"] == "open")
+            .cloned()
+            .collect();
+        (StatusCode::OK, json!(prs))
+    }
+
+    fn comment(&mut self, number: &str, body: &Value) -> (StatusCode, Value) {
+        let Some(number) = self.pr(number).map(|pr| pr["number"].as_u64().unwrap()) else {
+            return not_found();
+        };
+        let text = body["body"].as_str().unwrap_or_default().to_string();
+        self.comments.push((number, text.clone()));
+        (
+            StatusCode::CREATED,
+            comment(self.comments.len(), number, &text),
+        )
+    }
+
+    fn list_comments(&self, number: &str) -> (StatusCode, Value) {
+        let number: u64 = number.parse().unwrap_or_default();
+        let comments: Vec<_> = self
+            .comments
+            .iter()
+            .enumerate()
+            .filter(|(_, (pr, _))| *pr == number)
+            .map(|(index, (pr, body))| comment(index + 1, *pr, body))
+            .collect();
+        (StatusCode::OK, json!(comments))
+    }
+
+    fn edit_comment(&mut self, id: &str, body: &Value) -> (StatusCode, Value) {
+        let index = id.parse::<usize>().ok().and_then(|id| id.checked_sub(1));
+        let Some((number, text)) = index.and_then(|index| self.comments.get_mut(index)) else {
+            return not_found();
+        };
+        *text = body["body"].as_str().unwrap_or_default().to_string();
+        (StatusCode::OK, comment(index.unwrap() + 1, *number, text))
+    }
+
+    fn delete_comment(&mut self, id: &str) -> (StatusCode, Value) {
+        let index = id.parse::<usize>().ok().and_then(|id| id.checked_sub(1));
+        match index.and_then(|index| self.comments.get_mut(index)) {
+            // Ids are positions, so deleted comments are moved to PR 0, which never exists
+            Some(comment) if comment.0 != 0 => {
+                *comment = (0, String::new());
+                (StatusCode::NO_CONTENT, Value::Null)
+            }
+            _ => not_found(),
+        }
+    }
+}
+
+fn respond(status: StatusCode, body: Value) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap()
+}
+
+fn not_found() -> (StatusCode, Value) {
+    let body =
+        json!({ "message": "Not Found", "documentation_url": "https://docs.github.com/rest" });
+    (StatusCode::NOT_FOUND, body)
+}
+
+fn validation_failed(message: &str) -> (StatusCode, Value) {
+    let body = json!({
+        "message": "Validation Failed",
+        "errors": [{ "resource": "PullRequest", "code": "custom", "message": message }],
+        "documentation_url": "https://docs.github.com/rest",
+    });
+    (StatusCode::UNPROCESSABLE_ENTITY, body)
+}
+
+/// Decode the percent escapes in a query parameter
+fn decode(value: &str) -> String {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut rest = value.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        match (byte, tail) {
+            (b'%', [high, low, tail @ ..]) => {
+                let hex = std::str::from_utf8(&[*high, *low]).unwrap().to_string();
+                bytes.push(u8::from_str_radix(&hex, 16).unwrap());
+                rest = tail;
+            }
+            (b'+', _) => {
+                bytes.push(b' ');
+                rest = tail;
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8(bytes).unwrap()
+}
+
+/// The comment numbered `id`, counting from 1 across every PR
+fn comment(id: usize, number: u64, body: &str) -> Value {
+    json!({
+        "id": id,
+        "node_id": format!("IC_{id}"),
+        "url": format!("https://api.github.com/repos/{OWNER}/{REPO}/issues/comments/{id}"),
+        "html_url": format!("https://github.com/{OWNER}/{REPO}/pull/{number}#issuecomment-{id}"),
+        "body": body,
+        "user": user(),
+        "created_at": "2024-01-01T00:00:00Z",
+    })
+}
+
+fn repo() -> Value {
+    json!({
+        "id": 1,
+        "name": REPO,
+        "full_name": format!("{OWNER}/{REPO}"),
+        "url": format!("https://api.github.com/repos/{OWNER}/{REPO}"),
+        "permissions": { "admin": false, "push": true, "pull": true },
+    })
+}
+
+fn user() -> Value {
+    let url = "https://api.github.com/users/fel";
+    json!({
+        "login": "fel",
+        "id": 1,
+        "node_id": "U_1",
+        "avatar_url": "https://avatars.githubusercontent.com/u/1",
+        "gravatar_id": "",
+        "url": url,
+        "html_url": "https://github.com/fel",
+        "followers_url": format!("{url}/followers"),
+        "following_url": format!("{url}/following"),
+        "gists_url": format!("{url}/gists"),
+        "starred_url": format!("{url}/starred"),
+        "subscriptions_url": format!("{url}/subscriptions"),
+        "organizations_url": format!("{url}/orgs"),
+        "repos_url": format!("{url}/repos"),
+        "events_url": format!("{url}/events"),
+        "received_events_url": format!("{url}/received_events"),
+        "type": "User",
+        "site_admin": false,
+    })
+}
@@ -0,0 +1,5 @@
+//! Shared by the integration tests. Each test binary only uses some of it
+#![allow(dead_code)]
+
+pub mod github;
+pub mod test_repo;
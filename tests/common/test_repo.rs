@@ -0,0 +1,259 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use std::process::Command;
+
+use fel_core::config::Config;
+use fel_core::metadata::{Metadata, NOTE_REF};
+use fel_core::stack::Stack;
+use git2::{build::CheckoutBuilder, Oid, Repository, Signature};
+
+static NEXT: AtomicUsize = AtomicUsize::new(0);
+
+/// A repo in a temporary directory, with a bare `origin` to push to. `main` has a single
+/// commit, which is also `origin/main`, and the `stack` branch is checked out on top of it.
+/// Git copies fel's notes when commits are rewritten, as the readme asks users to set up.
+/// Everything is deleted when it's dropped
+pub struct TestRepo {
+    pub repo: Repository,
+    dir: PathBuf,
+}
+
+impl TestRepo {
+    pub fn new() -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "fel-test-{}-{}",
+            std::process::id(),
+            NEXT.fetch_add(1, Ordering::Relaxed)
+        ));
+        let remote = dir.join("remote.git");
+        Repository::init_bare(&remote).unwrap();
+
+        let repo = Repository::init(dir.join("work")).unwrap();
+        let mut config = repo.config().unwrap();
+        config.set_str("user.name", "fel test").unwrap();
+        config.set_str("user.email", "test@fel.invalid").unwrap();
+        config.set_str("notes.rewriteRef", NOTE_REF).unwrap();
+        repo.remote("origin", remote.to_str().unwrap()).unwrap();
+
+        let test_repo = Self { repo, dir };
+        let base = test_repo.commit_on(None, "Initial commit", "README", "fel test\n");
+        test_repo.reference("refs/heads/main", base);
+        test_repo.reference("refs/remotes/origin/main", base);
+        test_repo.reference("refs/heads/stack", base);
+        test_repo.repo.set_head("refs/heads/stack").unwrap();
+        test_repo.checkout();
+        test_repo
+    }
+
+    /// A repo with a commit on `stack` for each of `titles`, from the bottom of the stack up
+    pub fn with_stack(titles: &[&str]) -> Self {
+        let test_repo = Self::new();
+        for title in titles {
+            test_repo.commit(title);
+        }
+        test_repo
+    }
+
+    /// A repo with a stack of `titles` that was already submitted, as PRs numbered from 1 at
+    /// the bottom of the stack
+    pub fn submitted(titles: &[&str]) -> Self {
+        let test_repo = Self::with_stack(titles);
+        for (index, id) in test_repo.commits().into_iter().enumerate() {
+            let number = index as u64 + 1;
+            let metadata = Metadata {
+                branch: Some(format!("fel/stack/{index}")),
+                pr: Some(number),
+                revision: Some(0),
+                commit: Some(id.to_string()),
+                pr_url: Some(format!("https://github.com/fel/test/pull/{number}")),
+                stack: Some("stack".to_string()),
+                ..Default::default()
+            };
+            test_repo.set_metadata(id, &metadata);
+        }
+        test_repo
+    }
+
+    /// Commit a new file on top of HEAD, with `title` and a short body as its message
+    pub fn commit(&self, title: &str) -> Oid {
+        self.commit_with(title, "")
+    }
+
+    /// Like [`commit`](Self::commit), with `trailers` at the end of the message
+    pub fn commit_with(&self, title: &str, trailers: &str) -> Oid {
+        let head = self.repo.head().unwrap();
+        let branch = head.name().unwrap().to_string();
+        let head = head.peel_to_commit().unwrap().id();
+        let file = title.to_lowercase().replace(' ', "_");
+        let id = self.commit_on(
+            Some(head),
+            format!("{title}\n\nThe body of {title}.\n\n{trailers}").trim_end(),
+            &file,
+            &format!("{title}\n"),
+        );
+        self.reference(&branch, id);
+        self.checkout();
+        id
+    }
+
+    /// The commits of the checked out stack, from the bottom up
+    pub fn commits(&self) -> Vec<Oid> {
+        let mut walk = self.repo.revwalk().unwrap();
+        walk.push_head().unwrap();
+        walk.hide_ref("refs/remotes/origin/main").unwrap();
+        walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)
+            .unwrap();
+        walk.map(Result::unwrap).collect()
+    }
+
+    /// Read the checked out stack with [`config`](Self::config)
+    pub fn stack(&self) -> Stack {
+        Stack::new(&self.repo, &self.config()).unwrap()
+    }
+
+    /// Attach `metadata` to commit `id`, replacing any it had
+    pub fn set_metadata(&self, id: Oid, metadata: &Metadata) {
+        metadata.write(&self.repo, id).unwrap();
+    }
+
+    pub fn metadata(&self, id: Oid) -> Metadata {
+        Metadata::find(&self.repo, id).unwrap()
+    }
+
+    /// Add a commit to `origin/main`, as if someone else's PR merged. The stack is left where
+    /// it is, so it's stale until it's rebased
+    pub fn advance_upstream(&self, title: &str) -> Oid {
+        let upstream = self.upstream();
+        let file = format!("upstream_{}", title.to_lowercase().replace(' ', "_"));
+        let id = self.commit_on(Some(upstream), title, &file, &format!("{title}\n"));
+        self.reference("refs/remotes/origin/main", id);
+        id
+    }
+
+    /// Apply the change of commit `id` to `origin/main` as a new commit, as if its PR was
+    /// squash merged
+    pub fn land(&self, id: Oid) -> Oid {
+        let commit = self.repo.find_commit(id).unwrap();
+        let upstream = self.repo.find_commit(self.upstream()).unwrap();
+        let mut index = self
+            .repo
+            .cherrypick_commit(&commit, &upstream, 0, None)
+            .unwrap();
+        assert!(!index.has_conflicts(), "{id} doesn't apply to upstream");
+        let tree = index.write_tree_to(&self.repo).unwrap();
+        let tree = self.repo.find_tree(tree).unwrap();
+
+        let sig = Signature::now("fel test", "test@fel.invalid").unwrap();
+        let message = commit.message().unwrap();
+        let landed = self
+            .repo
+            .commit(None, &sig, &sig, message, &tree, &[&upstream])
+            .unwrap();
+        self.reference("refs/remotes/origin/main", landed);
+        landed
+    }
+
+    /// Reword the top commit of the stack to `title` with git, like a user would. Its note
+    /// is copied to the new commit
+    pub fn amend(&self, title: &str) {
+        self.git(&["commit", "--quiet", "--amend", "--message", title]);
+    }
+
+    /// Like [`amend`](Self::amend), with a Latin-1 `message` that git stores as is
+    pub fn amend_latin1(&self, message: &[u8]) {
+        let file = self.repo.path().join("FEL_TEST_MESSAGE");
+        std::fs::write(&file, message).unwrap();
+        let encoding = "i18n.commitEncoding=ISO-8859-1";
+        let file = file.to_str().unwrap();
+        self.git(&[
+            "-c", encoding, "commit", "--quiet", "--amend", "--file", file,
+        ]);
+    }
+
+    /// Drop the top commit of the stack
+    pub fn drop_top(&self) {
+        self.git(&["reset", "--quiet", "--hard", "HEAD~"]);
+    }
+
+    /// Rebase the checked out stack onto `onto` with git, like a user would. Notes follow
+    /// the rebased commits
+    pub fn rebase(&self, onto: &str) {
+        self.git(&["rebase", "--quiet", onto]);
+    }
+
+    fn git(&self, args: &[&str]) {
+        let status = Command::new("git")
+            .args(args)
+            .current_dir(self.path())
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    /// The bare repo `origin` points at
+    pub fn remote(&self) -> Repository {
+        Repository::open_bare(self.dir.join("remote.git")).unwrap()
+    }
+
+    pub fn path(&self) -> &Path {
+        self.repo.workdir().unwrap()
+    }
+
+    /// Config that submits to `origin/main`, without anything that would reach past the
+    /// mock forge
+    pub fn config(&self) -> Config {
+        toml::from_str(
+            r#"
+            token = "test"
+
+            [land]
+            strip_footer = false
+            "#,
+        )
+        .unwrap()
+    }
+
+    fn commit_on(&self, parent: Option<Oid>, message: &str, file: &str, contents: &str) -> Oid {
+        let parent = parent.map(|parent| self.repo.find_commit(parent).unwrap());
+        let mut tree = self
+            .repo
+            .treebuilder(
+                parent
+                    .as_ref()
+                    .map(|parent| parent.tree().unwrap())
+                    .as_ref(),
+            )
+            .unwrap();
+        let blob = self.repo.blob(contents.as_bytes()).unwrap();
+        tree.insert(file, blob, 0o100644).unwrap();
+        let tree = self.repo.find_tree(tree.write().unwrap()).unwrap();
+
+        let sig = Signature::now("fel test", "test@fel.invalid").unwrap();
+        let parents: Vec<_> = parent.iter().collect();
+        self.repo
+            .commit(None, &sig, &sig, message, &tree, &parents)
+            .unwrap()
+    }
+
+    fn upstream(&self) -> Oid {
+        self.repo.refname_to_id("refs/remotes/origin/main").unwrap()
+    }
+
+    fn reference(&self, name: &str, id: Oid) {
+        self.repo.reference(name, id, true, "fel test").unwrap();
+    }
+
+    fn checkout(&self) {
+        self.repo
+            .checkout_head(Some(CheckoutBuilder::new().force()))
+            .unwrap();
+    }
+}
+
+impl Drop for TestRepo {
+    fn drop(&mut self) {
+        fs::remove_dir_all(&self.dir).ok();
+    }
+}
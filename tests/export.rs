@@ -0,0 +1,31 @@
+mod common;
+
+use std::env;
+use std::path::Path;
+
+use common::test_repo::TestRepo;
+use fel_core::export;
+
+#[test]
+fn relative_output_dir() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    env::set_current_dir(test.path()).unwrap();
+
+    export::export(&test.stack(), &test.repo, Some(Path::new("patches"))).unwrap();
+    let mut patches: Vec<_> = test
+        .path()
+        .join("patches")
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    patches.sort();
+    assert_eq!(
+        patches,
+        [
+            "0000-cover-letter.patch",
+            "0001-Add-parser.patch",
+            "0002-Add-lexer.patch"
+        ]
+    );
+}
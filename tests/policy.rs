@@ -0,0 +1,48 @@
+mod common;
+
+use std::fs;
+use std::process::{Command, Output};
+
+use common::test_repo::TestRepo;
+
+/// Run the fel binary in `test`, with a home dir holding just enough config to start
+fn fel(test: &TestRepo, args: &[&str]) -> Output {
+    let home = test.path().parent().unwrap().join("home");
+    fs::create_dir_all(home.join(".config").join("fel")).unwrap();
+    fs::write(
+        home.join(".gitconfig"),
+        "[notes]\n\trewriteRef = refs/notes/fel\n",
+    )
+    .unwrap();
+    fs::write(home.join(".config/fel/config.toml"), "token = \"test\"\n").unwrap();
+
+    Command::new(env!("CARGO_BIN_EXE_fel"))
+        .arg("-C")
+        .arg(test.path())
+        .args(args)
+        .env("HOME", &home)
+        .env("XDG_CONFIG_HOME", home.join(".config"))
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn policy_applies_to_upstream_flag() {
+    let test = TestRepo::with_stack(&["Add parser"]);
+    fs::write(
+        test.path().join(".fel-policy.toml"),
+        "allowed_upstreams = [\"main\"]\n",
+    )
+    .unwrap();
+
+    assert!(fel(&test, &["env"]).status.success());
+
+    let output = fel(&test, &["--upstream", "release-1.0", "env"]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("doesn't allow submitting against release-1.0"),
+        "{stderr}"
+    );
+}
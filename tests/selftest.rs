@@ -0,0 +1,69 @@
+mod common;
+
+use common::github::MockGitHub;
+use common::test_repo::TestRepo;
+use fel_core::metadata::NOTE_REF;
+use fel_core::policy::Policy;
+use fel_core::selftest;
+use hyper::Method;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn selftest_cleans_up() {
+    let test = TestRepo::new();
+    let github = MockGitHub::start();
+    let mut remote = test.repo.find_remote("origin").unwrap();
+    let passed = selftest::selftest(
+        &test.repo,
+        &mut remote,
+        "main",
+        github.octocrab(),
+        &github.gh_repo(),
+        &test.config(),
+    )
+    .await
+    .unwrap();
+    assert!(passed);
+
+    let prs = github.prs();
+    assert_eq!(prs.len(), 2);
+    for pr in &prs {
+        assert!(pr["title"].as_str().unwrap().starts_with("[fel selftest]"));
+        assert_eq!(pr["draft"], true);
+        assert_eq!(pr["state"], "closed");
+    }
+    assert_eq!(test.remote().branches(None).unwrap().count(), 0);
+    assert_eq!(test.repo.notes(Some(NOTE_REF)).unwrap().count(), 0);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn selftest_keeps_policy() {
+    let test = TestRepo::new();
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    let policy: Policy = toml::from_str("allow_force = false\nlabels = [\"fel\"]").unwrap();
+    policy.apply(&mut config).unwrap();
+    let mut remote = test.repo.find_remote("origin").unwrap();
+    let passed = selftest::selftest(
+        &test.repo,
+        &mut remote,
+        "main",
+        github.octocrab(),
+        &github.gh_repo(),
+        &config,
+    )
+    .await
+    .unwrap();
+    assert!(passed);
+
+    // The new revision needs a force push, so only the first one was pushed
+    let prs = github.prs();
+    assert!(prs
+        .iter()
+        .all(|pr| !pr["title"].as_str().unwrap().contains("(amended)")));
+    let labels = github
+        .requests()
+        .iter()
+        .filter(|request| request.method == Method::POST && request.path.ends_with("/labels"))
+        .count();
+    assert_eq!(labels, 2);
+}
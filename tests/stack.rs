@@ -0,0 +1,119 @@
+mod common;
+
+use common::test_repo::TestRepo;
+use fel_core::metadata::{NoteBatch, NOTE_REF};
+use fel_core::stack::Stack;
+use fel_core::state::State;
+use fel_core::{rename, upstream};
+use git2::BranchType;
+
+#[test]
+fn metadata_follows_rebase() {
+    let test = TestRepo::submitted(&["Add parser", "Add lexer"]);
+    test.advance_upstream("Fix readme");
+    assert!(test.stack().is_stale());
+
+    test.rebase("origin/main");
+    let stack = test.stack();
+    assert!(!stack.is_stale());
+    let prs: Vec<_> = stack.iter().map(|commit| commit.metadata.pr).collect();
+    assert_eq!(prs, [Some(1), Some(2)]);
+    assert!(stack.iter().all(|commit| commit.changed()));
+}
+
+#[test]
+fn restack_drops_landed_commits() {
+    let test = TestRepo::submitted(&["Add parser", "Add lexer", "Add repl"]);
+    let commits = test.commits();
+    test.advance_upstream("Fix readme");
+    test.land(commits[0]);
+
+    let stack = test.stack();
+    assert_eq!(
+        upstream::landed(&test.repo, &stack, "origin/main").unwrap(),
+        1
+    );
+
+    upstream::restack_above(&test.repo, "origin/main", commits[0], false, false).unwrap();
+    let stack = test.stack();
+    let titles: Vec<_> = stack.iter().map(|commit| commit.title.as_str()).collect();
+    assert_eq!(titles, ["Add lexer", "Add repl"]);
+    let prs: Vec<_> = stack.iter().map(|commit| commit.metadata.pr).collect();
+    assert_eq!(prs, [Some(2), Some(3)]);
+}
+
+#[test]
+fn reword_keeps_messages_that_are_not_utf8() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let message = b"Add lexer\n\nHandles caf\xe9.\n";
+    test.amend_latin1(message);
+    let mut config = test.config();
+    config.lossy_utf8 = true;
+
+    let mut stack = Stack::new(&test.repo, &config).unwrap();
+    stack.reword(&test.repo, 0, "Add parser, faster").unwrap();
+    let titles: Vec<_> = stack.iter().map(|commit| commit.title.as_str()).collect();
+    assert_eq!(titles, ["Add parser, faster", "Add lexer"]);
+    let top = test.repo.find_commit(test.commits()[1]).unwrap();
+    assert_eq!(top.message_raw_bytes(), message);
+    assert_eq!(top.message_encoding(), Some("ISO-8859-1"));
+}
+
+#[test]
+fn rename_moves_stack() {
+    let test = TestRepo::submitted(&["Add parser", "Add lexer"]);
+    let mut state = State::load(&test.repo).unwrap();
+    state.stack_mut("stack").prs = vec![1, 2];
+    state.save().unwrap();
+    let notes = || test.repo.refname_to_id(NOTE_REF).unwrap();
+    let before = notes();
+
+    rename::rename(&test.repo, &test.stack(), "parser").unwrap();
+    // Both notes are updated in one notes commit
+    let after = test.repo.find_commit(notes()).unwrap();
+    assert_eq!(after.parent_id(0).unwrap(), before);
+    let stack = test.stack();
+    assert_eq!(stack.name(), "parser");
+    for commit in stack.iter() {
+        assert_eq!(commit.metadata.stack.as_deref(), Some("parser"));
+        assert!(commit.metadata.branch.as_ref().unwrap().contains("/stack/"));
+    }
+    let state = State::load(&test.repo).unwrap();
+    assert!(state.stack("stack").is_none());
+    assert_eq!(state.stack("parser").unwrap().prs, [1, 2]);
+
+    // A branch renamed with git is adopted by renaming the stack to the branch's name
+    test.repo
+        .find_branch("parser", BranchType::Local)
+        .unwrap()
+        .rename("lexer", false)
+        .unwrap();
+    rename::rename(&test.repo, &test.stack(), "lexer").unwrap();
+    assert!(test
+        .stack()
+        .iter()
+        .all(|commit| commit.metadata.stack.as_deref() == Some("lexer")));
+}
+
+#[test]
+fn note_batch_removes_existing_notes() {
+    let test = TestRepo::submitted(&["Add parser", "Add lexer"]);
+    let unsubmitted = test.commit("Add repl");
+    let notes = || test.repo.refname_to_id(NOTE_REF).unwrap();
+    let before = notes();
+
+    let mut batch = NoteBatch::default();
+    for id in test.commits() {
+        batch.remove(id);
+    }
+    batch.write(&test.repo).unwrap();
+
+    // One notes commit, which skipped the commit that never had a note
+    let after = test.repo.find_commit(notes()).unwrap();
+    assert_eq!(after.parent_id(0).unwrap(), before);
+    assert!(test.repo.find_note(Some(NOTE_REF), unsubmitted).is_err());
+    assert!(test
+        .stack()
+        .iter()
+        .all(|commit| commit.metadata.pr.is_none()));
+}
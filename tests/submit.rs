@@ -0,0 +1,639 @@
+mod common;
+
+use std::sync::Arc;
+
+use common::github::MockGitHub;
+use common::test_repo::TestRepo;
+use fel_core::config::{CloseDropped, Config, Footer, FooterPlacement};
+use fel_core::error::FelError;
+use fel_core::forge::{Forge, GitHub, MockForge};
+use fel_core::metadata::Metadata;
+use fel_core::policy::Policy;
+use fel_core::render::{RenderStore, StackRenderer};
+use fel_core::stack::Stack;
+use fel_core::submit::PlannedPr;
+use fel_core::{backport, detach, submit};
+use hyper::Method;
+use serde_json::{json, Value};
+
+/// Submit the stack checked out in `test`, without asking before replacing anything
+async fn submit(test: &TestRepo, github: &MockGitHub) -> fel_core::Result<submit::Profile> {
+    submit_config(test, github, test.config()).await
+}
+
+async fn submit_config(
+    test: &TestRepo,
+    github: &MockGitHub,
+    config: Config,
+) -> fel_core::Result<submit::Profile> {
+    let stack = Stack::new(&test.repo, &config)?;
+    let mut remote = test.repo.find_remote("origin")?;
+    submit::submit(
+        &stack,
+        &mut remote,
+        github.octocrab(),
+        &github.gh_repo(),
+        &test.repo,
+        &config,
+        true,
+    )
+    .await
+}
+
+/// Returns the PR titled `title`
+fn pr<'a>(prs: &'a [Value], title: &str) -> &'a Value {
+    prs.iter()
+        .find(|pr| pr["title"] == title)
+        .unwrap_or_else(|| panic!("no PR titled {title}"))
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn creates_stacked_prs() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer", "Add repl"]);
+    let github = MockGitHub::start();
+    let profile = submit(&test, &github).await.unwrap();
+
+    let prs = github.prs();
+    assert_eq!(prs.len(), 3);
+    let (parser, lexer, repl) = (
+        pr(&prs, "Add parser"),
+        pr(&prs, "Add lexer"),
+        pr(&prs, "Add repl"),
+    );
+    assert_eq!(parser["base"]["ref"], "main");
+    assert_eq!(lexer["base"]["ref"], parser["head"]["ref"]);
+    assert_eq!(repl["base"]["ref"], lexer["head"]["ref"]);
+
+    // Commit timings come back in stack order, whatever order the PRs finished in
+    let numbers: Vec<_> = profile.commits.iter().map(|commit| commit.pr).collect();
+    let expected: Vec<_> = [parser, lexer, repl]
+        .iter()
+        .map(|pr| pr["number"].as_u64().unwrap())
+        .collect();
+    assert_eq!(numbers, expected);
+
+    // Footers need every PR number, so they're only written once every PR exists
+    let requests = github.requests();
+    let last_create = requests
+        .iter()
+        .rposition(|request| request.is(&Method::POST, "pulls"))
+        .unwrap();
+    let first_update = requests
+        .iter()
+        .position(|request| request.method == Method::PATCH)
+        .unwrap();
+    assert!(last_create < first_update);
+
+    // Every footer lists the whole stack, top first
+    let links: Vec<_> = [repl, lexer, parser]
+        .iter()
+        .map(|pr| format!(">#{} {}</a>", pr["number"], pr["title"].as_str().unwrap()))
+        .collect();
+    for pr in [parser, lexer, repl] {
+        let body = pr["body"].as_str().unwrap();
+        let title = pr["title"].as_str().unwrap();
+        assert!(body.starts_with(&format!("The body of {title}.\n\n[#]:fel")));
+        let positions: Vec<_> = links
+            .iter()
+            .map(|link| body.find(link).expect(body))
+            .collect();
+        assert!(
+            positions.windows(2).all(|pair| pair[0] < pair[1]),
+            "footer out of order: {body}"
+        );
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resubmit_updates_existing_prs() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    submit(&test, &github).await.unwrap();
+
+    test.commit("Add repl");
+    let before = github.requests().len();
+    submit(&test, &github).await.unwrap();
+
+    let creates: Vec<_> = github.requests()[before..]
+        .iter()
+        .filter(|request| request.is(&Method::POST, "pulls"))
+        .map(|request| request.body["title"].clone())
+        .collect();
+    assert_eq!(creates, [json!("Add repl")]);
+
+    let prs = github.prs();
+    assert_eq!(prs.len(), 3);
+    let repl = pr(&prs, "Add repl");
+    assert_eq!(repl["base"]["ref"], pr(&prs, "Add lexer")["head"]["ref"]);
+    let link = format!(">#{} Add repl</a>", repl["number"]);
+    for pr in &prs {
+        assert!(pr["body"].as_str().unwrap().contains(&link));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn rejected_pr_fails_submit() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let errors = json!([{ "resource": "PullRequest", "code": "custom", "message": "No commits" }]);
+    github.fail(Method::POST, "pulls", 422, "Validation Failed", errors);
+
+    let error = anyhow::Error::from(submit(&test, &github).await.unwrap_err());
+    assert!(matches!(
+        FelError::classify(&error),
+        Some(FelError::Validation(_))
+    ));
+
+    // The error names the commit whose PR was rejected
+    let requests = github.requests();
+    let rejected = requests
+        .iter()
+        .find(|request| request.is(&Method::POST, "pulls"))
+        .unwrap();
+    assert!(format!("{error:#}").contains(rejected.body["title"].as_str().unwrap()));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn bases_split_stack_into_segments() {
+    let test = TestRepo::with_stack(&["Fix parser", "Test fix", "Add repl"]);
+    let github = MockGitHub::start();
+    let config = test.config();
+    let mut stack = Stack::new(&test.repo, &config).unwrap();
+    stack
+        .set_base(&test.repo, 0, Some("release-1.2".to_string()))
+        .unwrap();
+    stack
+        .set_base(&test.repo, 2, Some("main".to_string()))
+        .unwrap();
+
+    // The bases are kept for the next time the stack is read
+    let stack = Stack::new(&test.repo, &config).unwrap();
+    assert_eq!(stack.segment_base(1), "release-1.2");
+    let mut remote = test.repo.find_remote("origin").unwrap();
+    submit::submit(
+        &stack,
+        &mut remote,
+        github.octocrab(),
+        &github.gh_repo(),
+        &test.repo,
+        &config,
+        true,
+    )
+    .await
+    .unwrap();
+
+    let prs = github.prs();
+    let (fix, test_fix, repl) = (
+        pr(&prs, "Fix parser"),
+        pr(&prs, "Test fix"),
+        pr(&prs, "Add repl"),
+    );
+    assert_eq!(fix["base"]["ref"], "release-1.2");
+    assert_eq!(test_fix["base"]["ref"], fix["head"]["ref"]);
+    assert_eq!(repl["base"]["ref"], "main");
+
+    // One footer, with each segment's base below it
+    let body = repl["body"].as_str().unwrap();
+    let order: Vec<_> = [
+        " Add repl</a>",
+        ">main</a>",
+        " Test fix</a>",
+        ">release-1.2</a>",
+    ]
+    .iter()
+    .map(|link| body.find(link).unwrap())
+    .collect();
+    assert!(order.windows(2).all(|pair| pair[0] < pair[1]), "{body}");
+    assert_eq!(body.matches(">release-1.2</a>").count(), 1);
+}
+
+/// Renders the footer as one line of plain text
+struct PlainRenderer;
+
+impl StackRenderer for PlainRenderer {
+    fn footer(&self, store: &RenderStore) -> anyhow::Result<String> {
+        Ok(format!("{} of {} PRs", store.stack_name, store.prs.len()))
+    }
+
+    fn discussion_footer(&self, store: &RenderStore) -> anyhow::Result<String> {
+        self.footer(store)
+    }
+
+    fn overview(&self, store: &RenderStore) -> anyhow::Result<String> {
+        self.footer(store)
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn custom_renderer() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let config = test.config();
+    let stack = Stack::new(&test.repo, &config).unwrap();
+    let mut remote = test.repo.find_remote("origin").unwrap();
+    let forge = GitHub::new(github.octocrab(), github.gh_repo(), config.api);
+    submit::submit_with(
+        &stack,
+        &mut remote,
+        Arc::new(forge),
+        Arc::new(PlainRenderer),
+        github.octocrab(),
+        &github.gh_repo(),
+        &test.repo,
+        &config,
+        true,
+    )
+    .await
+    .unwrap();
+
+    for pr in github.prs() {
+        assert!(pr["body"].as_str().unwrap().contains("stack of 2 PRs"));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn footer_modes() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    config.submit.footer = Footer::Compact;
+    submit_config(&test, &github, config.clone()).await.unwrap();
+
+    let body = |title| pr(&github.prs(), title)["body"].clone();
+    assert_eq!(
+        body("Add lexer"),
+        "The body of Add lexer.\n\n[#]:fel\n\nPart of stack stack (2/2)\n"
+    );
+
+    // Without a footer the fel section goes away entirely
+    config.submit.footer = Footer::None;
+    submit_config(&test, &github, config).await.unwrap();
+    assert_eq!(body("Add parser"), "The body of Add parser.");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn plan_matches_submit() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let config = test.config();
+    let planned = submit::plan(&test.stack(), &config);
+    assert!(planned
+        .iter()
+        .all(|planned| planned.pr == PlannedPr::Create));
+    submit(&test, &github).await.unwrap();
+
+    let prs = github.prs();
+    for planned in &planned {
+        let pr = pr(&prs, &planned.title);
+        assert_eq!(pr["head"]["ref"], planned.branch);
+        assert_eq!(pr["base"]["ref"], planned.base);
+    }
+    let planned = submit::plan(&test.stack(), &config);
+    assert!(planned
+        .iter()
+        .all(|planned| matches!(planned.pr, PlannedPr::UpToDate(_))));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn compact_footer_skips_waiting() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let errors = json!([{ "resource": "PullRequest", "code": "custom", "message": "No commits" }]);
+    github.fail(Method::POST, "pulls", 422, "Validation Failed", errors);
+    let mut config = test.config();
+    config.submit.footer = Footer::Compact;
+    submit_config(&test, &github, config).await.unwrap_err();
+
+    // The compact footer doesn't reference the other PR, so it's added without waiting for
+    // the PR that failed
+    let prs = github.prs();
+    assert_eq!(prs.len(), 1);
+    assert!(prs[0]["body"]
+        .as_str()
+        .unwrap()
+        .contains("Part of stack stack"));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn new_delimiter_replaces_old_footer() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    submit(&test, &github).await.unwrap();
+
+    let mut config = test.config();
+    config.submit.footer_delimiter = Some("<!-- fel -->".to_string());
+    submit_config(&test, &github, config).await.unwrap();
+
+    for pr in github.prs() {
+        let body = pr["body"].as_str().unwrap();
+        let title = pr["title"].as_str().unwrap();
+        assert!(body.starts_with(&format!("The body of {title}.\n\n<!-- fel -->\n\n")));
+        assert!(!body.contains("[#]:fel"));
+        assert_eq!(body.matches(">#1 Add parser</a>").count(), 1);
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn footer_in_comment() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    config.submit.footer_placement = FooterPlacement::Comment;
+    submit_config(&test, &github, config.clone()).await.unwrap();
+
+    test.commit("Add repl");
+    submit_config(&test, &github, config.clone()).await.unwrap();
+
+    // Bodies are left as written, and each PR has one comment that lists the whole stack
+    for pr in github.prs() {
+        let title = pr["title"].as_str().unwrap();
+        assert_eq!(pr["body"], format!("The body of {title}."));
+        let comments = github.comments(pr["number"].as_u64().unwrap());
+        assert_eq!(comments.len(), 1);
+        assert!(comments[0].starts_with("<!-- fel:stack -->"));
+        assert!(comments[0].contains(">#3 Add repl</a>"));
+    }
+
+    // Moving the tree back to the body deletes the comments
+    config.submit.footer_placement = FooterPlacement::Body;
+    submit_config(&test, &github, config).await.unwrap();
+    for pr in github.prs() {
+        assert!(pr["body"].as_str().unwrap().contains(">#3 Add repl</a>"));
+        assert!(github.comments(pr["number"].as_u64().unwrap()).is_empty());
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn comments_and_login_through_forge() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    config.submit.footer_placement = FooterPlacement::Comment;
+    config.submit.branch_prefix = Some("@me".to_string());
+    let stack = Stack::new(&test.repo, &config).unwrap();
+    let mut remote = test.repo.find_remote("origin").unwrap();
+    let forge = Arc::new(MockForge::default());
+    submit::submit_with(
+        &stack,
+        &mut remote,
+        forge.clone(),
+        Arc::new(PlainRenderer),
+        github.octocrab(),
+        &github.gh_repo(),
+        &test.repo,
+        &config,
+        true,
+    )
+    .await
+    .unwrap();
+
+    // Nothing that has to do with PRs reaches GitHub
+    assert!(github.requests().is_empty(), "{:?}", github.requests());
+    for number in [1, 2] {
+        let pr = forge.get_pr(number).await.unwrap();
+        assert!(pr.head.ref_field.starts_with("mock/"));
+        let comments = forge.list_comments(number).await.unwrap();
+        assert_eq!(comments.len(), 1);
+        assert!(comments[0].body.ends_with("stack of 2 PRs"));
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn detach_hands_pr_off() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    submit(&test, &github).await.unwrap();
+
+    let config = test.config();
+    let stack = Stack::new(&test.repo, &config).unwrap();
+    let forge = GitHub::new(github.octocrab(), github.gh_repo(), config.api);
+    detach::detach(&stack, None, true, &test.repo, &forge, &config)
+        .await
+        .unwrap();
+
+    let prs = github.prs();
+    let lexer = pr(&prs, "Add lexer");
+    assert_eq!(lexer["body"], "The body of Add lexer.");
+    assert_eq!(lexer["base"]["ref"], "main");
+    assert!(pr(&prs, "Add parser")["body"]
+        .as_str()
+        .unwrap()
+        .contains("[#]:fel"));
+
+    let stack = Stack::new(&test.repo, &config).unwrap();
+    assert_eq!(stack.get(1).unwrap().metadata.pr, None);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn detached_pr_stays_open_when_dropped() {
+    let test = TestRepo::with_stack(&["Add parser", "Add lexer"]);
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    config.submit.footer_placement = FooterPlacement::Comment;
+    config.submit.close_dropped = CloseDropped::Always;
+    submit_config(&test, &github, config.clone()).await.unwrap();
+
+    // A second revision, whose first still has a note naming the PR
+    test.amend("Add lexer, faster");
+    submit_config(&test, &github, config.clone()).await.unwrap();
+
+    let stack = Stack::new(&test.repo, &config).unwrap();
+    let forge = GitHub::new(github.octocrab(), github.gh_repo(), config.api);
+    detach::detach(&stack, None, false, &test.repo, &forge, &config)
+        .await
+        .unwrap();
+    let comments = github.comments(2);
+    assert!(!comments.is_empty());
+    assert!(!comments
+        .iter()
+        .any(|comment| comment.starts_with("<!-- fel:stack -->")));
+
+    // Dropping the commit and submitting closes nothing, so GitHub is never asked which PRs
+    // are still open
+    test.drop_top();
+    let mut stack = Stack::new(&test.repo, &config).unwrap();
+    let remote = test.repo.find_remote("origin").unwrap();
+    submit::run(
+        &mut stack,
+        &test.repo,
+        remote,
+        github.octocrab(),
+        &github.gh_repo(),
+        &config,
+        true,
+    )
+    .await
+    .unwrap();
+    let lexer = github
+        .prs()
+        .into_iter()
+        .find(|pr| pr["number"] == 2)
+        .unwrap();
+    assert_eq!(lexer["state"], "open");
+    assert!(!github
+        .requests()
+        .iter()
+        .any(|request| request.path == "/graphql"
+            && request.body["query"]
+                .as_str()
+                .unwrap()
+                .contains("pullRequest(")));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn turning_off_force_pushes_next_to_branch() {
+    let test = TestRepo::with_stack(&["Add parser"]);
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    submit_config(&test, &github, config.clone()).await.unwrap();
+    let branch = github.prs()[0]["head"]["ref"].as_str().unwrap().to_string();
+
+    // `<branch>/v2` couldn't be pushed while `<branch>` exists
+    test.amend("Add parser, faster");
+    config.submit.allow_force = false;
+    submit_config(&test, &github, config).await.unwrap();
+    let revised = format!("{branch}-v2");
+    let remote = test.remote();
+    assert!(remote
+        .find_reference(&format!("refs/heads/{revised}"))
+        .is_ok());
+    assert!(github.prs().iter().any(|pr| pr["head"]["ref"] == revised));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backport_links_both_stacks() {
+    let test = TestRepo::with_stack(&["Fix parser"]);
+    test.commit_with("Test fix", "Fel-Base: main");
+    let github = MockGitHub::start();
+    submit(&test, &github).await.unwrap();
+
+    // The release branch was cut before the stack
+    let release = test.repo.refname_to_id("refs/remotes/origin/main").unwrap();
+    test.repo
+        .reference("refs/remotes/origin/release-1.0", release, true, "test")
+        .unwrap();
+
+    let config = test.config();
+    let backport = || async {
+        let mut stack = Stack::new(&test.repo, &config).unwrap();
+        backport::backport(
+            &test.repo,
+            &mut stack,
+            github.octocrab(),
+            &github.gh_repo(),
+            &config,
+            "release-1.0",
+        )
+        .await
+        .unwrap()
+    };
+    assert_eq!(backport().await, "stack-release-1.0");
+
+    let prs = github.prs();
+    assert_eq!(prs.len(), 4);
+    let branch = |pr: &Value| pr["head"]["ref"].as_str().unwrap().to_string();
+    let (originals, copies): (Vec<_>, Vec<_>) = prs
+        .iter()
+        .partition(|pr| branch(pr).starts_with("fel/stack/"));
+    let copy = |title: &str| *copies.iter().find(|pr| pr["title"] == title).unwrap();
+    let original = |title: &str| *originals.iter().find(|pr| pr["title"] == title).unwrap();
+    assert_eq!(copy("Fix parser")["base"]["ref"], "release-1.0");
+    // The copies don't keep the originals' Fel-Base
+    assert_eq!(original("Test fix")["base"]["ref"], "main");
+    assert_eq!(copy("Test fix")["base"]["ref"], branch(copy("Fix parser")));
+
+    for title in ["Fix parser", "Test fix"] {
+        let (original, copy) = (original(title), copy(title));
+        let picked = format!(" (picked from #{})", original["number"]);
+        let backported = format!(" (backported in #{})", copy["number"]);
+        assert!(copy["body"].as_str().unwrap().contains(&picked));
+        assert!(original["body"].as_str().unwrap().contains(&backported));
+    }
+
+    // Backporting again updates the same PRs
+    backport().await;
+    assert_eq!(github.prs().len(), 4);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn backport_keeps_lossy_messages() {
+    let test = TestRepo::with_stack(&["Fix parser"]);
+    test.amend_latin1(b"Fix caf\xe9\n");
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    config.lossy_utf8 = true;
+    submit_config(&test, &github, config.clone()).await.unwrap();
+    let release = test.repo.refname_to_id("refs/remotes/origin/main").unwrap();
+    test.repo
+        .reference("refs/remotes/origin/release-1.0", release, true, "test")
+        .unwrap();
+
+    let mut stack = Stack::new(&test.repo, &config).unwrap();
+    backport::backport(
+        &test.repo,
+        &mut stack,
+        github.octocrab(),
+        &github.gh_repo(),
+        &config,
+        "release-1.0",
+    )
+    .await
+    .unwrap();
+    let titles: Vec<_> = github.prs().iter().map(|pr| pr["title"].clone()).collect();
+    assert_eq!(titles, ["Fix caf\u{fffd}", "Fix caf\u{fffd}"]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn branch_prefix_me_uses_login() {
+    let test = TestRepo::with_stack(&["Add parser"]);
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    config.submit.branch_prefix = Some("@me".to_string());
+    submit_config(&test, &github, config).await.unwrap();
+
+    let branch = github.prs()[0]["head"]["ref"].as_str().unwrap().to_string();
+    assert!(branch.starts_with("fel/fel/stack/"), "{branch}");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn refuses_protected_branches() {
+    let test = TestRepo::with_stack(&["Add parser"]);
+    let github = MockGitHub::start();
+    let id = test.commits()[0];
+    let metadata = Metadata {
+        branch: Some("release/1.0".to_string()),
+        ..Default::default()
+    };
+    test.set_metadata(id, &metadata);
+
+    let error = submit(&test, &github).await.unwrap_err();
+    assert!(error
+        .to_string()
+        .contains("refusing to push to release/1.0"));
+    assert!(github.prs().is_empty());
+    assert!(test
+        .remote()
+        .find_reference("refs/heads/release/1.0")
+        .is_err());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn policy_overrides_trailers() {
+    let test = TestRepo::new();
+    test.commit_with("Add parser", "Fel-Draft: false");
+    let github = MockGitHub::start();
+    let mut config = test.config();
+    let policy: Policy = toml::from_str("draft = true\nallowed_upstreams = [\"main\"]").unwrap();
+    policy.apply(&mut config).unwrap();
+
+    submit_config(&test, &github, config.clone()).await.unwrap();
+    assert_eq!(pr(&github.prs(), "Add parser")["draft"], true);
+
+    test.commit_with("Add lexer", "Fel-Base: release-1.0");
+    let error = submit_config(&test, &github, config).await.unwrap_err();
+    assert!(
+        format!("{error:?}").contains("doesn't allow submitting against release-1.0"),
+        "{error:?}"
+    );
+    assert_eq!(github.prs().len(), 1);
+}